@@ -0,0 +1,48 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Fuzzing target: run arbitrary bytes as a ROM, with all the trackers
+// enabled. The first byte selects the platform and the key held
+//************************************************************************
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use rust_chip_8::chip8::{Chip8, Platform};
+use rust_chip_8::chip8::mock::{MockInput, MockScreen};
+
+// Number of instructions executed, unless the program stops with an error
+const STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let (config, rom) = match data.split_first() {
+        Some((config, rom)) => (*config, rom),
+        None => return
+    };
+
+    let mut chip8 = Chip8::new(MockScreen::new(), MockInput::pressed(&[config >> 4]));
+    chip8.enable_coverage();
+    chip8.enable_profiler();
+    chip8.enable_code_write_tracking(false);
+
+    if chip8.load_rom(rom).is_err() {
+        return;
+    }
+    chip8.set_platform([Platform::Chip8, Platform::SuperChip, Platform::XoChip][config as usize % 3]);
+    if chip8.init().is_err() {
+        return;
+    }
+
+    // The errors are the expected outcome of a malformed ROM, only a panic is a failure
+    for _ in 0..STEPS {
+        if chip8.step().is_err() {
+            break;
+        }
+    }
+
+    chip8.coverage_report();
+    chip8.code_write_report();
+    chip8.profile();
+});
@@ -0,0 +1,26 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Fuzzing target: load arbitrary bytes as a ROM, unpacking the zip
+// archives, and report on them
+//************************************************************************
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use rust_chip_8::chip8::Chip8;
+use rust_chip_8::chip8::mock::{MockInput, MockScreen};
+
+fuzz_target!(|rom: &[u8]| {
+    let mut chip8 = Chip8::new(MockScreen::new(), MockInput::new());
+    chip8.enable_coverage();
+    chip8.enable_profiler();
+
+    // Too big, empty or invalid ROMs and archives are errors, the others are disassembled by the reports
+    if chip8.load_rom_reader(rom).is_ok() && chip8.init().is_ok() {
+        chip8.coverage_report();
+        chip8.profile();
+    }
+});
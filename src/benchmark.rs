@@ -0,0 +1,93 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Benchmark of the instruction throughput with the SDL renderers
+//************************************************************************
+
+use std::time::{Duration, Instant};
+
+use sdl2::EventPump;
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use crate::chip8::{self, Chip8};
+use crate::display_input::{Input, Screen};
+use crate::image::Image;
+use crate::osd::Overlay;
+use crate::palette::Palette;
+use crate::renderer::Renderer;
+use crate::to_color;
+
+// Run the ROM uncapped for a number of frames with both renderers, and print the throughputs
+pub fn run_benchmark(rom_file: &str, frames: u32, canvas: &mut WindowCanvas, event_pump: &mut EventPump,
+                     texture_creator: &TextureCreator<WindowContext>) -> Result<(), String> {
+    let rom = chip8::read_rom_file(rom_file)?;
+    let palette = Palette::default();
+
+    // Draw every pixel with fill_rect after each instruction
+    let (instructions, duration) = {
+        let mut chip8 = new_chip8(&rom, event_pump)?;
+        let instructions = frames * chip8.instructions_per_frame();
+        let start = Instant::now();
+
+        for _ in 0..instructions {
+            chip8.step()?;
+
+            canvas.set_draw_color(to_color(palette.background()));
+            canvas.clear();
+            for (index, &pixel) in chip8.screen.data.iter().enumerate() {
+                if pixel != 0 {
+                    canvas.set_draw_color(to_color(palette.color(pixel)));
+                    canvas.fill_rect(Rect::new(index as i32 % 64 * 10, index as i32 / 64 * 10, 10, 10))?;
+                }
+            }
+            canvas.present();
+        }
+
+        (instructions, start.elapsed())
+    };
+    let fill_rect_ips = print_result("fill_rect per instruction", instructions, duration);
+
+    // Upload the framebuffer to a texture once per frame
+    let (instructions, duration) = {
+        let mut chip8 = new_chip8(&rom, event_pump)?;
+        let mut renderer = Renderer::new(texture_creator);
+        let instructions = frames * chip8.instructions_per_frame();
+        let start = Instant::now();
+
+        for _ in 0..frames {
+            chip8.run_frame()?;
+
+            if chip8.screen.require_update {
+                renderer.update(&Image::from_framebuffer(&chip8.screen.data, &palette))?;
+                chip8.screen.require_update = false;
+            }
+            renderer.present(canvas, &Overlay::default())?;
+        }
+
+        (instructions, start.elapsed())
+    };
+    let texture_ips = print_result("texture per frame", instructions, duration);
+
+    println!("Speedup: {:.1}x", texture_ips / fill_rect_ips);
+
+    Ok(())
+}
+
+fn new_chip8<'a>(rom: &[u8], event_pump: &'a mut EventPump) -> Result<Chip8<Screen, Input<'a>>, String> {
+    let mut chip8 = Chip8::new(Screen::new(), Input::new(event_pump));
+    chip8.load_rom(rom)?;
+    chip8.init()?;
+
+    Ok(chip8)
+}
+
+// Print the throughput of a renderer, in instructions per second
+fn print_result(name: &str, instructions: u32, duration: Duration) -> f64 {
+    let ips = instructions as f64 / duration.as_secs_f64();
+    println!("{:<26}: {} instructions in {:.2}s, {:.0} instructions/s", name, instructions, duration.as_secs_f64(), ips);
+
+    ips
+}
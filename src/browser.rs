@@ -0,0 +1,381 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// ROM library browser: the ROMs of a directory with their titles and the
+// thumbnail of their last screenshot, started with the keyboard. The ROMs
+// are listed by file name, the titles of the visible ones are looked up
+// in the ROM database when they are shown
+//************************************************************************
+
+use std::path::{Path, PathBuf};
+
+use sdl2::EventPump;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use crate::chip8::{self, Database};
+use crate::font::FONT_WIDTH;
+use crate::image::Image;
+use crate::renderer::{draw_text, TEXT_LINE_HEIGHT, TEXT_MARGIN, TEXT_SCALE};
+use crate::screenshot::{latest_capture, load_png};
+
+// Extensions of the files listed: binaries, zip archives, Octo cartridges and Intel HEX files
+const ROM_EXTENSIONS: [&str; 7] = ["ch8", "c8", "sc8", "xo8", "zip", "gif", "hex"];
+
+// List of the titles below the header, with the thumbnail on its right and the help or the error on the last row
+const LIST_ROWS     : usize = 16;
+const LIST_COLUMNS  : usize = 38;
+const FOOTER_ROW    : usize = 18;
+const FOOTER_COLUMNS: usize = 78;
+const HELP          : &str = "UP/DOWN: SELECT  ENTER: PLAY  ESC: QUIT";
+
+// Thumbnail, in pixels from the top left corner of the window
+const THUMBNAIL_X     : i32 = TEXT_MARGIN + 40 * (FONT_WIDTH as i32 + 1) * TEXT_SCALE as i32;
+const THUMBNAIL_Y     : i32 = TEXT_MARGIN + TEXT_LINE_HEIGHT;
+const THUMBNAIL_WIDTH : u32 = 288;
+const THUMBNAIL_HEIGHT: u32 = 144;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Highlight {
+    Normal,
+    Label,
+    Selected,
+    Error
+}
+
+impl Highlight {
+    // Text and background colors
+    fn colors(&self) -> (Color, Color) {
+        match self {
+            Highlight::Normal   => (Color::WHITE, Color::BLACK),
+            Highlight::Label    => (Color::RGB(0x80, 0x80, 0x80), Color::BLACK),
+            Highlight::Selected => (Color::BLACK, Color::RGB(0x33, 0xFF, 0x66)),
+            Highlight::Error    => (Color::RGB(0xFF, 0x55, 0x55), Color::BLACK)
+        }
+    }
+}
+
+// A text at a position of the character grid
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Span {
+    column   : usize,
+    row      : usize,
+    text     : String,
+    highlight: Highlight
+}
+
+impl Span {
+    fn new(column: usize, row: usize, text: String, highlight: Highlight) -> Self {
+        Span { column, row, text, highlight }
+    }
+}
+
+// A ROM of the directory, with its file name until its title is looked up in the ROM database
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomEntry {
+    pub path     : PathBuf,
+    pub title    : String,
+    pub looked_up: bool
+}
+
+// What the player chose in the browser
+pub enum BrowserAction {
+    Play(PathBuf),
+    Quit
+}
+
+pub struct Browser {
+    directory     : PathBuf,
+    screenshot_dir: PathBuf,
+    roms          : Vec<RomEntry>,
+    selected      : usize,
+    first_row     : usize,
+
+    // Last screenshot of the selected ROM
+    thumbnail: Option<Image>,
+
+    // Error of the last ROM played
+    message: Option<String>
+}
+
+impl Browser {
+    pub fn open(directory: &Path, screenshot_dir: &Path) -> Result<Self, String> {
+        let mut browser = Browser {
+            directory     : directory.to_path_buf(),
+            screenshot_dir: screenshot_dir.to_path_buf(),
+            roms          : scan_roms(directory)?,
+            selected      : 0,
+            first_row     : 0,
+            thumbnail     : None,
+            message       : None
+        };
+        browser.look_up_titles();
+        browser.load_thumbnail();
+
+        Ok(browser)
+    }
+
+    // Show an error below the list, until the selection changes
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    // Read the last screenshot of the selected ROM again, after playing it
+    pub fn load_thumbnail(&mut self) {
+        self.thumbnail = self.roms.get(self.selected)
+            .and_then(|rom| latest_capture(&self.screenshot_dir, &rom.path.to_string_lossy(), "png"))
+            .and_then(|path| load_png(&path).ok());
+    }
+
+    // Move the selection, scrolling the list to keep it visible
+    fn select(&mut self, index: isize) {
+        let index = index.clamp(0, self.roms.len().saturating_sub(1) as isize) as usize;
+        if index == self.selected {
+            return;
+        }
+
+        self.selected  = index;
+        self.first_row = self.first_row.clamp(index.saturating_sub(LIST_ROWS - 1), index);
+        self.message   = None;
+        self.look_up_titles();
+        self.load_thumbnail();
+    }
+
+    // Titles of the visible ROMs, each file being decoded once
+    fn look_up_titles(&mut self) {
+        for rom in self.roms.iter_mut().skip(self.first_row).take(LIST_ROWS).filter(|rom| !rom.looked_up) {
+            if let Some(title) = database_title(&rom.path) {
+                rom.title = title;
+            }
+            rom.looked_up = true;
+        }
+    }
+
+    // Draw the browser and wait for the keys, until a ROM is started or the browser is closed
+    pub fn choose(&mut self, canvas: &mut WindowCanvas, texture_creator: &TextureCreator<WindowContext>,
+                  event_pump: &mut EventPump) -> Result<BrowserAction, String> {
+        loop {
+            self.draw(canvas, texture_creator)?;
+
+            let selected = self.selected as isize;
+            match event_pump.wait_event() {
+                Event::Quit { .. } | Event::Window { win_event: WindowEvent::Close, .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(BrowserAction::Quit),
+                Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } => {
+                    if let Some(rom) = self.roms.get(self.selected) {
+                        return Ok(BrowserAction::Play(rom.path.clone()));
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Up), .. }       => self.select(selected - 1),
+                Event::KeyDown { keycode: Some(Keycode::Down), .. }     => self.select(selected + 1),
+                Event::KeyDown { keycode: Some(Keycode::PageUp), .. }   => self.select(selected - LIST_ROWS as isize),
+                Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => self.select(selected + LIST_ROWS as isize),
+                Event::KeyDown { keycode: Some(Keycode::Home), .. }     => self.select(0),
+                Event::KeyDown { keycode: Some(Keycode::End), .. }      => self.select(self.roms.len() as isize),
+                Event::MouseWheel { y, .. }                             => self.select(selected - y as isize),
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&self, canvas: &mut WindowCanvas, texture_creator: &TextureCreator<WindowContext>) -> Result<(), String> {
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+
+        let column_width = ((FONT_WIDTH + 1) as u32 * TEXT_SCALE) as i32;
+        for span in self.layout() {
+            let (color, background) = span.highlight.colors();
+            let (x, y) = (TEXT_MARGIN + span.column as i32 * column_width, TEXT_MARGIN + span.row as i32 * TEXT_LINE_HEIGHT);
+            draw_text(canvas, &span.text, x, y, color, background)?;
+        }
+
+        // The thumbnail is scaled to its box, which stays empty without screenshot
+        let frame = Rect::new(THUMBNAIL_X, THUMBNAIL_Y, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+        match &self.thumbnail {
+            Some(image) => {
+                let mut texture = texture_creator
+                    .create_texture_static(PixelFormatEnum::RGB24, image.width as u32, image.height as u32)
+                    .map_err(|e| e.to_string())?;
+                texture.update(None, &image.to_rgb24(), image.width * 3).map_err(|e| e.to_string())?;
+                canvas.copy(&texture, None, frame)?;
+            }
+            None => {
+                canvas.set_draw_color(Highlight::Label.colors().0);
+                canvas.draw_rect(frame)?;
+            }
+        }
+
+        canvas.present();
+
+        Ok(())
+    }
+
+    // Texts of the browser: the directory, the visible titles, the file of the selected ROM and the footer
+    fn layout(&self) -> Vec<Span> {
+        let header = format!("{} - {} ROMS", self.directory.display(), self.roms.len());
+        let mut spans = vec![Span::new(0, 0, truncate(&header, LIST_COLUMNS), Highlight::Label)];
+
+        for (row, rom) in self.roms.iter().enumerate().skip(self.first_row).take(LIST_ROWS) {
+            let highlight = if row == self.selected { Highlight::Selected } else { Highlight::Normal };
+            spans.push(Span::new(0, 1 + row - self.first_row, truncate(&rom.title, LIST_COLUMNS), highlight));
+        }
+        if self.roms.is_empty() {
+            spans.push(Span::new(0, 1, "NO ROM FOUND".to_string(), Highlight::Error));
+        }
+
+        let thumbnail_column = ((THUMBNAIL_X - TEXT_MARGIN) / ((FONT_WIDTH as i32 + 1) * TEXT_SCALE as i32)) as usize;
+        let thumbnail_rows = (THUMBNAIL_HEIGHT as i32 / TEXT_LINE_HEIGHT) as usize;
+        if let Some(name) = self.roms.get(self.selected).and_then(|rom| rom.path.file_name()) {
+            spans.push(Span::new(thumbnail_column, 2 + thumbnail_rows, truncate(&name.to_string_lossy(), LIST_COLUMNS), Highlight::Label));
+        }
+        if self.thumbnail.is_none() && !self.roms.is_empty() {
+            spans.push(Span::new(thumbnail_column + 1, 1 + thumbnail_rows / 2, "NO SCREENSHOT".to_string(), Highlight::Label));
+        }
+
+        spans.push(match &self.message {
+            Some(message) => Span::new(0, FOOTER_ROW, truncate(message, FOOTER_COLUMNS), Highlight::Error),
+            None => Span::new(0, FOOTER_ROW, HELP.to_string(), Highlight::Label)
+        });
+
+        spans
+    }
+}
+
+// ROMs of a directory, sorted by file name
+pub fn scan_roms(directory: &Path) -> Result<Vec<RomEntry>, String> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|e| format!("Impossible to read the directory {}: {}", directory.display(), e))?;
+
+    let mut roms: Vec<RomEntry> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && has_rom_extension(path))
+        .map(|path| RomEntry { title: path.file_stem().unwrap_or_default().to_string_lossy().to_string(), path, looked_up: false })
+        .collect();
+    roms.sort_by_cached_key(|rom| (rom.title.to_lowercase(), rom.path.clone()));
+
+    Ok(roms)
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+// Title of a ROM known to the database
+fn database_title(path: &Path) -> Option<String> {
+    chip8::read_rom_file(&path.to_string_lossy()).ok()
+        .and_then(|rom| chip8::decode_rom(&rom).ok().and_then(|(program, _)| Database::embedded().find(&program)))
+        .map(|info| info.title)
+}
+
+// Cut a text to a number of characters, ending it with ".." when it is cut
+fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+
+    text.chars().take(length - 2).chain("..".chars()).collect()
+}
+
+// Unit tests
+#[test]
+fn test_scan_roms() {
+    //--------------------------------------------------------------------
+    // Setup: A directory with ROMs, a text file and a sub directory
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("scan-roms");
+    std::fs::create_dir_all(directory.join("saves.ch8")).unwrap();
+    for name in ["zebra.ch8", "Alien.SC8", "notes.txt", "bounce.xo8"] {
+        std::fs::write(directory.join(name), [0x12, 0x00]).unwrap();
+    }
+
+    //--------------------------------------------------------------------
+    // Execute: Scan the directory
+    //--------------------------------------------------------------------
+    let roms = scan_roms(&directory).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: Only the ROM files are listed, sorted by their names without extension
+    //--------------------------------------------------------------------
+    let titles: Vec<&str> = roms.iter().map(|rom| rom.title.as_str()).collect();
+    assert_eq!(titles, vec!["Alien", "bounce", "zebra"], "The ROMs should be sorted regardless of the case");
+    assert_eq!(roms[0].path, directory.join("Alien.SC8"), "The path of the ROM should be kept");
+    assert!(roms.iter().all(|rom| !rom.looked_up), "The titles should not be looked up while scanning");
+    assert!(scan_roms(&directory).is_err(), "A missing directory should be an error");
+}
+
+#[test]
+fn test_browser_layout() {
+    //--------------------------------------------------------------------
+    // Setup: More ROMs than the visible rows, one with a long title
+    //--------------------------------------------------------------------
+    let mut roms: Vec<RomEntry> = (0..20)
+        .map(|index| RomEntry { path: PathBuf::from(format!("roms/rom{:02}.ch8", index)), title: format!("ROM {:02}", index),
+                                looked_up: true })
+        .collect();
+    roms[19].title = "A TITLE LONGER THAN THE COLUMNS OF THE LIST".to_string();
+
+    let mut browser = Browser {
+        directory     : PathBuf::from("roms"),
+        screenshot_dir: crate::test_directory("browser-layout"),
+        roms,
+        selected      : 0,
+        first_row     : 0,
+        thumbnail     : None,
+        message       : Some("Error load".to_string())
+    };
+
+    //--------------------------------------------------------------------
+    // Execute: Select the last ROM, then go up by 2 rows
+    //--------------------------------------------------------------------
+    browser.select(100);
+    let last = browser.layout();
+    browser.select(17);
+    std::fs::remove_dir_all(&browser.screenshot_dir).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The list scrolls to keep the selection visible, only when it goes out
+    //--------------------------------------------------------------------
+    assert_eq!((browser.selected, browser.first_row), (17, 4), "The list should not scroll while the selection is visible");
+    assert_eq!(last[0], Span::new(0, 0, "roms - 20 ROMS".to_string(), Highlight::Label), "The header should count the ROMs");
+    assert_eq!(last[1], Span::new(0, 1, "ROM 04".to_string(), Highlight::Normal), "The list should start at the 5th ROM");
+    assert_eq!(last[16], Span::new(0, 16, "A TITLE LONGER THAN THE COLUMNS OF T..".to_string(), Highlight::Selected),
+               "The last ROM should be selected and cut");
+    assert!(last.contains(&Span::new(40, 11, "rom19.ch8".to_string(), Highlight::Label)), "The file name should be below the thumbnail");
+    assert!(last.contains(&Span::new(41, 5, "NO SCREENSHOT".to_string(), Highlight::Label)), "The missing screenshot should be shown");
+    assert_eq!(last.last(), Some(&Span::new(0, FOOTER_ROW, HELP.to_string(), Highlight::Label)),
+               "The error should be cleared by the selection");
+}
+
+#[test]
+fn test_browser_titles() {
+    //--------------------------------------------------------------------
+    // Setup: More ROMs than the visible rows
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("browser-titles");
+    for index in 0..20 {
+        std::fs::write(directory.join(format!("rom{:02}.ch8", index)), [0x12, 0x00]).unwrap();
+    }
+
+    //--------------------------------------------------------------------
+    // Execute: Open the browser, then select the last ROM
+    //--------------------------------------------------------------------
+    let mut browser = Browser::open(&directory, &directory).unwrap();
+    let opened: Vec<bool> = browser.roms.iter().map(|rom| rom.looked_up).collect();
+    browser.select(100);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: Only the visible ROMs have been looked up
+    //--------------------------------------------------------------------
+    assert_eq!(opened.iter().filter(|&&looked_up| looked_up).count(), LIST_ROWS, "Only the first rows should be looked up");
+    assert!(browser.roms.iter().all(|rom| rom.looked_up), "The rows scrolled into view should be looked up");
+    assert_eq!(browser.roms[0].title, "rom00", "An unknown ROM should keep its file name");
+}
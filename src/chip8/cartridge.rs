@@ -0,0 +1,252 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Octo cartridges: GIF images carrying a program and its settings
+//
+// The 2 low bits of the color index of each pixel, frame after frame,
+// are a payload with 4 pixels per byte (high bits first). The payload is
+// a 32 bits big endian size followed by a JSON object with the Octo
+// source of the program and the options of the emulator, assembled by
+// the Octo assembler.
+//************************************************************************
+
+use std::io::Cursor;
+
+use serde::Deserialize;
+
+use crate::chip8::database::{parse_color, Database, RomInfo, sha1_hex};
+use crate::chip8::octo;
+use crate::chip8::quirks::Platform;
+
+// Signatures of the GIF images
+const CARTRIDGE_SIGNATURES: [&[u8]; 2] = [b"GIF87a", b"GIF89a"];
+
+// Octo maxSize option of the SUPER-CHIP and XO-CHIP programs
+const CARTRIDGE_SUPERCHIP_SIZE: u32 = 3216;
+const CARTRIDGE_XOCHIP_SIZE   : u32 = 65024;
+
+pub struct Cartridge {
+    pub program: Vec<u8>,
+    pub info   : RomInfo
+}
+
+impl Cartridge {
+    // Check the signature of a file, before decoding it
+    pub fn is_cartridge(data: &[u8]) -> bool {
+        CARTRIDGE_SIGNATURES.iter().any(|signature| data.starts_with(signature))
+    }
+
+    // Decode the program and the settings of a cartridge
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let payload = read_payload(data)?;
+        let payload: Payload = serde_json::from_slice(&payload)
+            .map_err(|e| format!("Invalid Octo cartridge settings ({})", e))?;
+        let program = octo::assemble(&payload.program).map_err(|e| format!("Invalid Octo cartridge program ({})", e))?;
+        let options = payload.options;
+
+        let platform = match options.max_size {
+            Some(CARTRIDGE_XOCHIP_SIZE)    => Platform::XoChip,
+            Some(CARTRIDGE_SUPERCHIP_SIZE) => Platform::SuperChip,
+            _                              => Platform::Chip8
+        };
+
+        // The database knows the title and the key layout of the published programs
+        let mut info = Database::embedded().find(&program).unwrap_or(RomInfo {
+            hash       : sha1_hex(&program),
+            title      : "Octo cartridge".to_string(),
+            description: None,
+            authors    : Vec::new(),
+            release    : None,
+            platform,
+            quirks     : platform.default_quirks(),
+            tickrate   : None,
+            colors     : Vec::new(),
+            keys       : Vec::new()
+        });
+
+        // The settings of the cartridge take precedence
+        info.platform = platform;
+        info.quirks   = platform.default_quirks();
+        let quirks = [(options.shift_quirks, &mut info.quirks.shift), (options.jump_quirks, &mut info.quirks.jump),
+                      (options.clip_quirks, &mut info.quirks.clip), (options.logic_quirks, &mut info.quirks.vf_reset),
+                      (options.load_store_quirks.map(|quirk| !quirk), &mut info.quirks.memory_increment)];
+        for (option, quirk) in quirks {
+            if let Some(option) = option {
+                *quirk = option;
+            }
+        }
+        info.tickrate = options.tickrate.or(info.tickrate);
+
+        let colors: Vec<[u8; 3]> = [options.background_color, options.fill_color, options.fill_color_2, options.blend_color]
+            .iter()
+            .map_while(|color| color.as_deref().and_then(parse_color))
+            .collect();
+        if colors.len() >= 2 {
+            info.colors = colors;
+        }
+
+        Ok(Cartridge { program, info })
+    }
+}
+
+// Bytes hidden in the color indexes of the frames, after their size
+fn read_payload(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(Cursor::new(data)).map_err(|e| format!("Invalid Octo cartridge ({})", e))?;
+
+    let mut bits = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| format!("Invalid Octo cartridge ({})", e))? {
+        bits.extend(frame.buffer.iter().map(|index| index & 0x03));
+    }
+
+    let bytes: Vec<u8> = bits.chunks_exact(4)
+        .map(|pixels| pixels.iter().fold(0, |byte, bits| byte << 2 | bits))
+        .collect();
+    if bytes.len() < 4 {
+        return Err("Invalid Octo cartridge (no payload)".to_string());
+    }
+
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    bytes.get(4..4 + size).map(|payload| payload.to_vec())
+        .ok_or(format!("Invalid Octo cartridge (payload of {} bytes in {} bytes)", size, bytes.len() - 4))
+}
+
+// ------- JSON structures -------
+#[derive(Deserialize)]
+struct Payload {
+    program: String,
+    #[serde(default)]
+    options: Options
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Options {
+    tickrate         : Option<u32>,
+    max_size         : Option<u32>,
+    shift_quirks     : Option<bool>,
+    load_store_quirks: Option<bool>,
+    jump_quirks      : Option<bool>,
+    clip_quirks      : Option<bool>,
+    logic_quirks     : Option<bool>,
+    background_color : Option<String>,
+    fill_color       : Option<String>,
+    #[serde(rename = "fillColor2")]
+    fill_color_2     : Option<String>,
+    blend_color      : Option<String>
+}
+
+// Unit tests
+#[cfg(test)]
+fn build_cartridge(payload: &str) -> Vec<u8> {
+    let mut bytes = (payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(payload.as_bytes());
+
+    // 4 pixels per byte, in frames of 32x32 pixels
+    let mut pixels: Vec<u8> = bytes.iter().flat_map(|byte| [byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3]).collect();
+    pixels.resize(pixels.len().div_ceil(1024) * 1024, 0);
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut gif, 32, 32, &[0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 255, 255]).unwrap();
+        for frame in pixels.chunks(1024) {
+            encoder.write_frame(&gif::Frame::from_indexed_pixels(32, 32, frame.to_vec(), None)).unwrap();
+        }
+    }
+
+    gif
+}
+
+#[test]
+fn test_decode_cartridge() {
+    //--------------------------------------------------------------------
+    // Setup: A cartridge with a program made of bytes, and its settings
+    //--------------------------------------------------------------------
+    let gif = build_cartridge(r##"{"program": ": main\n0x60 0x05 # LD V0, 5\n18 0 0b11 -1",
+        "options": {"tickrate": 30, "maxSize": 3216, "jumpQuirks": false, "loadStoreQuirks": true,
+                    "backgroundColor": "#000000", "fillColor": "#FF6600", "fillColor2": "#FFFFFF", "blendColor": "bad"}}"##);
+
+    //--------------------------------------------------------------------
+    // Execute: Decode it
+    //--------------------------------------------------------------------
+    let cartridge = Cartridge::decode(&gif).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The program is assembled, and the settings override the platform's
+    //--------------------------------------------------------------------
+    assert!(Cartridge::is_cartridge(&gif), "The GIF should be a cartridge");
+    assert_eq!(cartridge.program, vec![0x60, 0x05, 0x12, 0x00, 0x03, 0xFF], "The bytes should be assembled");
+
+    let info = cartridge.info;
+    assert_eq!((info.platform, info.tickrate), (Platform::SuperChip, Some(30)), "The platform should follow maxSize");
+    assert_eq!((info.quirks.shift, info.quirks.jump, info.quirks.memory_increment), (true, false, false),
+               "The quirks should be the SUPER-CHIP ones, with the overrides");
+    assert_eq!(info.colors, vec![[0x00, 0x00, 0x00], [0xFF, 0x66, 0x00], [0xFF, 0xFF, 0xFF]],
+               "The colors should stop at the invalid one");
+
+    let mut chip8 = crate::chip8::Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+    chip8.load_rom(&gif).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x206], chip8.platform(), chip8.clock_speed()),
+               (&[0x60, 0x05, 0x12, 0x00, 0x03, 0xFF][..], Platform::SuperChip, 1800), "The cartridge should be loaded");
+
+    let source = build_cartridge(r#"{"program": ": main\n  v0 := 256"}"#);
+    assert_eq!(Cartridge::decode(&source).err(),
+               Some("Invalid Octo cartridge program (line 2: The value 256 does not fit between -128 and 255)".to_string()),
+               "The invalid Octo sources should be rejected");
+}
+
+#[test]
+fn test_decode_octo_program() {
+    //--------------------------------------------------------------------
+    // Setup: A cartridge of an Octo program, summing 1 to 10 with a loop
+    // and a macro, then drawing the tens digit of the sum
+    //--------------------------------------------------------------------
+    let source = "
+        :alias sum v1
+        :alias counter v2
+        :const LIMIT 10
+        :macro add-to REGISTER VALUE { REGISTER += VALUE }
+
+        : digits
+          0 0 0
+
+        : main
+          clear
+          sum := 0
+          counter := 0
+          loop
+            counter += 1
+            add-to sum counter
+            while counter != LIMIT
+          again
+          i := digits
+          bcd sum
+          load v2
+          v3 := 8
+          v4 := 8
+          i := hex v1
+          sprite v3 v4 5
+          loop again";
+    let gif = build_cartridge(&serde_json::json!({"program": source}).to_string());
+
+    //--------------------------------------------------------------------
+    // Execute: Decode it and run it
+    //--------------------------------------------------------------------
+    let cartridge = Cartridge::decode(&gif).unwrap();
+    let mut chip8 = crate::chip8::Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+    chip8.load_rom(&gif).unwrap();
+    chip8.init().unwrap();
+    for _ in 0..200 {
+        chip8.step().unwrap();
+    }
+
+    //--------------------------------------------------------------------
+    // Verify: The program is assembled, and computes and draws the sum
+    //--------------------------------------------------------------------
+    assert_eq!((&cartridge.program[..5], cartridge.info.title.as_str()), (&[0x12, 0x05, 0x00, 0x00, 0x00][..], "Octo cartridge"),
+               "The program should jump over the data to main");
+    assert_eq!(&chip8.registers[..3], &[0, 5, 5], "The digits of the sum should be loaded");
+    assert!(chip8.gfx.iter().any(|&pixel| pixel != 0), "The digit should be drawn");
+}
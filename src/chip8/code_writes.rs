@@ -0,0 +1,136 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Self-modifying code detection: writes into bytes already executed
+//************************************************************************
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::chip8::constants::CHIP8_MEMORY_SIZE;
+use crate::chip8::types::Address;
+
+// Number of writes kept in the log, the others are only counted
+const CODE_WRITES_MAX_LOG: usize = 1000;
+
+// A write into a byte which has already been executed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub program_counter: Address,
+    pub address        : Address,
+    pub old_value      : u8,
+    pub new_value      : u8
+}
+
+pub struct CodeWriteTracker {
+    executed: Vec<bool>,
+
+    // First writes, and writers of each modified byte
+    log     : Vec<CodeWrite>,
+    total   : u64,
+    modified: BTreeMap<Address, BTreeSet<Address>>
+}
+
+impl CodeWriteTracker {
+    pub fn new() -> Self {
+        CodeWriteTracker {
+            executed: vec![false; CHIP8_MEMORY_SIZE],
+            log     : Vec::new(),
+            total   : 0,
+            modified: BTreeMap::new()
+        }
+    }
+
+    pub fn record_execution(&mut self, address: usize) {
+        self.executed[address % CHIP8_MEMORY_SIZE] = true;
+        self.executed[(address + 1) % CHIP8_MEMORY_SIZE] = true;
+    }
+
+    // Check a write, and get it when it modifies code
+    pub fn record_write(&mut self, write: CodeWrite) -> Option<CodeWrite> {
+        if !self.executed[write.address as usize % CHIP8_MEMORY_SIZE] {
+            return None;
+        }
+
+        self.total += 1;
+        if self.log.len() < CODE_WRITES_MAX_LOG {
+            self.log.push(write);
+        }
+        self.modified.entry(write.address).or_default().insert(write.program_counter);
+
+        Some(write)
+    }
+
+    // Forget the executed bytes, after the memory has been cleared
+    pub fn clear_executed(&mut self) {
+        self.executed.iter_mut().for_each(|executed| *executed = false);
+    }
+
+    // Contiguous modified bytes, with the instructions writing them
+    fn regions(&self) -> Vec<(Address, Address, BTreeSet<Address>)> {
+        let mut regions: Vec<(Address, Address, BTreeSet<Address>)> = Vec::new();
+        for (address, writers) in &self.modified {
+            match regions.last_mut() {
+                Some((_, end, region_writers)) if *end + 1 == *address => {
+                    *end = *address;
+                    region_writers.extend(writers);
+                }
+                _ => regions.push((*address, *address, writers.clone()))
+            }
+        }
+
+        regions
+    }
+
+    // Summary of the self-modifying regions, followed by the log of the writes
+    pub fn report(&self) -> String {
+        let regions = self.regions();
+        let mut report = String::new();
+
+        writeln!(report, "Writes into executed code: {}", self.total).unwrap();
+        writeln!(report, "Modified bytes           : {}", self.modified.len()).unwrap();
+
+        writeln!(report, "\nRegions\n  Start  End  Written by").unwrap();
+        for (start, end, writers) in &regions {
+            let writers: Vec<String> = writers.iter().map(|writer| format!("{:03X}", writer)).collect();
+            writeln!(report, "  {:03X}    {:03X}  {}", start, end, writers.join(", ")).unwrap();
+        }
+
+        writeln!(report, "\nLog (first {} writes)\n  PC   Address  Old  New", CODE_WRITES_MAX_LOG).unwrap();
+        for write in &self.log {
+            writeln!(report, "  {:03X}  {:03X}      {:02X}   {:02X}", write.program_counter, write.address,
+                     write.old_value, write.new_value).unwrap();
+        }
+
+        report
+    }
+}
+
+// Unit tests
+#[test]
+fn test_code_writes() {
+    //--------------------------------------------------------------------
+    // Setup: Execute 200-203, then store V0-V1 at 202 with FX55
+    //--------------------------------------------------------------------
+    let mut chip8 = crate::chip8::Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+    chip8.load_rom(&[0x60, 0x12, 0xA2, 0x02, 0xF1, 0x55, 0x12, 0x06]).unwrap();
+    chip8.init().unwrap();
+    chip8.enable_code_write_tracking(true);
+
+    //--------------------------------------------------------------------
+    // Execute: Run a frame
+    //--------------------------------------------------------------------
+    chip8.run_frame().unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The frame stops after the writing instruction
+    //--------------------------------------------------------------------
+    assert_eq!(chip8.take_code_write_hit(), Some(CodeWrite { program_counter: 0x204, address: 0x202, old_value: 0xA2, new_value: 0x12 }),
+               "The frame should stop after the write into the code");
+    assert_eq!(chip8.program_counter, 0x206, "The frame should stop after the writing instruction");
+
+    let report = chip8.code_write_report().unwrap();
+    assert!(report.starts_with("Writes into executed code: 2\nModified bytes           : 2\n\nRegions\n  Start  End  Written by\n  202    203  204\n"),
+            "The region should be summarized: {}", report);
+}
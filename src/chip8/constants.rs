@@ -5,15 +5,19 @@
 // Constants
 //************************************************************************
 
+use std::time::Duration;
+
 use crate::chip8::types::{Address, Register};
 
 pub const CHIP8_REGISTER_COUNT     : usize    = 16;      // Nb of registers
 pub const CHIP8_REGISTER_VF        : Register = 0xF;     // Index
-pub const CHIP8_CPU_CLOCK_SPEED    : u16      = 500;     // Hz
+pub const CHIP8_CPU_CLOCK_SPEED    : u32      = 500;     // Hz
 pub const CHIP8_TIMER_CLOCK_SPEED  : u16      = 60;      // Hz
 pub const CHIP8_PROGRAM_COUNTER_INC: u16      = 2;       // Bytes
 pub const CHIP8_MEMORY_START       : Address  = 0x200;   // Address
-pub const CHIP8_MEMORY_SIZE        : usize    = 4096;    // Bytes
+pub const CHIP8_MEMORY_SIZE        : usize    = 65536;   // Bytes, the largest memory (XO-CHIP)
 pub const CHIP8_STACK_COUNT        : usize    = 16;      // Nb of stacks
-pub const CHIP8_MAX_EXECUTABLE_SIZE: u16      = 3072;    // Bytes
-pub const CHIP8_PIXEL_COUNT        : usize    = 64 * 32; // Pixels
\ No newline at end of file
+pub const CHIP8_RPL_FLAG_COUNT     : usize    = 16;      // Nb of RPL user flags, the most (XO-CHIP)
+pub const CHIP8_PIXEL_COUNT        : usize    = 64 * 32; // Pixels
+pub const CHIP8_DRAW_PLANE         : u8       = 0x01;    // Bitplane mask
+pub const CHIP8_FRAME_DURATION     : Duration = Duration::from_nanos(1_000_000_000 / CHIP8_TIMER_CLOCK_SPEED as u64);
\ No newline at end of file
@@ -0,0 +1,126 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Code coverage: bytes executed, read as data and written, reported as
+// an annotated disassembly of the program
+//************************************************************************
+
+use std::fmt::Write;
+
+use crate::chip8::constants::{CHIP8_MEMORY_SIZE, CHIP8_MEMORY_START};
+use crate::chip8::disassembler::disassemble;
+use crate::chip8::types::OpCode;
+
+// Access flags of a byte
+const COVERAGE_EXECUTED   : u8 = 0x01;
+const COVERAGE_READ       : u8 = 0x02;
+const COVERAGE_WRITTEN    : u8 = 0x04;
+// First byte of an executed instruction
+const COVERAGE_INSTRUCTION: u8 = 0x08;
+
+pub struct Coverage {
+    flags: Vec<u8>
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            flags: vec![0; CHIP8_MEMORY_SIZE]
+        }
+    }
+
+    pub fn record_execution(&mut self, address: usize) {
+        self.flags[address % CHIP8_MEMORY_SIZE] |= COVERAGE_EXECUTED | COVERAGE_INSTRUCTION;
+        self.flags[(address + 1) % CHIP8_MEMORY_SIZE] |= COVERAGE_EXECUTED;
+    }
+
+    pub fn record_read(&mut self, address: usize) {
+        self.flags[address % CHIP8_MEMORY_SIZE] |= COVERAGE_READ;
+    }
+
+    pub fn record_write(&mut self, address: usize) {
+        self.flags[address % CHIP8_MEMORY_SIZE] |= COVERAGE_WRITTEN;
+    }
+
+    // Annotated disassembly of the program and of the memory written after it,
+    // with a summary of the bytes executed, read, written and never accessed
+    pub fn report(&self, memory: &[u8], rom_size: usize) -> String {
+        let start = CHIP8_MEMORY_START as usize;
+        let last_written = self.flags.iter().rposition(|flags| flags & COVERAGE_WRITTEN != 0).map_or(0, |last| last + 1);
+        let end = (start + rom_size).max(last_written).min(CHIP8_MEMORY_SIZE);
+        let flags = &self.flags[start..end];
+
+        let count = |mask: u8| flags.iter().filter(|flags| *flags & mask != 0).count();
+        let percent = |count: usize| count as f64 * 100.0 / flags.len().max(1) as f64;
+        let never_accessed = flags.iter().filter(|flags| **flags == 0).count();
+
+        let mut report = String::new();
+        writeln!(report, "Coverage of {:03X}-{:03X} ({} bytes)", start, end.max(start + 1) - 1, flags.len()).unwrap();
+        for (name, count) in [("Executed", count(COVERAGE_EXECUTED)), ("Read", count(COVERAGE_READ)),
+                              ("Written", count(COVERAGE_WRITTEN)), ("Never accessed", never_accessed)] {
+            writeln!(report, "  {:<15} {:>5} bytes {:>6.2}%", name, count, percent(count)).unwrap();
+        }
+
+        // An executed instruction takes 2 bytes, the other bytes are shown by words when possible
+        writeln!(report, "\nAddress  Bytes  Access  Instruction").unwrap();
+        let mut address = start;
+        while address < end {
+            let is_instruction = self.flags[address] & COVERAGE_INSTRUCTION != 0;
+            let next_is_instruction = address + 1 < end && self.flags[address + 1] & COVERAGE_INSTRUCTION != 0;
+            let size = if address + 1 < end && (is_instruction || !next_is_instruction) { 2 } else { 1 };
+
+            let line_flags = self.flags[address..address + size].iter().fold(0, |line_flags, flags| line_flags | flags);
+            let access: String = [(COVERAGE_EXECUTED, 'X'), (COVERAGE_READ, 'R'), (COVERAGE_WRITTEN, 'W')].iter()
+                .map(|(mask, letter)| if line_flags & mask != 0 { *letter } else { '-' })
+                .collect();
+
+            let (bytes, instruction) = match size {
+                2 => {
+                    let opcode = (memory[address] as OpCode) << 8 | memory[address + 1] as OpCode;
+                    (format!("{:04X}", opcode), disassemble(opcode))
+                }
+                _ => (format!("{:02X}", memory[address]), format!("DB {:02X}", memory[address]))
+            };
+            writeln!(report, "{:03X}      {:<4}   {}     {}", address, bytes, access, instruction).unwrap();
+
+            address += size;
+        }
+
+        report
+    }
+}
+
+// Unit tests
+#[test]
+fn test_coverage_report() {
+    //--------------------------------------------------------------------
+    // Setup: Draw a sprite, overwrite it with FX55 and loop, a jump is never executed
+    //--------------------------------------------------------------------
+    let mut chip8 = crate::chip8::Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+    let rom = [0xA2, 0x0A, 0xD0, 0x01, 0xF0, 0x55, 0x12, 0x06, 0x12, 0x00, 0xF0];
+    chip8.memory[0x200..0x20B].copy_from_slice(&rom);
+    chip8.rom_size = rom.len();
+    chip8.init().unwrap();
+    chip8.enable_coverage();
+
+    //--------------------------------------------------------------------
+    // Execute: Run until the loop, and get the report
+    //--------------------------------------------------------------------
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    let report = chip8.coverage_report().unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: Each byte is annotated with its accesses
+    //--------------------------------------------------------------------
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "Coverage of 200-20A (11 bytes)", "The ROM should be covered");
+    assert_eq!(lines[1], "  Executed            8 bytes  72.73%", "8 bytes should be executed");
+    assert_eq!(lines[4], "  Never accessed      2 bytes  18.18%", "The unexecuted jump should be counted");
+    assert_eq!(&lines[7..], ["200      A20A   X--     LD I, 20A", "202      D001   X--     DRW V0, V0, 1",
+                             "204      F055   X--     LD [I], V0", "206      1206   X--     JP 206",
+                             "208      1200   ---     JP 200", "20A      00     -RW     DB 00"],
+               "The disassembly should be annotated");
+}
@@ -0,0 +1,255 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// ROM database, using the chip-8-database format:
+// https://github.com/chip-8/chip-8-database
+//************************************************************************
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::chip8::constants::CHIP8_TIMER_CLOCK_SPEED;
+use crate::chip8::quirks::{Platform, Quirks};
+
+const PROGRAMS_JSON: &str = include_str!("database/programs.json");
+const HASHES_JSON  : &str = include_str!("database/sha1-hashes.json");
+
+// Everything known about a ROM
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+    pub hash       : String,
+    pub title      : String,
+    pub description: Option<String>,
+    pub authors    : Vec<String>,
+    pub release    : Option<String>,
+    pub platform   : Platform,
+    pub quirks     : Quirks,
+    pub tickrate   : Option<u32>,
+    pub colors     : Vec<[u8; 3]>,
+    pub keys       : Vec<(String, u8)>
+}
+
+impl RomInfo {
+    // Recommended CPU clock speed, in Hz
+    pub fn clock_speed(&self) -> Option<u32> {
+        self.tickrate.map(|tickrate| tickrate * CHIP8_TIMER_CLOCK_SPEED as u32)
+    }
+}
+
+// The ROM database
+pub struct Database {
+    programs: Vec<Program>,
+    hashes  : HashMap<String, usize>
+}
+
+impl Database {
+    // The database embedded in the executable, parsed on the first use
+    pub fn embedded() -> &'static Self {
+        static EMBEDDED: OnceLock<Database> = OnceLock::new();
+        EMBEDDED.get_or_init(|| Database::from_json(PROGRAMS_JSON, HASHES_JSON)
+            .expect("The embedded ROM database is invalid"))
+    }
+
+    // Load a database from the content of programs.json and sha1-hashes.json
+    pub fn from_json(programs: &str, hashes: &str) -> Result<Self, String> {
+        let programs = serde_json::from_str(programs)
+            .map_err(|e| format!("Invalid ROM database programs: {}", e))?;
+        let hashes = serde_json::from_str(hashes)
+            .map_err(|e| format!("Invalid ROM database hashes: {}", e))?;
+
+        Ok(Database { programs, hashes })
+    }
+
+    // Find a ROM in the database from its content
+    pub fn find(&self, rom: &[u8]) -> Option<RomInfo> {
+        self.find_by_hash(&sha1_hex(rom))
+    }
+
+    // Find a ROM in the database from its SHA-1
+    pub fn find_by_hash(&self, hash: &str) -> Option<RomInfo> {
+        let program = self.programs.get(*self.hashes.get(hash)?)?;
+        let rom = program.roms.get(hash)?;
+
+        // Use the first platform we support, and its quirks
+        let platform = rom.platforms.iter()
+            .find_map(|id| Platform::from_database_id(id))
+            .unwrap_or(Platform::Chip8);
+
+        let mut quirks = platform.default_quirks();
+        for (id, overrides) in rom.quirky_platforms.iter() {
+            if Platform::from_database_id(id) == Some(platform) {
+                overrides.apply(&mut quirks);
+            }
+        }
+
+        let mut keys: Vec<(String, u8)> = rom.keys.iter().map(|(name, key)| (name.clone(), *key)).collect();
+        keys.sort();
+
+        Some(RomInfo {
+            hash       : hash.to_string(),
+            title      : program.title.clone(),
+            description: program.description.clone(),
+            authors    : program.authors.clone(),
+            release    : program.release.clone(),
+            platform,
+            quirks,
+            tickrate   : rom.tickrate,
+            colors     : rom.colors.as_ref()
+                .map(|colors| colors.pixels.iter().filter_map(|c| parse_color(c)).collect())
+                .unwrap_or_default(),
+            keys
+        })
+    }
+}
+
+// Get the SHA-1 of a ROM, as a lowercase hex string
+pub fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Parse a "#RRGGBB" color, the # being optional
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+// ------- JSON structures -------
+#[derive(Deserialize)]
+struct Program {
+    title      : String,
+    description: Option<String>,
+    release    : Option<String>,
+    #[serde(default)]
+    authors    : Vec<String>,
+    roms       : HashMap<String, Rom>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms       : Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    tickrate        : Option<u32>,
+    colors          : Option<Colors>,
+    #[serde(default)]
+    keys            : HashMap<String, u8>
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+    shift                   : Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    jump                    : Option<bool>,
+    logic                   : Option<bool>,
+    wrap                    : Option<bool>
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift                       { quirks.shift = shift; }
+        if let Some(unchanged) = self.memory_leave_i_unchanged { quirks.memory_increment = !unchanged; }
+        if let Some(jump) = self.jump                         { quirks.jump = jump; }
+        if let Some(logic) = self.logic                       { quirks.vf_reset = logic; }
+        if let Some(wrap) = self.wrap                         { quirks.clip = !wrap; }
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+const TEST_PROGRAMS: &str = r##"[
+    {
+        "title": "Test ROM",
+        "authors": ["Le-dragon-dev"],
+        "roms": {
+            "a9993e364706816aba3e25717850c26c9cd0d89d": {
+                "platforms": ["superchip"],
+                "quirkyPlatforms": { "superchip": { "wrap": true } },
+                "tickrate": 30,
+                "colors": { "pixels": ["#000000", "#FF8000"] },
+                "keys": { "up": 5 }
+            }
+        }
+    }
+]"##;
+
+#[cfg(test)]
+const TEST_HASHES: &str = r#"{ "a9993e364706816aba3e25717850c26c9cd0d89d": 0 }"#;
+
+#[test]
+fn test_embedded_database() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: The embedded database should be valid
+    //--------------------------------------------------------------------
+    Database::embedded();
+}
+
+#[test]
+fn test_embedded_roms() {
+    //--------------------------------------------------------------------
+    // Setup: Read the hashes of the embedded ROMs, none until the files are fetched with update.sh
+    //--------------------------------------------------------------------
+    let hashes: HashMap<String, usize> = serde_json::from_str(HASHES_JSON).unwrap();
+    let programs: Vec<serde_json::Value> = serde_json::from_str(PROGRAMS_JSON).unwrap();
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Every known ROM hash should be found
+    //--------------------------------------------------------------------
+    assert!(hashes.values().all(|&index| index < programs.len()), "Every hash should point to a program");
+    for hash in hashes.keys() {
+        let info = Database::embedded().find_by_hash(hash);
+        assert_eq!(info.map(|info| info.hash), Some(hash.clone()), "The ROM {} should be found", hash);
+    }
+}
+
+#[test]
+fn test_sha1_hex() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: SHA-1 of "abc" should match the FIPS 180 example
+    //--------------------------------------------------------------------
+    assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d", "The SHA-1 should match");
+}
+
+#[test]
+fn test_find_rom() {
+    //--------------------------------------------------------------------
+    // Setup: Create a database with a ROM whose content is "abc"
+    //--------------------------------------------------------------------
+    let database = Database::from_json(TEST_PROGRAMS, TEST_HASHES).unwrap();
+
+    //--------------------------------------------------------------------
+    // Execute: Find the ROM
+    //--------------------------------------------------------------------
+    let info = database.find(b"abc").expect("The ROM should be found");
+
+    //--------------------------------------------------------------------
+    // Verify: Platform, quirks and settings come from the database
+    //--------------------------------------------------------------------
+    let mut quirks = Platform::SuperChip.default_quirks();
+    quirks.clip = false;
+
+    assert_eq!(info.title, "Test ROM", "The title should be Test ROM");
+    assert_eq!(info.platform, Platform::SuperChip, "The platform should be SUPER-CHIP");
+    assert_eq!(info.quirks, quirks, "The wrap quirk should be overridden");
+    assert_eq!(info.tickrate, Some(30), "The tickrate should be 30");
+    assert_eq!(info.colors, vec![[0, 0, 0], [0xFF, 0x80, 0]], "The colors should be parsed");
+    assert_eq!(info.keys, vec![("up".to_string(), 5)], "The keys should be parsed");
+    assert!(database.find(b"abd").is_none(), "An unknown ROM should not be found");
+}
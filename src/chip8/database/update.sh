@@ -0,0 +1,17 @@
+#!/bin/sh
+#*************************************************************************
+# Rust CHIP-8 emulator, created by David Garcia
+# Distributed under the MIT license
+#
+# Download the chip-8-database files embedded in the emulator
+#*************************************************************************
+
+set -e
+
+DATABASE_URL=https://raw.githubusercontent.com/chip-8/chip-8-database/master/database
+cd "$(dirname "$0")"
+
+for file in programs.json sha1-hashes.json; do
+    curl --fail --silent --show-error --location -o "$file.tmp" "$DATABASE_URL/$file"
+    mv "$file.tmp" "$file"
+done
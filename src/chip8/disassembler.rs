@@ -0,0 +1,197 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Disassembler, with the same decoding as the interpreter
+//************************************************************************
+
+use crate::chip8::MachineState;
+use crate::chip8::constants::CHIP8_PROGRAM_COUNTER_INC;
+use crate::chip8::opcodes::{get_addr_from_opcode, get_reg_and_reg_and_value_from_opcode, get_reg_and_reg_from_opcode,
+                            get_reg_and_value_from_opcode, get_reg_from_opcode};
+use crate::chip8::types::OpCode;
+
+// Mnemonic of an opcode, "DW" followed by the opcode when it is not an instruction
+pub fn disassemble(opcode: OpCode) -> String {
+    let address = get_addr_from_opcode(opcode);
+    let x = get_reg_from_opcode(opcode);
+    let (_, value) = get_reg_and_value_from_opcode(opcode);
+    let (_, y, n) = get_reg_and_reg_and_value_from_opcode(opcode);
+
+    match opcode {
+        0x00E0          => "CLS".to_string(),
+        0x00EE          => "RET".to_string(),
+        0x0000..=0x0FFF => format!("SYS {:03X}", address),
+        0x1000..=0x1FFF => format!("JP {:03X}", address),
+        0x2000..=0x2FFF => format!("CALL {:03X}", address),
+        0x3000..=0x3FFF => format!("SE V{:X}, {:02X}", x, value),
+        0x4000..=0x4FFF => format!("SNE V{:X}, {:02X}", x, value),
+        0x5000..=0x5FF0 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000..=0x6FFF => format!("LD V{:X}, {:02X}", x, value),
+        0x7000..=0x7FFF => format!("ADD V{:X}, {:02X}", x, value),
+
+        0x8000..=0x8FFF if n == 0x0 => format!("LD V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x1 => format!("OR V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x2 => format!("AND V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x3 => format!("XOR V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x4 => format!("ADD V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x5 => format!("SUB V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x6 => format!("SHR V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+        0x8000..=0x8FFF if n == 0xE => format!("SHL V{:X}, V{:X}", x, y),
+
+        0x9000..=0x9FF0 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000..=0xAFFF => format!("LD I, {:03X}", address),
+        0xB000..=0xBFFF => format!("JP V0, {:03X}", address),
+        0xC000..=0xCFFF => format!("RND V{:X}, {:02X}", x, value),
+        0xD000..=0xDFFF => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+
+        0xE09E..=0xEF9E if value == 0x9E => format!("SKP V{:X}", x),
+        0xE0A1..=0xEFA1 if value == 0xA1 => format!("SKNP V{:X}", x),
+
+        0xF007..=0xFF07 if value == 0x07 => format!("LD V{:X}, DT", x),
+        0xF00A..=0xFF0A if value == 0x0A => format!("LD V{:X}, K", x),
+        0xF015..=0xFF15 if value == 0x15 => format!("LD DT, V{:X}", x),
+        0xF018..=0xFF18 if value == 0x18 => format!("LD ST, V{:X}", x),
+        0xF01E..=0xFF1E if value == 0x1E => format!("ADD I, V{:X}", x),
+        0xF029..=0xFF29 if value == 0x29 => format!("LD F, V{:X}", x),
+        0xF033..=0xFF33 if value == 0x33 => format!("LD B, V{:X}", x),
+        0xF055..=0xFF55 if value == 0x55 => format!("LD [I], V{:X}", x),
+        0xF065..=0xFF65 if value == 0x65 => format!("LD V{:X}, [I]", x),
+        0xF075..=0xFF75 if value == 0x75 => format!("LD R, V{:X}", x),
+        0xF085..=0xFF85 if value == 0x85 => format!("LD V{:X}, R", x),
+
+        _ => format!("DW {:04X}", opcode)
+    }
+}
+
+// Class of an opcode, as written in the specifications ("8XY4", "DXYN"...), "DATA" when it is not an instruction
+pub fn opcode_class(opcode: OpCode) -> &'static str {
+    let n = opcode & 0x000F;
+    let value = opcode & 0x00FF;
+
+    match opcode {
+        0x00E0          => "00E0",
+        0x00EE          => "00EE",
+        0x0000..=0x0FFF => "0NNN",
+        0x1000..=0x1FFF => "1NNN",
+        0x2000..=0x2FFF => "2NNN",
+        0x3000..=0x3FFF => "3XNN",
+        0x4000..=0x4FFF => "4XNN",
+        0x5000..=0x5FF0 if n == 0 => "5XY0",
+        0x6000..=0x6FFF => "6XNN",
+        0x7000..=0x7FFF => "7XNN",
+
+        0x8000..=0x8FFF if n == 0x0 => "8XY0",
+        0x8000..=0x8FFF if n == 0x1 => "8XY1",
+        0x8000..=0x8FFF if n == 0x2 => "8XY2",
+        0x8000..=0x8FFF if n == 0x3 => "8XY3",
+        0x8000..=0x8FFF if n == 0x4 => "8XY4",
+        0x8000..=0x8FFF if n == 0x5 => "8XY5",
+        0x8000..=0x8FFF if n == 0x6 => "8XY6",
+        0x8000..=0x8FFF if n == 0x7 => "8XY7",
+        0x8000..=0x8FFF if n == 0xE => "8XYE",
+
+        0x9000..=0x9FF0 if n == 0 => "9XY0",
+        0xA000..=0xAFFF => "ANNN",
+        0xB000..=0xBFFF => "BNNN",
+        0xC000..=0xCFFF => "CXNN",
+        0xD000..=0xDFFF => "DXYN",
+
+        0xE09E..=0xEF9E if value == 0x9E => "EX9E",
+        0xE0A1..=0xEFA1 if value == 0xA1 => "EXA1",
+
+        0xF007..=0xFF07 if value == 0x07 => "FX07",
+        0xF00A..=0xFF0A if value == 0x0A => "FX0A",
+        0xF015..=0xFF15 if value == 0x15 => "FX15",
+        0xF018..=0xFF18 if value == 0x18 => "FX18",
+        0xF01E..=0xFF1E if value == 0x1E => "FX1E",
+        0xF029..=0xFF29 if value == 0x29 => "FX29",
+        0xF033..=0xFF33 if value == 0x33 => "FX33",
+        0xF055..=0xFF55 if value == 0x55 => "FX55",
+        0xF065..=0xFF65 if value == 0x65 => "FX65",
+        0xF075..=0xFF75 if value == 0x75 => "FX75",
+        0xF085..=0xFF85 if value == 0x85 => "FX85",
+
+        _ => "DATA"
+    }
+}
+
+// Effect of the next instruction, for the instructions changing the flow or waiting
+pub fn describe_next(state: &MachineState) -> String {
+    let opcode = match state.opcode_at(state.program_counter) {
+        Some(opcode) => opcode,
+        None => return "PC out of memory".to_string()
+    };
+
+    let (x, y) = get_reg_and_reg_from_opcode(opcode);
+    let (_, value) = get_reg_and_value_from_opcode(opcode);
+    let (vx, vy) = (state.registers[x], state.registers[y]);
+
+    let skip = |taken: bool| if taken { "skip taken" } else { "skip not taken" }.to_string();
+
+    match opcode {
+        0x00E0 => "clear the screen".to_string(),
+        0x00EE => match state.stack_ptr {
+            0 => "return with an empty stack".to_string(),
+            stack_ptr => format!("return to {:03X}", state.stack[stack_ptr - 1].wrapping_add(CHIP8_PROGRAM_COUNTER_INC))
+        },
+        0x1000..=0x1FFF => format!("jump to {:03X}", get_addr_from_opcode(opcode)),
+        0x2000..=0x2FFF => format!("call {:03X}", get_addr_from_opcode(opcode)),
+        0x3000..=0x3FFF => skip(vx == value),
+        0x4000..=0x4FFF => skip(vx != value),
+        0x5000..=0x5FF0 if opcode & 0x000F == 0 => skip(vx == vy),
+        0x9000..=0x9FF0 if opcode & 0x000F == 0 => skip(vx != vy),
+        0xB000..=0xBFFF => {
+            let register = if state.quirks.jump { x } else { 0 };
+            format!("jump to {:03X}", state.registers[register] as u16 + get_addr_from_opcode(opcode))
+        }
+        0xD000..=0xDFFF => format!("draw {} rows from {:03X} at {},{}", opcode & 0x000F, state.addr_register, vx, vy),
+        0xE09E..=0xEF9E if value == 0x9E => skip(state.keys[vx as usize & 0x0F]),
+        0xE0A1..=0xEFA1 if value == 0xA1 => skip(!state.keys[vx as usize & 0x0F]),
+        0xF00A..=0xFF0A if value == 0x0A => "wait for a key".to_string(),
+        _ => String::new()
+    }
+}
+
+// Unit tests
+#[test]
+fn test_disassemble() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Instructions and data
+    //--------------------------------------------------------------------
+    assert_eq!(disassemble(0x00E0), "CLS", "00E0 should be CLS");
+    assert_eq!(disassemble(0x2ABC), "CALL ABC", "2NNN should be CALL");
+    assert_eq!(disassemble(0x8126), "SHR V1, V2", "8XY6 should be SHR");
+    assert_eq!(disassemble(0xD125), "DRW V1, V2, 5", "DXYN should be DRW");
+    assert_eq!(disassemble(0xF365), "LD V3, [I]", "FX65 should be LD VX, [I]");
+    assert_eq!((disassemble(0xF775), disassemble(0xF785)), ("LD R, V7".to_string(), "LD V7, R".to_string()),
+               "FX75 and FX85 should be LD R, VX and LD VX, R");
+    assert_eq!(disassemble(0x8128), "DW 8128", "Unknown opcodes should be data");
+    assert_eq!((disassemble(0x5121), opcode_class(0x912F)), ("DW 5121".to_string(), "DATA"),
+               "5XYN and 9XYN should only be skips with N = 0");
+    assert_eq!((opcode_class(0x8124), opcode_class(0xF30A)), ("8XY4", "FX0A"), "The classes should be decoded");
+}
+
+#[test]
+fn test_describe_next() {
+    //--------------------------------------------------------------------
+    // Setup: SE V1, 05 at 0x200 with V1 = 5, and RET at 0x202
+    //--------------------------------------------------------------------
+    let mut state = MachineState::default();
+    state.memory[0x200..0x204].copy_from_slice(&[0x31, 0x05, 0x00, 0xEE]);
+    state.registers[1] = 5;
+    state.stack[0] = 0x300;
+    state.stack_ptr = 1;
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The skip is taken, and the return goes after the call
+    //--------------------------------------------------------------------
+    assert_eq!(describe_next(&state), "skip taken", "V1 should be equal to 5");
+
+    state.registers[1] = 6;
+    assert_eq!(describe_next(&state), "skip not taken", "V1 should not be equal to 5");
+
+    state.program_counter = 0x202;
+    assert_eq!(describe_next(&state), "return to 302", "RET should go after the CALL");
+}
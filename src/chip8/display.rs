@@ -7,6 +7,7 @@
 
 use crate::chip8::constants::CHIP8_PIXEL_COUNT;
 
+// Each pixel is the mask of the bitplanes it is set in (0 when the pixel is off)
 pub trait Display {
     fn draw(&mut self, pixels: [u8; CHIP8_PIXEL_COUNT]);
 }
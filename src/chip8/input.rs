@@ -7,5 +7,7 @@
 
 pub trait KeyInput {
     fn is_key_pressed(&self, key: u8) -> bool;
-    fn get_key(&mut self)             -> u8;
+
+    // Wait for a key press, None keeps the emulator waiting on FX0A
+    fn get_key(&mut self) -> Option<u8>;
 }
\ No newline at end of file
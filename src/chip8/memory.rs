@@ -5,13 +5,37 @@
 // Memory methods
 //************************************************************************
 
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{Seek, SeekFrom, Read};
+use std::io::{Cursor, Read};
 use std::path::Path;
 
-use crate::chip8::{Chip8, KeyInput};
-use crate::chip8::constants::{CHIP8_MAX_EXECUTABLE_SIZE, CHIP8_MEMORY_START};
+use zip::ZipArchive;
+
+use crate::chip8::{sha1_hex, Cartridge, Chip8, CodeWrite, Database, KeyInput, Platform, RomInfo};
+use crate::chip8::constants::{CHIP8_CPU_CLOCK_SPEED, CHIP8_MEMORY_SIZE, CHIP8_MEMORY_START};
 use crate::chip8::display::Display;
+use crate::chip8::types::Address;
+
+// Signature of the zip archives
+const ZIP_SIGNATURE: [u8; 4] = *b"PK\x03\x04";
+
+// Extensions of the ROMs looked for in the zip archives with several files
+const ZIP_ROM_EXTENSIONS: [&str; 4] = ["ch8", "sc8", "xo8", "c8"];
+
+// Extensions of the ROMs written as a hex listing or an Intel HEX file
+const TEXT_ROM_EXTENSIONS: [&str; 2] = ["hex", "txt"];
+
+// Biggest file read as a ROM, room for a 64 KB program written as a hex listing, an Intel HEX file or a cartridge
+const ROM_FILE_MAX_SIZE: u64 = 1024 * 1024;
+
+// Intel HEX record types, the start address records are ignored as the programs start at 0x200
+const INTEL_HEX_DATA            : u8 = 0x00;
+const INTEL_HEX_END_OF_FILE     : u8 = 0x01;
+const INTEL_HEX_SEGMENT_ADDRESS : u8 = 0x02;
+const INTEL_HEX_SEGMENT_START   : u8 = 0x03;
+const INTEL_HEX_LINEAR_ADDRESS  : u8 = 0x04;
+const INTEL_HEX_LINEAR_START    : u8 = 0x05;
 
 impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
     // Load the fontset in memory
@@ -38,41 +62,461 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
         self.memory[0x0050..0x00A0].copy_from_slice(&font[..]);
     }
 
-    // Try to load the executable in memory
-    pub fn load_rom_file(&mut self, path: &String) -> Result<(), String> {
-        let path = Path::new(path);
+    // Write a byte before running, e.g. the test selected at 0x1FF by the test ROMs
+    pub fn poke(&mut self, address: Address, value: u8) {
+        self.memory[address as usize % CHIP8_MEMORY_SIZE] = value;
+    }
 
-        // The path does not exist
-        if !path.exists() {
-            return Err(format!("Error load: The path {} does not exist!", path.to_str().unwrap()));
+    // Read a byte as data, for the instructions using I. The addresses past the end of the memory wrap around
+    pub(crate) fn read_memory(&mut self, address: usize) -> u8 {
+        let address = address % self.platform.memory_size();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(address);
         }
 
-        // The path is not a file
-        if !path.is_file() {
-            return Err(format!("Error load: {} is not a file!", path.to_str().unwrap()));
+        self.memory[address]
+    }
+
+    // Write a byte, for the instructions using I. The addresses past the end of the memory wrap around
+    pub(crate) fn write_memory(&mut self, address: usize, value: u8) {
+        let address = address % self.platform.memory_size();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_write(address);
+        }
+
+        // The instructions are decoded from the memory at each step, a modified code runs as is
+        if let Some(code_writes) = &mut self.code_writes {
+            let write = CodeWrite {
+                program_counter: self.program_counter,
+                address        : address as Address,
+                old_value      : self.memory[address],
+                new_value      : value
+            };
+
+            if let Some(write) = code_writes.record_write(write) {
+                if self.break_on_code_write && self.code_write_hit.is_none() {
+                    self.code_write_hit = Some(write);
+                }
+            }
         }
 
-        // Open the file and get the size
-        let mut file = File::open(path)
-            .map_err(|_| format!("Impossible to load the file {}", path.to_str().unwrap()))?;
+        self.memory[address] = value;
+    }
+
+    // Try to load the executable in memory, from a file, the standard input ("-") or a zip archive
+    pub fn load_rom_file(&mut self, path: &str) -> Result<(), String> {
+        let rom = read_rom_file(path)?;
+        self.load_rom(&rom).map_err(|e| format!("Error load: {}: {}", path, e))
+    }
 
-        let file_size = file.seek(SeekFrom::End(0))
-            .map_err(|_| format!("Impossible to read the file {}", path.to_str().unwrap()))?;
+    // Load an executable from a reader, unpacking it from a zip archive, the text formats are recognized from
+    // their content as for the standard input
+    pub fn load_rom_reader(&mut self, reader: impl Read) -> Result<(), String> {
+        let rom = read_rom(reader, None)?;
+        self.load_rom(&rom)
+    }
 
-        // If the file size is over 3kb, return an error
-        if file_size > CHIP8_MAX_EXECUTABLE_SIZE as u64 {
-            return Err(format!("The file {} is too big! ({} bytes / {} allowed bytes)",
-                               path.to_str().unwrap(), file_size, CHIP8_MAX_EXECUTABLE_SIZE));
+    // Load an executable from memory: a binary, or the program of an Octo cartridge with its settings
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        let (program, rom_info) = decode_rom(rom)?;
+        self.load_program(&program, rom_info)
+    }
+
+    // Replace the ROM with a new version and reset the machine, keeping the registers V0-VF and I on request. An
+    // invalid ROM is rejected before the reset, leaving the running program untouched
+    pub fn reload_rom(&mut self, rom: &[u8], keep_registers: bool) -> Result<(), String> {
+        let (program, rom_info) = decode_rom(rom)?;
+        program_platform(&program, rom_info.as_ref())?;
+        if program.is_empty() {
+            return Err("No rom loaded!".to_string());
         }
 
-        // Return at the start of the file
-        file.seek(SeekFrom::Start(0))
-            .map_err(|_| format!("Impossible to read the file {}", path.to_str().unwrap()))?;
+        let (registers, addr_register) = (self.registers, self.addr_register);
+        self.reset(true);
+        self.load_program(&program, rom_info)?;
+        self.init()?;
 
-        // Copy the file into memory
-        file.read_exact(&mut self.memory[CHIP8_MEMORY_START as usize..(CHIP8_MEMORY_START as usize + file_size as usize)])
-            .map_err(|_| format!("Impossible to copy the executable into memory"))?;
+        if keep_registers {
+            self.registers     = registers;
+            self.addr_register = addr_register;
+        }
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    // Load a program, with the platform, quirks and clock speed of its database entry, nothing is kept from the
+    // previous program but the font
+    fn load_program(&mut self, rom: &[u8], rom_info: Option<RomInfo>) -> Result<(), String> {
+        let platform = program_platform(rom, rom_info.as_ref())?;
+
+        // Copy the ROM into memory, clearing the rest of the previous program
+        self.memory[CHIP8_MEMORY_START as usize..].fill(0);
+        self.memory[CHIP8_MEMORY_START as usize..(CHIP8_MEMORY_START as usize + rom.len())].copy_from_slice(rom);
+        self.rom_size = rom.len();
+        self.rom_hash = sha1_hex(rom);
+
+        // Use the platform, quirks and clock speed of the ROM, or the defaults
+        self.set_platform(platform);
+        self.set_clock_speed(CHIP8_CPU_CLOCK_SPEED);
+        if let Some(info) = &rom_info {
+            self.quirks = info.quirks;
+
+            if let Some(clock_speed) = info.clock_speed() {
+                self.set_clock_speed(clock_speed);
+            }
+        }
+        self.rom_info = rom_info;
+
+        Ok(())
+    }
+}
+
+// Platform of a program, from its database entry. The unknown ROMs too big for 4 KB of memory run as XO-CHIP, and
+// the ROM has to fit between 0x200 and the end of the memory
+fn program_platform(rom: &[u8], rom_info: Option<&RomInfo>) -> Result<Platform, String> {
+    let platform = match rom_info {
+        Some(info) => info.platform,
+        None if rom.len() > Platform::Chip8.max_rom_size() => Platform::XoChip,
+        None => Platform::Chip8
+    };
+
+    if rom.len() > platform.max_rom_size() {
+        return Err(format!("The ROM is too big for {}! ({} bytes / {} allowed bytes)",
+                           platform.name(), rom.len(), platform.max_rom_size()));
+    }
+
+    Ok(platform)
+}
+
+// Get the program of a ROM and its settings, from the cartridge or the database
+pub fn decode_rom(rom: &[u8]) -> Result<(Cow<'_, [u8]>, Option<RomInfo>), String> {
+    if Cartridge::is_cartridge(rom) {
+        let cartridge = Cartridge::decode(rom)?;
+        return Ok((Cow::Owned(cartridge.program), Some(cartridge.info)));
+    }
+
+    let rom_info = Database::embedded().find(rom);
+    Ok((Cow::Borrowed(rom), rom_info))
+}
+
+// Read a ROM from a file, or from the standard input with "-", unpacking it from a zip archive. The text formats
+// are converted to binary, so they are looked up in the database as the binary ROMs
+pub fn read_rom_file(path: &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        return read_rom(std::io::stdin().lock(), None).map_err(|e| format!("Error load: standard input: {}", e));
+    }
+
+    let path = Path::new(path);
+
+    // The path does not exist
+    if !path.exists() {
+        return Err(format!("Error load: The path {} does not exist!", path.to_str().unwrap()));
+    }
+
+    // The path is not a file
+    if !path.is_file() {
+        return Err(format!("Error load: {} is not a file!", path.to_str().unwrap()));
+    }
+
+    // Read the file
+    let file = File::open(path)
+        .map_err(|_| format!("Impossible to load the file {}", path.to_str().unwrap()))?;
+
+    read_rom(file, path.to_str()).map_err(|e| format!("Error load: {}: {}", path.to_str().unwrap(), e))
+}
+
+// Read a ROM, unpacking it when it is a zip archive, and converting it to binary when it is a text format. The text
+// formats come from the extension of the file, and from the content when the ROM has no name (standard input)
+fn read_rom(reader: impl Read, name: Option<&str>) -> Result<Vec<u8>, String> {
+    let data = read_limited(reader).map_err(|e| format!("Cannot read the ROM ({})", e))?;
+
+    let (data, name) = if data.starts_with(&ZIP_SIGNATURE) {
+        let (name, data) = read_zipped_rom(&data)?;
+        (data, Some(name))
+    } else {
+        (data, name.map(String::from))
+    };
+
+    let is_text = match name {
+        Some(name) => has_extension(&name, &TEXT_ROM_EXTENSIONS),
+        None => is_intel_hex(&data) || is_hex_text(&data)
+    };
+    if !is_text {
+        return Ok(data);
+    }
+
+    let text = String::from_utf8_lossy(&data);
+    match is_intel_hex(&data) {
+        true  => parse_intel_hex(&text),
+        false => parse_hex_text(&text)
+    }
+}
+
+// Unpack the ROM of a zip archive, with its name: its only file, or its only file with a ROM extension
+fn read_zipped_rom(data: &[u8]) -> Result<(String, Vec<u8>), String> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Invalid zip archive ({})", e))?;
+
+    // The directories and the metadata added by macOS are not files of the archive
+    let files: Vec<String> = archive.file_names()
+        .filter(|name| !name.ends_with('/') && !name.starts_with("__MACOSX/"))
+        .map(String::from)
+        .collect();
+    let roms: Vec<&String> = match files.len() {
+        1 => files.iter().collect(),
+        _ => files.iter().filter(|name| has_extension(name, &ZIP_ROM_EXTENSIONS)).collect()
+    };
+
+    match roms[..] {
+        [rom] => {
+            let file = archive.by_name(rom).map_err(|e| format!("Cannot unpack {} ({})", rom, e))?;
+            let data = read_limited(file).map_err(|e| format!("Cannot unpack {} ({})", rom, e))?;
+            Ok((rom.clone(), data))
+        }
+        []   => Err(format!("No ROM in the zip archive (.{})", ZIP_ROM_EXTENSIONS.join(", ."))),
+        _    => Err(format!("Several ROMs in the zip archive: {}", roms.iter().map(|rom| rom.as_str()).collect::<Vec<&str>>().join(", ")))
+    }
+}
+
+// Read at most the size of the biggest ROM file
+fn read_limited(reader: impl Read) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    reader.take(ROM_FILE_MAX_SIZE + 1).read_to_end(&mut data).map_err(|e| e.to_string())?;
+
+    if data.len() as u64 > ROM_FILE_MAX_SIZE {
+        return Err(format!("too big, more than {} bytes", ROM_FILE_MAX_SIZE));
+    }
+    Ok(data)
+}
+
+// Only hex digits and whitespaces
+fn is_hex_text(data: &[u8]) -> bool {
+    data.iter().any(u8::is_ascii_hexdigit) && data.iter().all(|byte| byte.is_ascii_hexdigit() || byte.is_ascii_whitespace())
+}
+
+// Records starting with a colon, made of hex digits
+fn is_intel_hex(data: &[u8]) -> bool {
+    data.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b':') &&
+        data.iter().all(|byte| byte.is_ascii_hexdigit() || byte.is_ascii_whitespace() || *byte == b':')
+}
+
+// Bytes of a run of hex digits, 2 digits per byte
+fn parse_hex_digits(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Program written as whitespace separated hex bytes or words ("00E0 A22A" or "00 E0 A2 2A")
+fn parse_hex_text(text: &str) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    for token in text.split_whitespace() {
+        rom.extend(parse_hex_digits(token).ok_or(format!("Invalid hex listing: odd number of digits in {}", token))?);
+    }
+
+    Ok(rom)
+}
+
+// Program in Intel HEX, the addresses of the records being memory addresses from 0x200
+fn parse_intel_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    let mut base = 0;
+
+    for (line, record) in text.lines().enumerate().map(|(index, record)| (index + 1, record.trim())) {
+        if record.is_empty() {
+            continue;
+        }
+
+        // :LLAAAATT followed by LL bytes of data and the checksum
+        let bytes = record.strip_prefix(':').and_then(parse_hex_digits)
+            .filter(|bytes| bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5)
+            .ok_or(format!("Invalid Intel HEX record at line {}", line))?;
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("Invalid Intel HEX checksum at line {}", line));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        match (bytes[3], data.len()) {
+            (INTEL_HEX_DATA, _) => {
+                let start = base + address;
+                if start < CHIP8_MEMORY_START as usize || start + data.len() > CHIP8_MEMORY_SIZE {
+                    return Err(format!("Intel HEX data at {:04X} out of the program memory at line {}", start, line));
+                }
+
+                let offset = start - CHIP8_MEMORY_START as usize;
+                if rom.len() < offset + data.len() {
+                    rom.resize(offset + data.len(), 0);
+                }
+                rom[offset..offset + data.len()].copy_from_slice(data);
+            }
+            (INTEL_HEX_END_OF_FILE, _) => return Ok(rom),
+            (INTEL_HEX_SEGMENT_ADDRESS, 2) => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4,
+            (INTEL_HEX_LINEAR_ADDRESS, 2)  => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+            (INTEL_HEX_SEGMENT_START, _) | (INTEL_HEX_LINEAR_START, _) => {}
+            (record_type, _) => return Err(format!("Invalid Intel HEX record type {:02X} at line {}", record_type, line))
+        }
+    }
+
+    Err("Invalid Intel HEX: no end of file record".to_string())
+}
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name).extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension.to_lowercase().as_str()))
+}
+
+// Unit tests
+#[cfg(test)]
+fn zip_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    use std::io::Write;
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in files {
+        writer.start_file(*name, SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated)).unwrap();
+        writer.write_all(data).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_load_rom() {
+    //--------------------------------------------------------------------
+    // Setup: An emulator running CHIP-8 ROMs
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The ROMs are read from readers and zip archives
+    //--------------------------------------------------------------------
+    chip8.load_rom_reader(&[0x12, 0x00][..]).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x202], chip8.rom_size), (&[0x12, 0x00][..], 2), "The ROM should be read");
+
+    let archive = zip_archive(&[("readme.txt", b"A game"), ("game/game.CH8", &[0x13, 0x00, 0x00])]);
+    chip8.load_rom_reader(&archive[..]).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x203], chip8.rom_size), (&[0x13, 0x00, 0x00][..], 3), "The ROM should be unpacked");
+
+    let archive = zip_archive(&[("game.bin", &[0x14, 0x00])]);
+    chip8.load_rom_reader(&archive[..]).unwrap();
+    assert_eq!(chip8.memory[0x200], 0x14, "The only file of an archive should be the ROM");
+
+    let archive = zip_archive(&[("a.ch8", &[0x12, 0x00]), ("b.ch8", &[0x12, 0x00])]);
+    assert_eq!(chip8.load_rom_reader(&archive[..]), Err("Several ROMs in the zip archive: a.ch8, b.ch8".to_string()),
+               "The ROM to load should not be guessed");
+    assert!(chip8.load_rom_reader(&b"PK\x03\x04"[..]).unwrap_err().starts_with("Invalid zip archive"),
+            "A truncated archive should be an error");
+
+    let huge = vec![0x12; ROM_FILE_MAX_SIZE as usize + 1];
+    assert_eq!(chip8.load_rom_reader(&huge[..]), Err("Cannot read the ROM (too big, more than 1048576 bytes)".to_string()),
+               "A huge stream should not be read to the end");
+    let archive = zip_archive(&[("huge.ch8", &huge)]);
+    assert_eq!(chip8.load_rom_reader(&archive[..]), Err("Cannot unpack huge.ch8 (too big, more than 1048576 bytes)".to_string()),
+               "A huge file of an archive should not be unpacked to the end");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The size limit depends on the platform
+    //--------------------------------------------------------------------
+    chip8.load_rom(&[0x12; 3584]).unwrap();
+    assert_eq!(chip8.platform(), Platform::Chip8, "A ROM filling 4 KB should run as CHIP-8");
+    assert_eq!(chip8.quirks, Platform::Chip8.default_quirks(), "An unknown ROM should have the quirks of its platform");
+
+    chip8.load_rom(&[0x12; 3585]).unwrap();
+    assert_eq!(chip8.platform(), Platform::XoChip, "A bigger unknown ROM should run as XO-CHIP");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Nothing is kept from the previous program
+    //--------------------------------------------------------------------
+    chip8.set_clock_speed(100);
+    chip8.load_rom(&[0x13, 0x00]).unwrap();
+    assert_eq!((chip8.platform(), chip8.quirks, chip8.clock_speed()),
+               (Platform::Chip8, Platform::Chip8.default_quirks(), CHIP8_CPU_CLOCK_SPEED),
+               "An unknown ROM should use the default settings");
+    assert!(chip8.memory[0x202..].iter().all(|&byte| byte == 0), "The previous program should be cleared");
+
+    assert_eq!(chip8.load_rom(&vec![0x12; 65025]),
+               Err("The ROM is too big for XO-CHIP! (65025 bytes / 65024 allowed bytes)".to_string()),
+               "A ROM should not be bigger than the memory");
+}
+
+#[test]
+fn test_load_text_rom() {
+    //--------------------------------------------------------------------
+    // Setup: An emulator
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Hex listings read without a name are bytes or words
+    //--------------------------------------------------------------------
+    chip8.load_rom_reader(&b"00e0 A2 2A\n\t1200\n"[..]).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x206], chip8.rom_size), (&[0x00, 0xE0, 0xA2, 0x2A, 0x12, 0x00][..], 6),
+               "The hex listing should be converted");
+    assert_eq!(chip8.load_rom_reader(&b"00E0 A22"[..]), Err("Invalid hex listing: odd number of digits in A22".to_string()),
+               "A digit should not be missing");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Intel HEX records are loaded at their addresses
+    //--------------------------------------------------------------------
+    chip8.load_rom_reader(&b":020000040000FA\n:02020400120CDA\n:0402000000E0A22A4E\n:00000001FF\n"[..]).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x206], chip8.rom_size), (&[0x00, 0xE0, 0xA2, 0x2A, 0x12, 0x0C][..], 6),
+               "The records should be loaded at their addresses");
+
+    assert_eq!(chip8.load_rom_reader(&b":0402000000E0A22A4F\n:00000001FF\n"[..]),
+               Err("Invalid Intel HEX checksum at line 1".to_string()), "The checksum should be checked");
+    assert_eq!(chip8.load_rom_reader(&b":0201000000E01D\n:00000001FF\n"[..]),
+               Err("Intel HEX data at 0100 out of the program memory at line 1".to_string()),
+               "The data should not overwrite the interpreter");
+    assert_eq!(chip8.load_rom_reader(&b":0402000000E0A22A4E\n"[..]), Err("Invalid Intel HEX: no end of file record".to_string()),
+               "The end of file record should be required");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The files are text when named .hex or .txt, even if a binary looks like text
+    //--------------------------------------------------------------------
+    let directory = std::env::temp_dir().join(format!("rust-chip-8-test-text-rom-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let (binary, listing) = (directory.join("ascii.ch8"), directory.join("listing.HEX"));
+    std::fs::write(&binary, b"12 00\n").unwrap();
+    std::fs::write(&listing, b"12 00\n").unwrap();
+    let roms = (read_rom_file(&binary.to_string_lossy()), read_rom_file(&listing.to_string_lossy()));
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(roms.0, Ok(b"12 00\n".to_vec()), "A binary ROM made of hex digits should be kept as binary");
+    assert_eq!(roms.1, Ok(vec![0x12, 0x00]), "A .hex file should be a hex listing");
+}
+
+#[test]
+fn test_reload_rom() {
+    //--------------------------------------------------------------------
+    // Setup: Run a ROM setting V0 and I
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(crate::chip8::mock::MockScreen::new(), crate::chip8::mock::MockInput::new());
+    chip8.load_rom(&[0x60, 0x05, 0xA3, 0x21, 0x12, 0x04]).unwrap();
+    chip8.init().unwrap();
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The new ROM restarts with the registers kept on request, an invalid ROM is ignored
+    //--------------------------------------------------------------------
+    chip8.reload_rom(&[0x61, 0x07, 0x12, 0x02], true).unwrap();
+    assert_eq!((&chip8.memory[0x200..0x206], chip8.program_counter), (&[0x61, 0x07, 0x12, 0x02, 0x00, 0x00][..], 0x200),
+               "The new ROM should replace the old one and start");
+    assert_eq!((chip8.registers[0], chip8.addr_register), (0x05, 0x321), "V0 and I should be kept");
+
+    chip8.step().unwrap();
+    assert_eq!(chip8.reload_rom(&[], false), Err("No rom loaded!".to_string()), "An empty ROM should be rejected");
+    assert_eq!(chip8.reload_rom(&vec![0x12; 0x10000], false).err(),
+               Some("The ROM is too big for XO-CHIP! (65536 bytes / 65024 allowed bytes)".to_string()),
+               "A too big ROM should be rejected");
+    assert_eq!((chip8.memory[0x200], chip8.program_counter, chip8.registers[1]), (0x61, 0x202, 0x07),
+               "The invalid ROMs should leave the machine untouched");
+
+    chip8.reload_rom(&[0x12, 0x00], false).unwrap();
+    assert_eq!((chip8.registers[0], chip8.registers[1], chip8.addr_register), (0, 0, 0), "The registers should be reset");
+    assert!(chip8.reload_rom(&[0x00, 0xE0, 0x12, 0x02], false).is_ok(), "A ROM starting with 00E0 should be reloaded");
+}
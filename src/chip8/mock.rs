@@ -0,0 +1,158 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Screen and keypad doubles for the tests, and emulator builder for the
+// unit tests
+//************************************************************************
+
+use crate::chip8::{Display, KeyInput};
+use crate::chip8::constants::CHIP8_PIXEL_COUNT;
+#[cfg(test)]
+use crate::chip8::{Chip8, Platform, Quirks};
+#[cfg(test)]
+use crate::chip8::constants::CHIP8_MEMORY_START;
+#[cfg(test)]
+use crate::chip8::types::{Address, OpCode, Register};
+
+// Keeps the last drawn pixels
+pub struct MockScreen {
+    pub pixels: [u8; CHIP8_PIXEL_COUNT],
+    pub draws : usize
+}
+
+impl MockScreen {
+    pub fn new() -> Self {
+        MockScreen {
+            pixels: [0; CHIP8_PIXEL_COUNT],
+            draws : 0
+        }
+    }
+}
+
+impl Default for MockScreen {
+    fn default() -> Self {
+        MockScreen::new()
+    }
+}
+
+impl Display for MockScreen {
+    fn draw(&mut self, pixels: [u8; CHIP8_PIXEL_COUNT]) {
+        self.pixels = pixels;
+        self.draws += 1;
+    }
+}
+
+// The keys are pressed by the tests, FX0A gets the lowest pressed key
+#[derive(Default)]
+pub struct MockInput {
+    pub keys: [bool; 16]
+}
+
+impl MockInput {
+    pub fn new() -> Self {
+        MockInput {
+            keys: [false; 16]
+        }
+    }
+
+    // Keys held during the whole test
+    pub fn pressed(keys: &[u8]) -> Self {
+        let mut input = MockInput::new();
+        for key in keys {
+            input.keys[*key as usize & 0x0F] = true;
+        }
+        input
+    }
+}
+
+impl KeyInput for MockInput {
+    fn is_key_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize & 0x0F]
+    }
+
+    fn get_key(&mut self) -> Option<u8> {
+        self.keys.iter().position(|pressed| *pressed).map(|key| key as u8)
+    }
+}
+
+// Emulator in a given state, with the program counter at 0x200, for the tests of the instructions
+#[cfg(test)]
+pub struct Chip8Builder {
+    chip8: Chip8<MockScreen, MockInput>
+}
+
+#[cfg(test)]
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Chip8Builder::new()
+    }
+}
+
+#[cfg(test)]
+impl Chip8Builder {
+    pub fn new() -> Self {
+        let mut chip8 = Chip8::new(MockScreen::new(), MockInput::new());
+        chip8.load_fontset();
+        chip8.program_counter = CHIP8_MEMORY_START;
+
+        Chip8Builder { chip8 }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.chip8.quirks = quirks;
+        self
+    }
+
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.chip8.platform = platform;
+        self
+    }
+
+    pub fn register(mut self, register: Register, value: u8) -> Self {
+        self.chip8.registers[register] = value;
+        self
+    }
+
+    pub fn addr_register(mut self, address: Address) -> Self {
+        self.chip8.addr_register = address;
+        self
+    }
+
+    pub fn memory(mut self, address: Address, bytes: &[u8]) -> Self {
+        let address = address as usize;
+        self.chip8.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    // Push return addresses, the last one on the top of the stack
+    pub fn stack(mut self, addresses: &[Address]) -> Self {
+        for address in addresses {
+            self.chip8.stack[self.chip8.stack_ptr] = *address;
+            self.chip8.stack_ptr += 1;
+        }
+        self
+    }
+
+    pub fn rpl_flags(mut self, flags: &[u8]) -> Self {
+        self.chip8.set_rpl_flags(flags);
+        self
+    }
+
+    pub fn key(mut self, key: u8) -> Self {
+        self.chip8.key_input.keys[key as usize] = true;
+        self
+    }
+
+    // Execute a single instruction
+    pub fn run(self, opcode: OpCode) -> Chip8<MockScreen, MockInput> {
+        self.try_run(opcode).unwrap()
+    }
+
+    // Execute a single instruction, which can fail
+    pub fn try_run(self, opcode: OpCode) -> Result<Chip8<MockScreen, MockInput>, String> {
+        let mut chip8 = self.chip8;
+        chip8.execute_opcode(opcode)?;
+        Ok(chip8)
+    }
+}
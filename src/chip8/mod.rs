@@ -5,35 +5,70 @@
 // CHIP-8 emulator
 //************************************************************************
 
-use std::time::{SystemTime, Duration};
-
+use std::collections::BTreeSet;
+use std::time::Instant;
+
+pub use crate::chip8::cartridge::Cartridge;
+pub use crate::chip8::code_writes::CodeWrite;
+pub use crate::chip8::constants::{CHIP8_CPU_CLOCK_SPEED, CHIP8_FRAME_DURATION};
+pub use crate::chip8::database::{Database, RomInfo, parse_color, sha1_hex};
+pub use crate::chip8::disassembler::{describe_next, disassemble};
 pub use crate::chip8::display::Display;
 pub use crate::chip8::input::KeyInput;
+pub use crate::chip8::memory::{decode_rom, read_rom_file};
+pub use crate::chip8::quirks::{Platform, Quirks};
+pub use crate::chip8::state::MachineState;
 
+use crate::chip8::code_writes::CodeWriteTracker;
 use crate::chip8::constants::*;
+use crate::chip8::coverage::Coverage;
+use crate::chip8::profiler::Profiler;
 use crate::chip8::timer::Timer;
 use crate::chip8::types::{Address, OpCode};
 use std::thread::sleep;
 
+mod cartridge;
+mod code_writes;
 mod constants;
+mod coverage;
+mod database;
+mod disassembler;
 mod display;
 mod input;
 mod memory;
+pub mod mock;
+mod octo;
 mod opcodes;
+mod profiler;
+mod quirks;
+mod state;
 mod timer;
 mod types;
 
 // CHIP-8 structure
 pub struct Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
     // CPU
-    registers            : [u8; CHIP8_REGISTER_COUNT],
-    addr_register        : Address,
-    program_counter      : Address,
-    clock_speed          : u16,
-    last_instruction_time: Option<SystemTime>,
+    registers      : [u8; CHIP8_REGISTER_COUNT],
+    addr_register  : Address,
+    program_counter: Address,
+    clock_speed    : u32,
+
+    // Number of instructions executed since the creation of the emulator
+    instruction_count: u64,
+
+    // Platform
+    platform: Platform,
+    quirks  : Quirks,
+    rom_info: Option<RomInfo>,
 
-    // Memory
-    memory: [u8; CHIP8_MEMORY_SIZE],
+    // Memory, and size and SHA-1 of the loaded ROM
+    memory  : [u8; CHIP8_MEMORY_SIZE],
+    rom_size: usize,
+    rom_hash: String,
+
+    // RPL user flags of FX75 and FX85, kept by the resets, and whether FX75 saved them since the last check
+    rpl_flags      : [u8; CHIP8_RPL_FLAG_COUNT],
+    rpl_flags_saved: bool,
 
     // Stack
     stack    : [Address; CHIP8_STACK_COUNT],
@@ -48,7 +83,22 @@ pub struct Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
     sound_timer: Timer,
 
     // Input
-    pub key_input: Input
+    pub key_input: Input,
+
+    // Debugger: the frame stops before executing an instruction at a breakpoint,
+    // the breakpoint is ignored once when running again from it
+    breakpoints      : BTreeSet<Address>,
+    breakpoint_hit   : Option<Address>,
+    ignore_breakpoint: bool,
+
+    // Writes into the code already executed, the frame optionally stops after the instruction writing it
+    code_writes        : Option<CodeWriteTracker>,
+    break_on_code_write: bool,
+    code_write_hit     : Option<CodeWrite>,
+
+    // Profiler, counting the instructions, and coverage of the memory accesses, when enabled
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>
 }
 
 impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
@@ -60,10 +110,22 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
             addr_register  : 0,
             program_counter: 0,
             clock_speed    : CHIP8_CPU_CLOCK_SPEED,
-            last_instruction_time: None,
+
+            instruction_count: 0,
+
+            // Platform
+            platform: Platform::Chip8,
+            quirks  : Platform::Chip8.default_quirks(),
+            rom_info: None,
 
             // Memory
-            memory: [0; CHIP8_MEMORY_SIZE],
+            memory  : [0; CHIP8_MEMORY_SIZE],
+            rom_size: 0,
+            rom_hash: String::new(),
+
+            // RPL user flags
+            rpl_flags      : [0; CHIP8_RPL_FLAG_COUNT],
+            rpl_flags_saved: false,
 
             // Stack
             stack    : [0; CHIP8_STACK_COUNT],
@@ -78,7 +140,21 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
             sound_timer: Timer::new(),
 
             // Input
-            key_input
+            key_input,
+
+            // Debugger
+            breakpoints      : BTreeSet::new(),
+            breakpoint_hit   : None,
+            ignore_breakpoint: false,
+
+            // Self-modifying code detection
+            code_writes        : None,
+            break_on_code_write: false,
+            code_write_hit     : None,
+
+            // Profiler and coverage
+            profiler: None,
+            coverage: None
         }
     }
 
@@ -88,8 +164,8 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
         self.load_fontset();
 
         // Check if the program is loaded
-        if self.memory[0x0200] == 0 {
-            return Err(format!("No rom loaded!"));
+        if self.rom_size == 0 {
+            return Err("No rom loaded!".to_string());
         }
 
         // Set the PC at 0x200
@@ -98,57 +174,308 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
         Ok(())
     }
 
-    // Make a step
+    // Reset the CPU, the stack, the timers and the screen, a hard reset also clears the memory and
+    // the platform settings. The ROM has to be loaded again before calling init
+    pub fn reset(&mut self, hard: bool) {
+        self.registers       = [0; CHIP8_REGISTER_COUNT];
+        self.addr_register   = 0;
+        self.program_counter = 0;
+        self.stack     = [0; CHIP8_STACK_COUNT];
+        self.stack_ptr = 0;
+        self.delay_timer = Timer::new();
+        self.sound_timer = Timer::new();
+
+        self.gfx = [0; CHIP8_PIXEL_COUNT];
+        self.screen.draw(self.gfx);
+
+        // The profile goes on, from an empty call stack
+        if let Some(profiler) = &mut self.profiler {
+            profiler.clear_call_stack();
+        }
+
+        if hard {
+            self.memory      = [0; CHIP8_MEMORY_SIZE];
+            self.rom_size    = 0;
+            self.rom_hash    = String::new();
+            self.clock_speed = CHIP8_CPU_CLOCK_SPEED;
+            self.platform    = Platform::Chip8;
+            self.quirks      = Platform::Chip8.default_quirks();
+            self.rom_info    = None;
+
+            if let Some(code_writes) = &mut self.code_writes {
+                code_writes.clear_executed();
+            }
+        }
+    }
+
+    // Make a step: execute the next instruction
     pub fn step(&mut self) -> Result<(), String> {
         // Check if the program is loaded
-        if self.memory[0x0200] == 0 {
-            return Err(format!("No rom loaded!"));
+        if self.rom_size == 0 {
+            return Err("No rom loaded!".to_string());
+        }
+
+        // The last byte of the memory cannot start an instruction
+        if self.program_counter as usize + 1 >= self.platform.memory_size() {
+            return Err(format!("The program counter {:03X} is out of the memory!", self.program_counter));
         }
 
         // Get the opcode
         let op_code: OpCode = ((self.memory[self.program_counter as usize] as OpCode) << 8) +
             (self.memory[self.program_counter as usize + 1] as OpCode);
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(self.program_counter, op_code);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_execution(self.program_counter as usize);
+        }
+        if let Some(code_writes) = &mut self.code_writes {
+            code_writes.record_execution(self.program_counter as usize);
+        }
+
         // Execute the opcode
-        self.execute_opcode(op_code);
+        self.execute_opcode(op_code)?;
+        self.instruction_count += 1;
+
+        Ok(())
+    }
 
-        // Emulate CPU speed
-        self.emulate_cpu_speed();
+    // Run the instructions of a 60 Hz frame as fast as possible, the caller paces the frames
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        for _ in 0..self.instructions_per_frame() {
+            let ignore_breakpoint = std::mem::take(&mut self.ignore_breakpoint);
+            if !ignore_breakpoint && self.breakpoints.contains(&self.program_counter) {
+                self.breakpoint_hit = Some(self.program_counter);
+                self.ignore_breakpoint = true;
+                return Ok(());
+            }
+
+            self.step()?;
+            if self.code_write_hit.is_some() {
+                return Ok(());
+            }
+        }
 
-        // Update timers
-        self.delay_timer.update();
-        self.sound_timer.update();
+        // Timers count down once per frame
+        self.delay_timer.tick();
+        self.sound_timer.tick();
 
         Ok(())
     }
 
+    // Number of instructions executed in a 60 Hz frame
+    pub fn instructions_per_frame(&self) -> u32 {
+        (self.clock_speed / CHIP8_TIMER_CLOCK_SPEED as u32).max(1)
+    }
+
     // Main loop
     pub fn init_and_loop(&mut self) -> Result<(), String> {
         // Init
         self.init()?;
 
-        // Loop
+        // Loop, emulating the clock speed one frame at a time
         loop {
-            self.step()?;
+            let frame_start = Instant::now();
+            self.run_frame()?;
+
+            if let Some(remaining) = CHIP8_FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+                sleep(remaining);
+            }
         }
     }
 
-    // Emulate clock speed, should be call after each instruction
-    fn emulate_cpu_speed(&mut self) {
-        // Get the current system time
-        let time_now = SystemTime::now();
+    // Set the CPU clock speed, in Hz
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        self.clock_speed = clock_speed.max(1);
+    }
 
-        // If there is an instruction before, simulate latency
-        if self.last_instruction_time.is_some() {
-            let duration = self.last_instruction_time.unwrap().elapsed().unwrap();
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
 
-            // We have to sleep
-            if duration < Duration::from_micros(1_000_000 / self.clock_speed as u64) {
-                sleep(Duration::from_micros(1_000_000 / self.clock_speed as u64) - duration);
-            }
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // Run as a platform, with its usual quirks
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks   = platform.default_quirks();
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    // Set or clear a breakpoint, returns true when it is set
+    pub fn toggle_breakpoint(&mut self, address: Address) -> bool {
+        if self.breakpoints.remove(&address) {
+            return false;
         }
 
-        // Set the new last instruction time
-        self.last_instruction_time = Some(time_now);
+        self.breakpoints.insert(address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<Address> {
+        &self.breakpoints
+    }
+
+    // Address of the breakpoint which stopped the last frame, if any
+    pub fn take_breakpoint_hit(&mut self) -> Option<Address> {
+        self.breakpoint_hit.take()
+    }
+
+    // Start detecting the writes into the code already executed, optionally stopping the frames after them
+    pub fn enable_code_write_tracking(&mut self, break_on_code_write: bool) {
+        self.code_writes = Some(CodeWriteTracker::new());
+        self.break_on_code_write = break_on_code_write;
+    }
+
+    // Write into the code which stopped the last frame, if any
+    pub fn take_code_write_hit(&mut self) -> Option<CodeWrite> {
+        self.code_write_hit.take()
+    }
+
+    // Summary and log of the writes into the code, when the detection is enabled
+    pub fn code_write_report(&self) -> Option<String> {
+        self.code_writes.as_ref().map(CodeWriteTracker::report)
+    }
+
+    // Start counting the executed instructions
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    // Profiler report and collapsed stacks, when the profiler is enabled
+    pub fn profile(&self) -> Option<(String, String)> {
+        self.profiler.as_ref().map(|profiler| (profiler.report(&self.memory), profiler.collapsed_stacks()))
+    }
+
+    // Start tracking the bytes executed, read and written
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
     }
-}
\ No newline at end of file
+
+    // Annotated disassembly of the loaded ROM, when the coverage is enabled
+    pub fn coverage_report(&self) -> Option<String> {
+        self.coverage.as_ref().map(|coverage| coverage.report(&self.memory, self.rom_size))
+    }
+
+    // Size of the loaded program, in bytes
+    pub fn rom_size(&self) -> usize {
+        self.rom_size
+    }
+
+    // SHA-1 of the loaded ROM, the key of its RPL user flags
+    pub fn rom_hash(&self) -> &str {
+        &self.rom_hash
+    }
+
+    pub fn rpl_flags(&self) -> &[u8] {
+        &self.rpl_flags
+    }
+
+    // Restore the RPL user flags saved by a previous run of the ROM
+    pub fn set_rpl_flags(&mut self, flags: &[u8]) {
+        let count = flags.len().min(CHIP8_RPL_FLAG_COUNT);
+        self.rpl_flags = [0; CHIP8_RPL_FLAG_COUNT];
+        self.rpl_flags[..count].copy_from_slice(&flags[..count]);
+    }
+
+    // Whether FX75 saved the RPL user flags since the last call, to persist them
+    pub fn take_rpl_flags_saved(&mut self) -> bool {
+        std::mem::take(&mut self.rpl_flags_saved)
+    }
+
+    // Database entry of the loaded ROM, if known
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+}
+
+// Unit tests
+#[test]
+fn test_breakpoint() {
+    //--------------------------------------------------------------------
+    // Setup: Loop on LD V0, 01 / JP 200 with a breakpoint on the jump
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(mock::MockScreen::new(), mock::MockInput::new());
+    chip8.load_rom(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+    chip8.init().unwrap();
+    assert!(chip8.toggle_breakpoint(0x202), "The breakpoint should be set");
+
+    //--------------------------------------------------------------------
+    // Execute: Run 2 frames
+    //--------------------------------------------------------------------
+    chip8.run_frame().unwrap();
+    let first_hit = (chip8.take_breakpoint_hit(), chip8.instruction_count());
+    chip8.run_frame().unwrap();
+    let second_hit = (chip8.take_breakpoint_hit(), chip8.instruction_count());
+
+    //--------------------------------------------------------------------
+    // Verify: Each frame stops at the breakpoint, after going through it once
+    //--------------------------------------------------------------------
+    assert_eq!(first_hit, (Some(0x202), 1), "The first frame should stop before the jump");
+    assert_eq!(second_hit, (Some(0x202), 3), "The second frame should run from the breakpoint");
+    assert!(!chip8.toggle_breakpoint(0x202), "The breakpoint should be cleared");
+}
+
+#[test]
+fn test_step_errors() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Malformed programs stop with an error
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(mock::MockScreen::new(), mock::MockInput::new());
+    assert_eq!(chip8.init(), Err("No rom loaded!".to_string()), "The emulator should not start without a ROM");
+
+    chip8.load_rom(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+    assert_eq!(chip8.init().and_then(|_| chip8.step()), Ok(()), "A ROM starting with 00E0 should run");
+
+    chip8.load_rom(&[0x60, 0x00, 0x00, 0xEE]).unwrap();
+    chip8.init().unwrap();
+    chip8.step().unwrap();
+    assert_eq!(chip8.step(), Err("Stack underflow: return at 202 with an empty stack!".to_string()),
+               "00EE should fail with an empty stack");
+
+    chip8.load_rom(&[0x22, 0x00]).unwrap();
+    chip8.init().unwrap();
+    for _ in 0..CHIP8_STACK_COUNT {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.step(), Err("Stack overflow: call at 200 with 16 nested calls!".to_string()),
+               "2NNN should fail with a full stack");
+
+    chip8.load_rom(&[0xFF, 0xFF]).unwrap();
+    chip8.init().unwrap();
+    assert_eq!(chip8.step(), Err("Unknown opcode FFFF at 200!".to_string()), "FFFF should not be an instruction");
+
+    chip8.load_rom(&[0x1F, 0xFF]).unwrap();
+    chip8.init().unwrap();
+    chip8.step().unwrap();
+    assert_eq!(chip8.step(), Err("The program counter FFF is out of the memory!".to_string()),
+               "The last byte should not be executed");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The accesses through I wrap around the memory
+    //--------------------------------------------------------------------
+    chip8.load_rom(&[0xAF, 0xFF, 0xF1, 0x55]).unwrap();
+    chip8.init().unwrap();
+    chip8.registers[..2].copy_from_slice(&[1, 2]);
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert_eq!((chip8.memory[0xFFF], chip8.memory[0x000]), (1, 2), "FX55 should wrap to the start of the memory");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The program counter wraps around the 64 KB of XO-CHIP
+    //--------------------------------------------------------------------
+    chip8.load_rom(&[0x12, 0x00]).unwrap();
+    chip8.set_platform(Platform::XoChip);
+    chip8.init().unwrap();
+    chip8.memory[0xFFFC..].copy_from_slice(&[0x60, 0x00, 0x30, 0x00]);
+    chip8.program_counter = 0xFFFC;
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter, 0xFFFE, "6XNN should go to the last instruction");
+    chip8.step().unwrap();
+    assert_eq!(chip8.program_counter, 0x0002, "A skip at the end of the memory should wrap around");
+}
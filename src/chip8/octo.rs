@@ -0,0 +1,1068 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Octo assembler, for the programs of the Octo cartridges
+//
+// The program starts at 0x200 with a jump to the main label, dropped when
+// main is the first label. The names used before their definition are
+// resolved once the whole source is assembled, as Octo does. The :calc
+// expressions have no precedence and are evaluated from right to left.
+//************************************************************************
+
+use std::collections::HashMap;
+
+use crate::chip8::constants::{CHIP8_MEMORY_SIZE, CHIP8_MEMORY_START};
+
+// Keys of the Octo keyboard layout
+const OCTO_KEYS: [(&str, u8); 16] = [
+    ("OCTO_KEY_1", 0x1), ("OCTO_KEY_2", 0x2), ("OCTO_KEY_3", 0x3), ("OCTO_KEY_4", 0xC),
+    ("OCTO_KEY_Q", 0x4), ("OCTO_KEY_W", 0x5), ("OCTO_KEY_E", 0x6), ("OCTO_KEY_R", 0xD),
+    ("OCTO_KEY_A", 0x7), ("OCTO_KEY_S", 0x8), ("OCTO_KEY_D", 0x9), ("OCTO_KEY_F", 0xE),
+    ("OCTO_KEY_Z", 0xA), ("OCTO_KEY_X", 0x0), ("OCTO_KEY_C", 0xB), ("OCTO_KEY_V", 0xF)
+];
+
+// Words of the language, which cannot be names
+const RESERVED: &[&str] = &[
+    ":", ":next", ":unpack", ":breakpoint", ":proto", ":monitor", ":alias", ":const", ":calc", ":byte", ":org",
+    ":macro", ":stringmode", ":assert", ":call", ";", "return", "clear", "bcd", "save", "load", "saveflags",
+    "loadflags", "sprite", "jump", "jump0", "native", "scroll-down", "scroll-up", "scroll-right", "scroll-left",
+    "exit", "lores", "hires", "plane", "audio", "pitch", "delay", "buzzer", "i", "if", "then", "begin", "else",
+    "end", "loop", "while", "again", "key", "-key", "hex", "bighex", "long", "random", ":=", "+=", "-=", "=-",
+    "|=", "&=", "^=", ">>=", "<<=", "==", "!=", "<", ">", "<=", ">=", "-", "{", "}", "(", ")"
+];
+
+// Operators of the :calc expressions
+const UNARY_OPERATORS : &[&str] = &["-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil",
+                                    "floor", "@", "strlen"];
+const BINARY_OPERATORS: &[&str] = &["-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=",
+                                    "==", "!=", ">=", ">"];
+
+// Macros expanded before giving up on a recursive one
+const MAX_EXPANSIONS: usize = 65536;
+
+#[derive(Clone)]
+struct Token {
+    text  : String,
+    quoted: bool,
+    line  : usize
+}
+
+// Bytes of an instruction referring to a name, patched once it is defined
+enum Reference {
+    Address12,
+    Address16,
+    Unpack(Option<u8>)
+}
+
+struct Fixup {
+    name     : String,
+    reference: Reference,
+    address  : usize,
+    line     : usize
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body      : Vec<Token>,
+    calls     : usize
+}
+
+struct StringMode {
+    alphabet: Vec<char>,
+    body    : Vec<Token>
+}
+
+struct Loop {
+    start : usize,
+    whiles: Vec<usize>,
+    line  : usize
+}
+
+struct Assembler {
+    // Tokens left, the next one last
+    tokens    : Vec<Token>,
+    line      : usize,
+    expansions: usize,
+
+    // Program from 0x200, and address of the next byte
+    rom      : Vec<u8>,
+    here     : usize,
+    main_jump: bool,
+
+    // Names
+    labels      : HashMap<String, usize>,
+    constants   : HashMap<String, f64>,
+    aliases     : HashMap<String, u16>,
+    macros      : HashMap<String, Macro>,
+    string_modes: HashMap<String, StringMode>,
+    fixups      : Vec<Fixup>,
+
+    // Jumps of the open if ... begin and else blocks, and open loops
+    branches: Vec<(usize, usize)>,
+    loops   : Vec<Loop>
+}
+
+// Assemble an Octo source into a program loaded at 0x200
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::new(tokenize(source)?);
+    while let Some(token) = assembler.tokens.pop() {
+        assembler.line = token.line;
+        assembler.statement(token).map_err(|e| format!("line {}: {}", assembler.line, e))?;
+    }
+
+    assembler.finish()
+}
+
+// Words, numbers and quoted strings, without the comments
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '#' {
+                break;
+            } else if c == '"' {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('t') => '\t',
+                            Some('v') => '\x0B',
+                            Some('0') => '\0',
+                            Some(c @ ('\\' | '"')) => c,
+                            _ => return Err(format!("line {}: Invalid escape in a string", index + 1))
+                        }),
+                        Some(c) => text.push(c),
+                        None => return Err(format!("line {}: Unterminated string", index + 1))
+                    }
+                }
+                tokens.push(Token { text, quoted: true, line: index + 1 });
+            } else {
+                let mut text = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    text.push(c);
+                }
+                tokens.push(Token { text, quoted: false, line: index + 1 });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Decimal, hexadecimal (0x) or binary (0b) integer, optionally negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+// Register vX, whatever the case of the hex digit
+fn parse_register(text: &str) -> Option<u16> {
+    let digit = text.strip_prefix('v').or(text.strip_prefix('V'))?;
+    if digit.len() == 1 { u16::from_str_radix(digit, 16).ok() } else { None }
+}
+
+fn is_name(text: &str) -> bool {
+    !RESERVED.contains(&text) && !text.starts_with(':') && parse_register(text).is_none() && parse_number(text).is_none()
+}
+
+fn unary(operator: &str, value: f64) -> f64 {
+    match operator {
+        "-"     => -value,
+        "~"     => !(value as i64) as f64,
+        "!"     => if value == 0.0 { 1.0 } else { 0.0 },
+        "sin"   => value.sin(),
+        "cos"   => value.cos(),
+        "tan"   => value.tan(),
+        "exp"   => value.exp(),
+        "log"   => value.ln(),
+        "abs"   => value.abs(),
+        "sqrt"  => value.sqrt(),
+        "sign"  => if value == 0.0 { 0.0 } else { value.signum() },
+        "ceil"  => value.ceil(),
+        _       => value.floor()
+    }
+}
+
+fn binary(operator: &str, left: f64, right: f64) -> f64 {
+    let (integer_left, integer_right) = (left as i64, right as i64);
+    match operator {
+        "-"   => left - right,
+        "+"   => left + right,
+        "*"   => left * right,
+        "/"   => left / right,
+        "%"   => left % right,
+        "&"   => (integer_left & integer_right) as f64,
+        "|"   => (integer_left | integer_right) as f64,
+        "^"   => (integer_left ^ integer_right) as f64,
+        "<<"  => integer_left.wrapping_shl(integer_right as u32) as f64,
+        ">>"  => integer_left.wrapping_shr(integer_right as u32) as f64,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "<"   => (left < right) as u8 as f64,
+        "<="  => (left <= right) as u8 as f64,
+        "=="  => (left == right) as u8 as f64,
+        "!="  => (left != right) as u8 as f64,
+        ">="  => (left >= right) as u8 as f64,
+        _     => (left > right) as u8 as f64
+    }
+}
+
+impl Assembler {
+    fn new(mut tokens: Vec<Token>) -> Self {
+        tokens.reverse();
+        Assembler {
+            tokens,
+            line        : 1,
+            expansions  : 0,
+            rom         : vec![0x00, 0x00],
+            here        : CHIP8_MEMORY_START as usize + 2,
+            main_jump   : true,
+            labels      : HashMap::new(),
+            constants   : OCTO_KEYS.iter().map(|&(name, key)| (name.to_string(), key as f64)).collect(),
+            aliases     : HashMap::new(),
+            macros      : HashMap::new(),
+            string_modes: HashMap::new(),
+            fixups      : Vec::new(),
+            branches    : Vec::new(),
+            loops       : Vec::new()
+        }
+    }
+
+    // Check the blocks and resolve the names used before their definition
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        if let Some(open) = self.loops.last() {
+            return Err(format!("line {}: 'loop' without 'again'", open.line));
+        }
+        if let Some(&(_, line)) = self.branches.last() {
+            return Err(format!("line {}: 'begin' without 'end'", line));
+        }
+
+        if self.main_jump {
+            let main = *self.labels.get("main").ok_or("The program has no main label")?;
+            self.patch_jump(CHIP8_MEMORY_START as usize, main)?;
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let value = match (self.labels.get(&fixup.name), self.constants.get(&fixup.name)) {
+                (Some(&address), _) => address as i64,
+                (None, Some(&value)) => value as i64,
+                (None, None) => return Err(format!("line {}: Undefined name '{}'", fixup.line, fixup.name))
+            };
+            self.resolve(&fixup.reference, fixup.address, value).map_err(|e| format!("line {}: {}", fixup.line, e))?;
+        }
+
+        Ok(self.rom)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), String> {
+        if token.quoted {
+            return Err(format!("Unexpected string \"{}\"", token.text));
+        }
+        if let Some(x) = self.register(&token) {
+            return self.register_statement(x);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                // main first: the program starts with it instead of jumping to it
+                let name = self.name()?;
+                if name == "main" && self.main_jump && self.here == CHIP8_MEMORY_START as usize + 2 && self.rom.len() == 2 {
+                    self.main_jump = false;
+                    self.rom.clear();
+                    self.here = CHIP8_MEMORY_START as usize;
+                }
+                self.define_label(name, self.here)
+            },
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)
+            },
+            ":unpack" => {
+                let nybble = if self.next_is("long") {
+                    self.next()?;
+                    None
+                } else {
+                    Some(self.value(0, 0xF)? as u8)
+                };
+                let address = self.here;
+                self.emit(&[0x60, nybble.unwrap_or(0) << 4, 0x61, 0x00])?;
+                self.refer(Reference::Unpack(nybble), address)
+            },
+            ":breakpoint" | ":proto" => self.next().map(|_| ()),
+            ":monitor" => self.next().and_then(|_| self.next()).map(|_| ()),
+            ":alias" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let register = self.register(&token).ok_or(format!("'{}' is not a register", token.text))?;
+                self.aliases.insert(name, register);
+                Ok(())
+            },
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value(i64::MIN, i64::MAX)?;
+                self.constants.insert(name, value as f64);
+                Ok(())
+            },
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                Ok(())
+            },
+            ":byte" => {
+                let value = if self.next_is("{") { self.calc()? as i64 } else { self.value(i64::MIN, i64::MAX)? };
+                if !(-128..=255).contains(&value) {
+                    return Err(format!("The byte {} does not fit between -128 and 255", value));
+                }
+                self.emit(&[value as u8])
+            },
+            ":org" => {
+                self.here = self.value(CHIP8_MEMORY_START as i64, CHIP8_MEMORY_SIZE as i64 - 1)? as usize;
+                Ok(())
+            },
+            ":macro" => {
+                let name = self.name()?;
+                let mut parameters = Vec::new();
+                while !self.next_is("{") {
+                    parameters.push(self.name()?);
+                }
+                let body = self.block()?;
+                self.macros.insert(name, Macro { parameters, body, calls: 0 });
+                Ok(())
+            },
+            ":stringmode" => {
+                let name = self.name()?;
+                let alphabet = self.string()?.chars().collect();
+                let body = self.block()?;
+                self.string_modes.insert(name, StringMode { alphabet, body });
+                Ok(())
+            },
+            ":assert" => {
+                let message = if self.tokens.last().is_some_and(|token| token.quoted) { Some(self.string()?) } else { None };
+                if self.calc()? == 0.0 {
+                    return Err(message.unwrap_or("Assertion failed".to_string()));
+                }
+                Ok(())
+            },
+            ":call" => self.address_instruction(0x2000),
+            ";" | "return" => self.instruction(0x00EE),
+            "clear" => self.instruction(0x00E0),
+            "bcd" => {
+                let x = self.register_operand()?;
+                self.instruction(0xF033 | x << 8)
+            },
+            "save" | "load" => {
+                let x = self.register_operand()?;
+                let save = token.text == "save";
+                if self.next_is("-") {
+                    self.next()?;
+                    let y = self.register_operand()?;
+                    self.instruction(0x5000 | x << 8 | y << 4 | if save { 0x2 } else { 0x3 })
+                } else {
+                    self.instruction(if save { 0xF055 } else { 0xF065 } | x << 8)
+                }
+            },
+            "saveflags" => {
+                let x = self.register_operand()?;
+                self.instruction(0xF075 | x << 8)
+            },
+            "loadflags" => {
+                let x = self.register_operand()?;
+                self.instruction(0xF085 | x << 8)
+            },
+            "sprite" => {
+                let x = self.register_operand()?;
+                let y = self.register_operand()?;
+                let n = self.value(0, 0xF)? as u16;
+                self.instruction(0xD000 | x << 8 | y << 4 | n)
+            },
+            "jump" => self.address_instruction(0x1000),
+            "jump0" => self.address_instruction(0xB000),
+            "native" => self.address_instruction(0x0000),
+            "scroll-down" => {
+                let n = self.value(0, 0xF)? as u16;
+                self.instruction(0x00C0 | n)
+            },
+            "scroll-up" => {
+                let n = self.value(0, 0xF)? as u16;
+                self.instruction(0x00D0 | n)
+            },
+            "scroll-right" => self.instruction(0x00FB),
+            "scroll-left" => self.instruction(0x00FC),
+            "exit" => self.instruction(0x00FD),
+            "lores" => self.instruction(0x00FE),
+            "hires" => self.instruction(0x00FF),
+            "plane" => {
+                let n = self.value(0, 0xF)? as u16;
+                self.instruction(0xF001 | n << 8)
+            },
+            "audio" => self.instruction(0xF002),
+            "pitch" | "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register_operand()?;
+                let opcode = match token.text.as_str() {
+                    "pitch" => 0xF03A,
+                    "delay" => 0xF015,
+                    _       => 0xF018
+                };
+                self.instruction(opcode | x << 8)
+            },
+            "i" => self.i_statement(),
+            "if" => {
+                let index = if self.peek(1).is_some_and(|token| token.text == "key" || token.text == "-key") { 2 } else { 3 };
+                match self.peek(index).map(|token| token.text.as_str()) {
+                    Some("then") => {
+                        self.conditional(false)?;
+                        self.expect("then")
+                    },
+                    Some("begin") => {
+                        // The negated condition skips the jump to the else or end
+                        self.conditional(true)?;
+                        self.expect("begin")?;
+                        self.branches.push((self.here, self.line));
+                        self.emit(&[0x00, 0x00])
+                    },
+                    _ => Err("Expected 'then' or 'begin' after the condition".to_string())
+                }
+            },
+            "else" => {
+                let (begin, line) = self.branches.pop().ok_or("'else' without 'if ... begin'")?;
+                let jump = self.here;
+                self.emit(&[0x00, 0x00])?;
+                self.patch_jump(begin, self.here)?;
+                self.branches.push((jump, line));
+                Ok(())
+            },
+            "end" => {
+                let (jump, _) = self.branches.pop().ok_or("'end' without 'if ... begin'")?;
+                self.patch_jump(jump, self.here)
+            },
+            "loop" => {
+                self.loops.push(Loop { start: self.here, whiles: Vec::new(), line: self.line });
+                Ok(())
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err("'while' outside of a loop".to_string());
+                }
+
+                // The negated condition skips the jump out of the loop
+                self.conditional(true)?;
+                let jump = self.here;
+                if let Some(open) = self.loops.last_mut() {
+                    open.whiles.push(jump);
+                }
+                self.emit(&[0x00, 0x00])
+            },
+            "again" => {
+                let open = self.loops.pop().ok_or("'again' without 'loop'")?;
+                let jump = self.here;
+                self.emit(&[0x00, 0x00])?;
+                self.patch_jump(jump, open.start)?;
+                for exit in open.whiles {
+                    self.patch_jump(exit, self.here)?;
+                }
+                Ok(())
+            },
+            _ => self.call_or_data(token)
+        }
+    }
+
+    // vX := ..., vX += ..., and the other operations on a register
+    fn register_statement(&mut self, x: u16) -> Result<(), String> {
+        let operator = self.next()?;
+        let y = self.tokens.last().and_then(|token| self.register(token));
+        if y.is_some() {
+            self.next()?;
+        }
+
+        match (operator.text.as_str(), y) {
+            (":=", Some(y))   => self.instruction(0x8000 | x << 8 | y << 4),
+            ("|=", Some(y))   => self.instruction(0x8001 | x << 8 | y << 4),
+            ("&=", Some(y))   => self.instruction(0x8002 | x << 8 | y << 4),
+            ("^=", Some(y))   => self.instruction(0x8003 | x << 8 | y << 4),
+            ("+=", Some(y))   => self.instruction(0x8004 | x << 8 | y << 4),
+            ("-=", Some(y))   => self.instruction(0x8005 | x << 8 | y << 4),
+            (">>=", Some(y))  => self.instruction(0x8006 | x << 8 | y << 4),
+            ("=-", Some(y))   => self.instruction(0x8007 | x << 8 | y << 4),
+            ("<<=", Some(y))  => self.instruction(0x800E | x << 8 | y << 4),
+            (":=", None) if self.next_is("random") => {
+                self.next()?;
+                let n = self.byte()?;
+                self.instruction(0xC000 | x << 8 | n as u16)
+            },
+            (":=", None) if self.next_is("key") => {
+                self.next()?;
+                self.instruction(0xF00A | x << 8)
+            },
+            (":=", None) if self.next_is("delay") => {
+                self.next()?;
+                self.instruction(0xF007 | x << 8)
+            },
+            (":=", None) => {
+                let n = self.byte()?;
+                self.instruction(0x6000 | x << 8 | n as u16)
+            },
+            ("+=", None) => {
+                let n = self.byte()?;
+                self.instruction(0x7000 | x << 8 | n as u16)
+            },
+            ("-=", None) => {
+                let n = self.byte()?;
+                self.instruction(0x7000 | x << 8 | n.wrapping_neg() as u16)
+            },
+            _ => Err(format!("Unexpected '{}' after a register", operator.text))
+        }
+    }
+
+    // i := ..., i += vX
+    fn i_statement(&mut self) -> Result<(), String> {
+        let operator = self.next()?;
+        match operator.text.as_str() {
+            ":=" if self.next_is("hex") => {
+                self.next()?;
+                let x = self.register_operand()?;
+                self.instruction(0xF029 | x << 8)
+            },
+            ":=" if self.next_is("bighex") => {
+                self.next()?;
+                let x = self.register_operand()?;
+                self.instruction(0xF030 | x << 8)
+            },
+            ":=" if self.next_is("long") => {
+                self.next()?;
+                let address = self.here + 2;
+                self.emit(&[0xF0, 0x00, 0x00, 0x00])?;
+                self.refer(Reference::Address16, address)
+            },
+            ":=" => self.address_instruction(0xA000),
+            "+=" => {
+                let x = self.register_operand()?;
+                self.instruction(0xF01E | x << 8)
+            },
+            _ => Err(format!("Unexpected '{}' after i", operator.text))
+        }
+    }
+
+    // Instructions skipping the next one when the condition is false, or true when negated
+    fn conditional(&mut self, negated: bool) -> Result<(), String> {
+        let x = self.register_operand()?;
+        let operator = self.next()?;
+        let operator = match (negated, operator.text.as_str()) {
+            (false, operator) => operator,
+            (true, "==")      => "!=",
+            (true, "!=")      => "==",
+            (true, "key")     => "-key",
+            (true, "-key")    => "key",
+            (true, ">")       => "<=",
+            (true, "<")       => ">=",
+            (true, ">=")      => "<",
+            (true, "<=")      => ">",
+            (true, operator)  => operator
+        };
+
+        match operator {
+            "key" => self.instruction(0xE0A1 | x << 8),
+            "-key" => self.instruction(0xE09E | x << 8),
+            "==" | "!=" => {
+                let equal = operator == "==";
+                match self.tokens.last().and_then(|token| self.register(token)) {
+                    Some(y) => {
+                        self.next()?;
+                        self.instruction(if equal { 0x9000 } else { 0x5000 } | x << 8 | y << 4)
+                    },
+                    None => {
+                        let n = self.byte()?;
+                        self.instruction(if equal { 0x4000 } else { 0x3000 } | x << 8 | n as u16)
+                    }
+                }
+            },
+            ">" | "<" | ">=" | "<=" => {
+                // VF gets the operand, then the difference, and its flag tells the result
+                match self.tokens.last().and_then(|token| self.register(token)) {
+                    Some(y) => {
+                        self.next()?;
+                        self.instruction(0x8F00 | y << 4)?;
+                    },
+                    None => {
+                        let n = self.byte()?;
+                        self.instruction(0x6F00 | n as u16)?;
+                    }
+                }
+                let subtract = if operator == ">" || operator == "<=" { 0x5 } else { 0x7 };
+                self.instruction(0x8F00 | x << 4 | subtract)?;
+                self.instruction(if operator == ">" || operator == "<" { 0x3F01 } else { 0x4F01 })
+            },
+            _ => Err(format!("Unexpected '{}' in a condition", operator))
+        }
+    }
+
+    // Macro and string mode expansions, bytes, and calls
+    fn call_or_data(&mut self, token: Token) -> Result<(), String> {
+        if let Some(definition) = self.macros.get_mut(&token.text) {
+            definition.calls += 1;
+            let (parameters, body, calls) = (definition.parameters.clone(), definition.body.clone(), definition.calls - 1);
+            let mut arguments = HashMap::from([("CALLS".to_string(), calls.to_string())]);
+            for parameter in parameters {
+                let argument = self.next()?;
+                arguments.insert(parameter, argument.text);
+            }
+
+            let expansion = body.into_iter()
+                .map(|body_token| match arguments.get(&body_token.text) {
+                    Some(argument) if !body_token.quoted => Token { text: argument.clone(), quoted: false, line: token.line },
+                    _ => Token { line: token.line, ..body_token }
+                })
+                .collect();
+            return self.expand(expansion);
+        }
+
+        if let Some(mode) = self.string_modes.get(&token.text) {
+            let (alphabet, body) = (mode.alphabet.clone(), mode.body.clone());
+            let text = self.string()?;
+            let mut expansion = Vec::new();
+            for (index, c) in text.chars().enumerate() {
+                let value = alphabet.iter().position(|&letter| letter == c)
+                    .ok_or(format!("The string mode '{}' has no character '{}'", token.text, c))?;
+                let arguments = HashMap::from([("VALUE", value as u32), ("CHAR", c as u32), ("INDEX", index as u32)]);
+                expansion.extend(body.iter().map(|body_token| match arguments.get(body_token.text.as_str()) {
+                    Some(argument) if !body_token.quoted => Token { text: argument.to_string(), quoted: false, line: token.line },
+                    _ => Token { line: token.line, ..body_token.clone() }
+                }));
+            }
+            return self.expand(expansion);
+        }
+
+        if let Some(value) = parse_number(&token.text).or(self.constants.get(&token.text).map(|&value| value as i64)) {
+            if !(-128..=255).contains(&value) {
+                return Err(format!("The byte {} does not fit between -128 and 255", value));
+            }
+            return self.emit(&[value as u8]);
+        }
+
+        if token.text.starts_with(':') && !RESERVED.contains(&token.text.as_str()) {
+            return Err(format!("Unknown directive '{}'", token.text));
+        }
+        if !is_name(&token.text) {
+            return Err(format!("Unexpected '{}'", token.text));
+        }
+
+        // A label, maybe defined later, is called
+        self.tokens.push(token);
+        self.address_instruction(0x2000)
+    }
+
+    // ------- Tokens -------
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.pop().ok_or("Unexpected end of the source")?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn peek(&self, index: usize) -> Option<&Token> {
+        self.tokens.len().checked_sub(index + 1).map(|index| &self.tokens[index])
+    }
+
+    fn next_is(&self, text: &str) -> bool {
+        self.peek(0).is_some_and(|token| !token.quoted && token.text == text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token.quoted || token.text != text {
+            return Err(format!("Expected '{}' instead of '{}'", text, token.text));
+        }
+
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if !token.quoted {
+            return Err(format!("Expected a string instead of '{}'", token.text));
+        }
+
+        Ok(token.text)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if token.quoted || !is_name(&token.text) {
+            return Err(format!("'{}' cannot be a name", token.text));
+        }
+
+        Ok(token.text)
+    }
+
+    fn register(&self, token: &Token) -> Option<u16> {
+        if token.quoted {
+            return None;
+        }
+
+        self.aliases.get(&token.text).copied().or(parse_register(&token.text))
+    }
+
+    fn register_operand(&mut self) -> Result<u16, String> {
+        let token = self.next()?;
+        self.register(&token).ok_or(format!("'{}' is not a register", token.text))
+    }
+
+    // Tokens of a { ... } block, with the nested blocks
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        self.expect("{")?;
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next().map_err(|_| "Unterminated block".to_string())?;
+            match (token.quoted, token.text.as_str()) {
+                (false, "{") => depth += 1,
+                (false, "}") if depth == 0 => return Ok(body),
+                (false, "}") => depth -= 1,
+                _ => ()
+            }
+            body.push(token);
+        }
+    }
+
+    fn expand(&mut self, expansion: Vec<Token>) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err("Too many macro expansions, the macro may be recursive".to_string());
+        }
+
+        self.tokens.extend(expansion.into_iter().rev());
+        Ok(())
+    }
+
+    // ------- Values -------
+    // Number, constant or label, None for a name not defined yet
+    fn lookup(&self, token: &Token) -> Result<Option<i64>, String> {
+        if token.quoted {
+            return Err(format!("Expected a value instead of \"{}\"", token.text));
+        }
+
+        if let Some(value) = parse_number(&token.text) {
+            Ok(Some(value))
+        } else if let Some(&value) = self.constants.get(&token.text) {
+            Ok(Some(value as i64))
+        } else if let Some(&address) = self.labels.get(&token.text) {
+            Ok(Some(address as i64))
+        } else if is_name(&token.text) {
+            Ok(None)
+        } else {
+            Err(format!("Expected a value instead of '{}'", token.text))
+        }
+    }
+
+    fn value(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        let token = self.next()?;
+        let value = self.lookup(&token)?.ok_or(format!("Undefined name '{}'", token.text))?;
+        if !(min..=max).contains(&value) {
+            return Err(format!("The value {} does not fit between {} and {}", value, min, max));
+        }
+
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        self.value(-128, 255).map(|value| value as u8)
+    }
+
+    // Value of a :calc expression, between braces
+    fn calc(&mut self) -> Result<f64, String> {
+        self.expect("{")?;
+        let value = self.expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn expression(&mut self) -> Result<f64, String> {
+        let left = self.term()?;
+        match self.peek(0) {
+            Some(token) if !token.quoted && BINARY_OPERATORS.contains(&token.text.as_str()) => {
+                let operator = self.next()?;
+                let right = self.expression()?;
+                Ok(binary(&operator.text, left, right))
+            },
+            _ => Ok(left)
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            _ if token.quoted => Err(format!("Unexpected string \"{}\"", token.text)),
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            },
+            "strlen" => Ok(self.string()?.chars().count() as f64),
+            "@" => {
+                let address = self.term()? as i64;
+                let index = address - CHIP8_MEMORY_START as i64;
+                let byte = if index < 0 { None } else { self.rom.get(index as usize) };
+                Ok(byte.copied().unwrap_or(0) as f64)
+            },
+            operator if UNARY_OPERATORS.contains(&operator) => {
+                let value = self.term()?;
+                Ok(unary(operator, value))
+            },
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            text if text.starts_with(|c: char| c.is_ascii_digit()) && parse_number(text).is_none() => {
+                text.parse().map_err(|_| format!("Invalid number '{}'", text))
+            },
+            _ => self.lookup(&token)?.map(|value| value as f64).ok_or(format!("Undefined name '{}'", token.text))
+        }
+    }
+
+    // ------- Bytes -------
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        for &byte in bytes {
+            if self.here >= CHIP8_MEMORY_SIZE {
+                return Err(format!("The program does not fit in the {} bytes of memory", CHIP8_MEMORY_SIZE));
+            }
+
+            let index = self.here - CHIP8_MEMORY_START as usize;
+            if index >= self.rom.len() {
+                self.rom.resize(index + 1, 0);
+            }
+            self.rom[index] = byte;
+            self.here += 1;
+        }
+
+        Ok(())
+    }
+
+    fn instruction(&mut self, opcode: u16) -> Result<(), String> {
+        self.emit(&opcode.to_be_bytes())
+    }
+
+    // Instruction with a 12 bits address, maybe defined later
+    fn address_instruction(&mut self, opcode: u16) -> Result<(), String> {
+        let address = self.here;
+        self.instruction(opcode)?;
+        self.refer(Reference::Address12, address)
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) {
+            return Err(format!("The label '{}' is already defined", name));
+        }
+
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    // Patch the reference to the next token now if it is known, once defined otherwise
+    fn refer(&mut self, reference: Reference, address: usize) -> Result<(), String> {
+        let token = self.next()?;
+        match self.lookup(&token)? {
+            Some(value) => self.resolve(&reference, address, value),
+            None => {
+                self.fixups.push(Fixup { name: token.text, reference, address, line: token.line });
+                Ok(())
+            }
+        }
+    }
+
+    fn resolve(&mut self, reference: &Reference, address: usize, value: i64) -> Result<(), String> {
+        let max = match reference {
+            Reference::Address12 | Reference::Unpack(Some(_)) => 0xFFF,
+            Reference::Address16 | Reference::Unpack(None)    => 0xFFFF
+        };
+        if !(0..=max).contains(&value) {
+            return Err(format!("The address {} does not fit between 0 and {}", value, max));
+        }
+
+        let index = address - CHIP8_MEMORY_START as usize;
+        let [high, low] = (value as u16).to_be_bytes();
+        match reference {
+            Reference::Address12 => {
+                self.rom[index] = self.rom[index] & 0xF0 | high;
+                self.rom[index + 1] = low;
+            },
+            Reference::Address16 => {
+                self.rom[index] = high;
+                self.rom[index + 1] = low;
+            },
+            Reference::Unpack(nybble) => {
+                self.rom[index + 1] = nybble.unwrap_or(0) << 4 | high;
+                self.rom[index + 3] = low;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn patch_jump(&mut self, address: usize, target: usize) -> Result<(), String> {
+        if target > 0xFFF {
+            return Err(format!("The jump to {:04X} does not fit in 12 bits", target));
+        }
+
+        let index = address - CHIP8_MEMORY_START as usize;
+        self.rom[index] = 0x10 | (target >> 8) as u8;
+        self.rom[index + 1] = target as u8;
+        Ok(())
+    }
+}
+
+// Unit tests
+
+#[test]
+fn test_assemble_statements() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Each statement gives its instructions
+    //--------------------------------------------------------------------
+    let cases: [(&str, &[u8]); 7] = [
+        (": main clear return ;", &[0x00, 0xE0, 0x00, 0xEE, 0x00, 0xEE]),
+        (": main v1 := 0x2A v2 := v1 v3 := random 0x0F v4 := key v5 := delay",
+         &[0x61, 0x2A, 0x82, 0x10, 0xC3, 0x0F, 0xF4, 0x0A, 0xF5, 0x07]),
+        (": main v1 += 3 v1 -= 1 v1 += v2 v1 -= v2 v1 =- v2 v1 |= v2 v1 &= v2 v1 ^= v2 v1 >>= v2 v1 <<= VF",
+         &[0x71, 0x03, 0x71, 0xFF, 0x81, 0x24, 0x81, 0x25, 0x81, 0x27, 0x81, 0x21, 0x81, 0x22, 0x81, 0x23, 0x81, 0x26,
+           0x81, 0xFE]),
+        (": main i := 0x300 i += v1 i := hex v2 i := bighex v3 i := long 0x1234 delay := v4 buzzer := v5 pitch := v6",
+         &[0xA3, 0x00, 0xF1, 0x1E, 0xF2, 0x29, 0xF3, 0x30, 0xF0, 0x00, 0x12, 0x34, 0xF4, 0x15, 0xF5, 0x18, 0xF6, 0x3A]),
+        (": main bcd v1 save v2 load v3 save v1 - v4 load v4 - v1 saveflags v5 loadflags v6",
+         &[0xF1, 0x33, 0xF2, 0x55, 0xF3, 0x65, 0x51, 0x42, 0x54, 0x13, 0xF5, 0x75, 0xF6, 0x85]),
+        (": main sprite v1 v2 5 scroll-down 4 scroll-up 2 scroll-right scroll-left exit lores hires plane 3 audio \
+          native 0x123 jump0 0x300 :call 0x400",
+         &[0xD1, 0x25, 0x00, 0xC4, 0x00, 0xD2, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFD, 0x00, 0xFE, 0x00, 0xFF, 0xF3, 0x01,
+           0xF0, 0x02, 0x01, 0x23, 0xB3, 0x00, 0x24, 0x00]),
+        (": main if v1 > v2 then v0 := 1 if v1 <= 5 then v0 := 2 if v3 -key then v0 := 3",
+         &[0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x01, 0x60, 0x01, 0x6F, 0x05, 0x8F, 0x15, 0x4F, 0x01, 0x60, 0x02, 0xE3, 0x9E,
+           0x60, 0x03])
+    ];
+    for (source, program) in cases {
+        assert_eq!(assemble(source).as_deref(), Ok(program), "{} should be assembled", source);
+    }
+}
+
+#[test]
+fn test_assemble_control_flow() {
+    //--------------------------------------------------------------------
+    // Setup: A loop with conditions and an if ... else block
+    //--------------------------------------------------------------------
+    let source = "
+        : main
+          loop
+            v0 += 1
+            if v0 == 10 then v1 := 0
+            if v0 != v1 begin
+              v2 := 1
+            else
+              v2 := 2
+            end
+            while v0 < 20
+            if v1 key then clear
+          again";
+
+    //--------------------------------------------------------------------
+    // Execute: Assemble it
+    //--------------------------------------------------------------------
+    let program = assemble(source);
+
+    //--------------------------------------------------------------------
+    // Verify: The blocks jump over each other, and while jumps out of the loop
+    //--------------------------------------------------------------------
+    assert_eq!(program, Ok(vec![0x70, 0x01, 0x40, 0x0A, 0x61, 0x00, 0x90, 0x10, 0x12, 0x0E, 0x62, 0x01, 0x12, 0x10, 0x62, 0x02,
+                                0x6F, 0x14, 0x8F, 0x07, 0x4F, 0x01, 0x12, 0x1E, 0xE1, 0xA1, 0x00, 0xE0, 0x12, 0x00]),
+               "The control flow should be assembled");
+}
+
+#[test]
+fn test_assemble_names() {
+    //--------------------------------------------------------------------
+    // Setup: Aliases, constants, data before main, and forward references
+    //--------------------------------------------------------------------
+    let source = "
+        :alias counter v3
+        :const SPEED 4
+        :calc DOUBLE { SPEED * 2 + 1 }   # Right to left: 4 * 3
+        :macro set-pair A B { v0 := A v1 := B }
+        :macro count { :byte CALLS }
+        :stringmode text \"ABC\" { :byte { VALUE * 2 } }
+
+        : sprite-data
+          0b11110000 0x90
+        : main
+          counter := SPEED
+          counter += DOUBLE
+          i := sprite-data
+          sprite counter counter 2
+          draw
+          jump end-label
+        : draw
+          :unpack 0xA table
+          i := long table
+          return
+        :next patched
+          v0 := 0
+        : end-label
+          jump end-label
+        : table
+          :byte { HERE >> 8 }
+          :byte DOUBLE
+          set-pair 1 OCTO_KEY_W
+          text \"CAB\"
+          count count
+          :assert \"next\" { patched == 0x21B }";
+
+    //--------------------------------------------------------------------
+    // Execute: Assemble it
+    //--------------------------------------------------------------------
+    let program = assemble(source);
+
+    //--------------------------------------------------------------------
+    // Verify: The program jumps to main, and the names are resolved
+    //--------------------------------------------------------------------
+    assert_eq!(program, Ok(vec![0x12, 0x04, 0xF0, 0x90, 0x63, 0x04, 0x73, 0x0C, 0xA2, 0x02, 0xD3, 0x32, 0x22, 0x10, 0x12, 0x1C,
+                                0x60, 0xA2, 0x61, 0x1E, 0xF0, 0x00, 0x02, 0x1E, 0x00, 0xEE, 0x60, 0x00, 0x12, 0x1C,
+                                0x02, 0x0C, 0x60, 0x01, 0x61, 0x05, 0x04, 0x00, 0x02, 0x00, 0x01]),
+               "The names should be resolved");
+}
+
+#[test]
+fn test_assemble_errors() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: The invalid sources are rejected with their line
+    //--------------------------------------------------------------------
+    let cases = [
+        ("v0 := 5", "The program has no main label"),
+        (": main\n  v0 := 256", "line 2: The value 256 does not fit between -128 and 255"),
+        (": main\n  jump nowhere", "line 2: Undefined name 'nowhere'"),
+        (": main\n  loop\n  v0 += 1", "line 2: 'loop' without 'again'"),
+        (": main\n  if v0 == 1 v1 := 2", "line 2: Expected 'then' or 'begin' after the condition"),
+        (": main\n: main", "line 2: The label 'main' is already defined"),
+        (":macro m { m }\n: main m", "line 2: Too many macro expansions, the macro may be recursive"),
+        (": main\n  :include \"file.8o\"", "line 2: Unknown directive ':include'"),
+        (": main\n  v0 := \"text", "line 2: Unterminated string")
+    ];
+    for (source, error) in cases {
+        assert_eq!(assemble(source), Err(error.to_string()), "{:?} should be rejected", source);
+    }
+}
@@ -6,21 +6,21 @@
 //************************************************************************
 
 use crate::chip8::{Chip8, KeyInput};
-use crate::chip8::constants::{CHIP8_PROGRAM_COUNTER_INC, CHIP8_REGISTER_VF};
+use crate::chip8::constants::{CHIP8_DRAW_PLANE, CHIP8_PROGRAM_COUNTER_INC, CHIP8_REGISTER_VF, CHIP8_STACK_COUNT};
 use crate::chip8::display::Display;
 use crate::chip8::types::{OpCode, Address, Register};
 
 impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
-    pub(crate) fn execute_opcode(&mut self, opcode: OpCode) {
+    pub(crate) fn execute_opcode(&mut self, opcode: OpCode) -> Result<(), String> {
         match opcode {
             0x00E0          => { self.clear_screen(); }
-            0x00EE          => { self.return_from_subroutine(); }
+            0x00EE          => { self.return_from_subroutine()?; }
             0x0000..=0x0FFF => { self.call_rca1802_program(opcode); }
             0x1000..=0x1FFF => { self.goto(opcode); }
-            0x2000..=0x2FFF => { self.call_subroutine(opcode); }
+            0x2000..=0x2FFF => { self.call_subroutine(opcode)?; }
             0x3000..=0x3FFF => { self.if_eq_const_skip(opcode); }
             0x4000..=0x4FFF => { self.if_neq_const_skip(opcode); }
-            0x5000..=0x5FF0 => { self.if_eq_reg_skip(opcode); }
+            0x5000..=0x5FF0 if opcode & 0x000F == 0 => { self.if_eq_reg_skip(opcode); }
             0x6000..=0x6FFF => { self.set_reg(opcode); }
             0x7000..=0x7FFF => { self.add_const_to_reg(opcode); }
 
@@ -34,7 +34,7 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
             0x8000..=0x8FFF if opcode & 0x000F == 0x7 => { self.sub_reg0_to_reg1(opcode); }
             0x8000..=0x8FFF if opcode & 0x000F == 0xE => { self.shift_left_reg(opcode); }
 
-            0x9000..=0x9FF0 => { self.if_neq_reg_skip(opcode); }
+            0x9000..=0x9FF0 if opcode & 0x000F == 0 => { self.if_neq_reg_skip(opcode); }
             0xA000..=0xAFFF => { self.set_addr(opcode); }
             0xB000..=0xBFFF => { self.jump_to_addr(opcode); }
             0xC000..=0xCFFF => { self.rand(opcode); }
@@ -52,9 +52,18 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
             0xF033..=0xFF33 if opcode & 0x00FF == 0x33 => { self.set_bcd(opcode); }
             0xF055..=0xFF55 if opcode & 0x00FF == 0x55 => { self.reg_dump(opcode); }
             0xF065..=0xFF65 if opcode & 0x00FF == 0x65 => { self.reg_load(opcode); }
+            0xF075..=0xFF75 if opcode & 0x00FF == 0x75 => { self.save_rpl_flags(opcode)?; }
+            0xF085..=0xFF85 if opcode & 0x00FF == 0x85 => { self.load_rpl_flags(opcode)?; }
 
-            _ => { panic!("Unknown OPCODE {:X}!", opcode); }
+            _ => { return Err(format!("Unknown opcode {:04X} at {:03X}!", opcode, self.program_counter)); }
         }
+
+        Ok(())
+    }
+
+    // Move to the next instruction, the program counter wraps around at the end of the 64 KB of memory
+    fn next_instruction(&mut self) {
+        self.program_counter = self.program_counter.wrapping_add(CHIP8_PROGRAM_COUNTER_INC);
     }
 
     // 0NNN
@@ -62,17 +71,24 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
         //unimplemented!()
     }
 
-    // 00E0
+    // 00E0, the cleared screen is drawn at once as a ROM can clear it and wait without drawing
     fn clear_screen(&mut self) {
         self.gfx = [0; 2048];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.screen.draw(self.gfx);
+        self.next_instruction();
     }
 
     // 00EE
-    fn return_from_subroutine(&mut self) {
+    fn return_from_subroutine(&mut self) -> Result<(), String> {
+        if self.stack_ptr == 0 {
+            return Err(format!("Stack underflow: return at {:03X} with an empty stack!", self.program_counter));
+        }
+
         self.stack_ptr -= 1;
         self.program_counter = self.stack[self.stack_ptr];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
+
+        Ok(())
     }
 
     // 1NNN
@@ -81,11 +97,17 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
     }
 
     // 2NNN
-    fn call_subroutine(&mut self, op_code: OpCode) {
+    fn call_subroutine(&mut self, op_code: OpCode) -> Result<(), String> {
+        if self.stack_ptr == CHIP8_STACK_COUNT {
+            return Err(format!("Stack overflow: call at {:03X} with {} nested calls!", self.program_counter, CHIP8_STACK_COUNT));
+        }
+
         let address = get_addr_from_opcode(op_code);
         self.stack[self.stack_ptr] = self.program_counter;
         self.stack_ptr += 1;
         self.program_counter = address;
+
+        Ok(())
     }
 
     // 3XNN
@@ -94,10 +116,10 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
 
         // Jump
         if self.registers[register] == value {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 4XNN
@@ -106,29 +128,29 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
 
         // Jump
         if self.registers[register] != value {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 5XY0
     fn if_eq_reg_skip(&mut self, op_code: OpCode) {
-        let (register_1, register_2) = get_reg_and_value_from_opcode(op_code);
+        let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
 
         // Jump
-        if self.registers[register_1 as usize] == self.registers[register_2 as usize] {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        if self.registers[register_1] == self.registers[register_2] {
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 6XNN
     fn set_reg(&mut self, op_code: OpCode) {
         let (register, value) = get_reg_and_value_from_opcode(op_code);
         self.registers[register] = value;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 7XNN
@@ -136,202 +158,239 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
         let (register, value) = get_reg_and_value_from_opcode(op_code);
 
         self.registers[register] = self.registers[register].wrapping_add(value);
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 8XY0
     fn copy_reg(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
         self.registers[register_1] = self.registers[register_2];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 8XY1
     fn or_reg(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
         self.registers[register_1] |= self.registers[register_2];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+
+        if self.quirks.vf_reset {
+            self.registers[CHIP8_REGISTER_VF] = 0;
+        }
+
+        self.next_instruction();
     }
 
     // 8XY2
     fn and_reg(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
         self.registers[register_1] &= self.registers[register_2];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+
+        if self.quirks.vf_reset {
+            self.registers[CHIP8_REGISTER_VF] = 0;
+        }
+
+        self.next_instruction();
     }
 
     // 8XY3
     fn xor_reg(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
         self.registers[register_1] ^= self.registers[register_2];
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+
+        if self.quirks.vf_reset {
+            self.registers[CHIP8_REGISTER_VF] = 0;
+        }
+
+        self.next_instruction();
     }
 
     // 8XY4
     fn add_reg_to_reg(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
 
-        self.registers[CHIP8_REGISTER_VF] = (self.registers[register_2] > (0xFF - self.registers[register_1])) as u8;
-        self.registers[register_1] = self.registers[register_1].wrapping_add(self.registers[register_2]);
+        // VF is set last, the flag wins when VF is the destination
+        let (result, carry) = self.registers[register_1].overflowing_add(self.registers[register_2]);
+        self.registers[register_1] = result;
+        self.registers[CHIP8_REGISTER_VF] = carry as u8;
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 8XY5
     fn sub_reg1_to_reg0(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
 
-        self.registers[CHIP8_REGISTER_VF] = (self.registers[register_1] >= self.registers[register_2]) as u8;
-        self.registers[register_1] = self.registers[register_1].wrapping_sub(self.registers[register_2]);
+        let (result, borrow) = self.registers[register_1].overflowing_sub(self.registers[register_2]);
+        self.registers[register_1] = result;
+        self.registers[CHIP8_REGISTER_VF] = !borrow as u8;
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 8XY6
     fn shift_right_reg(&mut self, op_code: OpCode) {
-        let register = get_reg_from_opcode(op_code);
+        let (register, register_2) = get_reg_and_reg_from_opcode(op_code);
 
-        // Store the less significant bit in VF
-        self.registers[CHIP8_REGISTER_VF] = self.registers[register] & 0x01;
+        // Without the shift quirk, VY is shifted into VX
+        if !self.quirks.shift {
+            self.registers[register] = self.registers[register_2];
+        }
 
-        // Shift right
+        // Shift right, and store the less significant bit in VF
+        let flag = self.registers[register] & 0x01;
         self.registers[register] >>= 1;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.registers[CHIP8_REGISTER_VF] = flag;
+        self.next_instruction();
     }
 
     // 8XY7
     fn sub_reg0_to_reg1(&mut self, op_code: OpCode) {
         let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
 
-        self.registers[CHIP8_REGISTER_VF] = (self.registers[register_2] >= self.registers[register_1]) as u8;
-        self.registers[register_1] = self.registers[register_2].wrapping_sub(self.registers[register_1]);
+        let (result, borrow) = self.registers[register_2].overflowing_sub(self.registers[register_1]);
+        self.registers[register_1] = result;
+        self.registers[CHIP8_REGISTER_VF] = !borrow as u8;
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // 8XYE
     fn shift_left_reg(&mut self, op_code: OpCode) {
-        let register = get_reg_from_opcode(op_code);
+        let (register, register_2) = get_reg_and_reg_from_opcode(op_code);
 
-        // Store the most significant bit in VF
-        self.registers[CHIP8_REGISTER_VF] = self.registers[register] & 0x80;
+        // Without the shift quirk, VY is shifted into VX
+        if !self.quirks.shift {
+            self.registers[register] = self.registers[register_2];
+        }
 
-        // Shift right
+        // Shift left, and store the most significant bit in VF
+        let flag = self.registers[register] >> 7;
         self.registers[register] <<= 1;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.registers[CHIP8_REGISTER_VF] = flag;
+        self.next_instruction();
     }
 
     // 9XY0
     fn if_neq_reg_skip(&mut self, op_code: OpCode) {
-        let (register_1, register_2) = get_reg_and_value_from_opcode(op_code);
+        let (register_1, register_2) = get_reg_and_reg_from_opcode(op_code);
 
         // Jump
-        if self.registers[register_1 as usize] != self.registers[register_2 as usize] {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        if self.registers[register_1] != self.registers[register_2] {
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // ANNN
     fn set_addr(&mut self, op_code: OpCode) {
         self.addr_register = get_addr_from_opcode(op_code);
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // BNNN
     fn jump_to_addr(&mut self, op_code: OpCode) {
-        self.program_counter = self.registers[0] as u16 + get_addr_from_opcode(op_code);
+        // With the jump quirk, BXNN jumps to XNN + VX
+        let register = if self.quirks.jump { get_reg_from_opcode(op_code) } else { 0 };
+        self.program_counter = self.registers[register] as u16 + get_addr_from_opcode(op_code);
     }
 
     // CXNN
     fn rand(&mut self, op_code: OpCode) {
         let (register, value) = get_reg_and_value_from_opcode(op_code);
         self.registers[register] = rand::random::<u8>() & value;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // DXYN
     fn draw(&mut self, op_code: OpCode) {
         let (register_1, register_2, height) = get_reg_and_reg_and_value_from_opcode(op_code);
 
-        // Get x and y values from registers
-        let x = self.registers[register_1];
-        let y = self.registers[register_2];
+        // Get x and y values from registers, the start position always wraps
+        let x = self.registers[register_1] as usize % 64;
+        let y = self.registers[register_2] as usize % 32;
 
         self.registers[CHIP8_REGISTER_VF] = 0;
 
-        for col in 0..height {
-            let pixel = self.memory[self.addr_register as usize + col as usize];
+        for col in 0..height as usize {
+            let pixel = self.read_memory(self.addr_register as usize + col);
             for row in 0..8 {
+                // Sprites either wrap around or are clipped at the screen edges
+                if self.quirks.clip && (x + row >= 64 || y + col >= 32) {
+                    continue;
+                }
+
                 // If the pixel is 1
                 if (pixel & (0x80 >> row)) != 0 {
-                    let index_pixel_memory = (x as usize + row as usize + ((y as usize + col as usize) * 64usize)) % 2048;
+                    let index_pixel_memory = (x + row) % 64 + ((y + col) % 32) * 64;
 
                     // If the pixel in memory == 1, then collision -> Vf = 1
-                    if self.gfx[index_pixel_memory] == 0xFF {
+                    if self.gfx[index_pixel_memory] & CHIP8_DRAW_PLANE != 0 {
                         self.registers[CHIP8_REGISTER_VF] = 0x01;
                     }
 
-                    self.gfx[index_pixel_memory] ^= 0xFF;
+                    self.gfx[index_pixel_memory] ^= CHIP8_DRAW_PLANE;
                 }
             }
         }
 
         self.screen.draw(self.gfx);
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // EX9E
     fn if_eq_key_skip(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
 
-        if self.key_input.is_key_pressed(self.registers[register]) {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        // Only the low nibble of VX selects the key
+        if self.key_input.is_key_pressed(self.registers[register] & 0x0F) {
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // EXA1
     fn if_neq_key_skip(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
 
-        if !self.key_input.is_key_pressed(self.registers[register]) {
-            self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        if !self.key_input.is_key_pressed(self.registers[register] & 0x0F) {
+            self.next_instruction();
         }
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX07
     fn get_delay_timer_value(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         self.registers[register] = self.delay_timer.get_delay();
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX0A
     fn get_key_value(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
 
-        self.registers[register] = self.key_input.get_key();
-
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        // Execute this instruction again until a key is pressed
+        if let Some(key) = self.key_input.get_key() {
+            self.registers[register] = key;
+            self.next_instruction();
+        }
     }
 
     // FX15
     fn set_delay_timer(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         self.delay_timer.start(self.registers[register]);
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX18
     fn set_sound_timer(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         self.sound_timer.start(self.registers[register]);
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX1E
@@ -344,68 +403,119 @@ impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput
 
         // VF set to 1 if overflow, otherwise 0
         self.registers[CHIP8_REGISTER_VF] = (self.addr_register < old_addr_value) as u8;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX29
     fn set_sprite_to_addr(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         self.addr_register = 0x0050 + self.registers[register] as u16 * 5;
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX33
     fn set_bcd(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
 
-        self.memory[self.addr_register as usize]     =  self.registers[register] / 100;
-        self.memory[self.addr_register as usize + 1] = (self.registers[register] / 10)  % 10;
-        self.memory[self.addr_register as usize + 2] = (self.registers[register] % 100) % 10;
+        self.write_memory(self.addr_register as usize,      self.registers[register] / 100);
+        self.write_memory(self.addr_register as usize + 1, (self.registers[register] / 10)  % 10);
+        self.write_memory(self.addr_register as usize + 2, (self.registers[register] % 100) % 10);
 
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+        self.next_instruction();
     }
 
     // FX55
     fn reg_dump(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         for x in 0 ..= register {
-            self.memory[self.addr_register as usize + x] = self.registers[x];
+            self.write_memory(self.addr_register as usize + x, self.registers[x]);
+        }
+
+        // The original interpreter leaves I after the last register
+        if self.quirks.memory_increment {
+            self.addr_register = self.addr_register.wrapping_add(register as u16 + 1);
         }
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+
+        self.next_instruction();
     }
 
     // FX65
     fn reg_load(&mut self, op_code: OpCode) {
         let register = get_reg_from_opcode(op_code);
         for x in 0 ..= register {
-            self.registers[x] = self.memory[self.addr_register as usize + x];
+            self.registers[x] = self.read_memory(self.addr_register as usize + x);
+        }
+
+        // The original interpreter leaves I after the last register
+        if self.quirks.memory_increment {
+            self.addr_register = self.addr_register.wrapping_add(register as u16 + 1);
         }
-        self.program_counter += CHIP8_PROGRAM_COUNTER_INC;
+
+        self.next_instruction();
+    }
+
+    // FX75
+    fn save_rpl_flags(&mut self, op_code: OpCode) -> Result<(), String> {
+        let register = self.get_rpl_flag_reg(op_code)?;
+        self.rpl_flags[..=register].copy_from_slice(&self.registers[..=register]);
+        self.rpl_flags_saved = true;
+
+        self.next_instruction();
+        Ok(())
+    }
+
+    // FX85
+    fn load_rpl_flags(&mut self, op_code: OpCode) -> Result<(), String> {
+        let register = self.get_rpl_flag_reg(op_code)?;
+        self.registers[..=register].copy_from_slice(&self.rpl_flags[..=register]);
+
+        self.next_instruction();
+        Ok(())
+    }
+
+    // Last register of FX75 and FX85, within the RPL user flags of the platform
+    fn get_rpl_flag_reg(&self, op_code: OpCode) -> Result<Register, String> {
+        let register = get_reg_from_opcode(op_code);
+        if register >= self.platform.rpl_flag_count() {
+            return Err(format!("{:04X} at {:03X} uses V{:X}, beyond the {} RPL user flags of {}!", op_code,
+                               self.program_counter, register, self.platform.rpl_flag_count(), self.platform.name()));
+        }
+
+        Ok(register)
     }
 }
 
 // ------- Utils -------
-fn get_addr_from_opcode(opcode: OpCode) -> Address {
+pub(crate) fn get_addr_from_opcode(opcode: OpCode) -> Address {
     opcode & 0x0FFF
 }
 
-fn get_reg_from_opcode(opcode: OpCode) -> Register {
+pub(crate) fn get_reg_from_opcode(opcode: OpCode) -> Register {
     (opcode >> 8 & 0x000F) as Register
 }
 
-fn get_reg_and_value_from_opcode(opcode: OpCode) -> (Register, u8) {
+pub(crate) fn get_reg_and_value_from_opcode(opcode: OpCode) -> (Register, u8) {
     (get_reg_from_opcode(opcode), (opcode & 0x00FF) as u8)
 }
 
-fn get_reg_and_reg_from_opcode(opcode: OpCode) -> (Register, Register) {
+pub(crate) fn get_reg_and_reg_from_opcode(opcode: OpCode) -> (Register, Register) {
     (get_reg_from_opcode(opcode), (opcode >> 4 & 0x000F) as Register)
 }
 
-fn get_reg_and_reg_and_value_from_opcode(opcode: OpCode) -> (Register, Register, u8) {
+pub(crate) fn get_reg_and_reg_and_value_from_opcode(opcode: OpCode) -> (Register, Register, u8) {
     (get_reg_from_opcode(opcode), (opcode >> 4 & 0x000F) as Register, (opcode & 0x000F) as u8)
 }
 
 // Unit tests
+#[cfg(test)]
+use crate::chip8::{Platform, Quirks};
+#[cfg(test)]
+use crate::chip8::mock::{Chip8Builder, MockInput, MockScreen};
+
+// Historical behaviour of this emulator, the tests turn on the quirk they check
+#[cfg(test)]
+const LEGACY_QUIRKS: Quirks = Quirks { shift: true, memory_increment: false, jump: false, vf_reset: false, clip: false };
+
 #[test]
 fn test_get_addr_from_opcode() {
     //--------------------------------------------------------------------
@@ -469,4 +579,320 @@ fn test_get_reg_and_reg_and_value_from_opcode() {
     // Execute and Verify: Registers should be C and 1 and value 9
     //--------------------------------------------------------------------
     assert_eq!(get_reg_and_reg_and_value_from_opcode(opcode), (0x0C, 0x01, 9), "Registers should be C and 1 and value 9");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_clear_screen() {
+    //--------------------------------------------------------------------
+    // Setup: Draw the font sprite of 0
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8Builder::new().addr_register(0x50).run(0xD005);
+
+    //--------------------------------------------------------------------
+    // Execute: 00E0
+    //--------------------------------------------------------------------
+    chip8.execute_opcode(0x00E0).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The screen is cleared and drawn
+    //--------------------------------------------------------------------
+    assert!(chip8.screen.pixels.iter().all(|pixel| *pixel == 0), "The screen should be cleared");
+    assert_eq!(chip8.screen.draws, 2, "The cleared screen should be drawn");
+    assert_eq!(chip8.program_counter, 0x204, "PC should be after the 2 instructions");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: A ROM drawing a sprite, then clearing the screen and waiting shows an empty screen
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8::new(MockScreen::new(), MockInput::new());
+    chip8.load_rom(&[0xA0, 0x50, 0xD0, 0x05, 0x00, 0xE0, 0x12, 0x06]).unwrap();
+    chip8.init().unwrap();
+    chip8.run_frame().unwrap();
+    assert!(chip8.screen.pixels.iter().all(|pixel| *pixel == 0), "The cleared screen should be shown");
+}
+
+#[test]
+fn test_subroutines_and_jumps() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: 2NNN pushes PC, 00EE returns after the call, 1NNN and BNNN jump
+    //--------------------------------------------------------------------
+    let chip8 = Chip8Builder::new().run(0x2ABC);
+    assert_eq!((chip8.program_counter, chip8.stack_ptr, chip8.stack[0]), (0xABC, 1, 0x200), "2NNN should push PC");
+
+    let chip8 = Chip8Builder::new().stack(&[0x300]).run(0x00EE);
+    assert_eq!((chip8.program_counter, chip8.stack_ptr), (0x302, 0), "00EE should return after the call");
+
+    let chip8 = Chip8Builder::new().run(0x1456);
+    assert_eq!(chip8.program_counter, 0x456, "1NNN should jump to NNN");
+
+    let chip8 = Chip8Builder::new().register(0x0, 0x10).register(0x3, 0x20).run(0xB300);
+    assert_eq!(chip8.program_counter, 0x310, "BNNN should jump to NNN + V0");
+
+    let quirks = Quirks { jump: true, ..LEGACY_QUIRKS };
+    let chip8 = Chip8Builder::new().quirks(quirks).register(0x0, 0x10).register(0x3, 0x20).run(0xB300);
+    assert_eq!(chip8.program_counter, 0x320, "With the jump quirk, BXNN should jump to XNN + VX");
+}
+
+#[test]
+fn test_skips() {
+    //--------------------------------------------------------------------
+    // Setup: V1 = V2 = 5, V3 = 6 and the key 5 pressed
+    //--------------------------------------------------------------------
+    let builder = || Chip8Builder::new().register(0x1, 5).register(0x2, 5).register(0x3, 6).key(5);
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: PC skips the next instruction when the condition is true
+    //--------------------------------------------------------------------
+    let cases = [
+        (0x3105, 0x204), (0x3106, 0x202), // 3XNN
+        (0x4105, 0x202), (0x4106, 0x204), // 4XNN
+        (0x5120, 0x204), (0x5130, 0x202), // 5XY0
+        (0x9120, 0x202), (0x9130, 0x204), // 9XY0
+        (0xE19E, 0x204), (0xE39E, 0x202), // EX9E
+        (0xE1A1, 0x202), (0xE3A1, 0x204)  // EXA1
+    ];
+    for (opcode, program_counter) in cases {
+        assert_eq!(builder().run(opcode).program_counter, program_counter, "Wrong skip for {:04X}", opcode);
+    }
+
+    for opcode in [0x5121, 0x912F] {
+        assert_eq!(builder().try_run(opcode).err(), Some(format!("Unknown opcode {:04X} at 200!", opcode)),
+                   "{:04X} should not be a skip", opcode);
+    }
+}
+
+#[test]
+fn test_loads_and_logic() {
+    //--------------------------------------------------------------------
+    // Setup: V1 = 0b1100, V2 = 0b1010 and VF = 7
+    //--------------------------------------------------------------------
+    let builder = |quirks: Quirks| Chip8Builder::new().quirks(quirks).register(0x1, 0b1100).register(0x2, 0b1010).register(0xF, 7);
+    let vf_reset = Quirks { vf_reset: true, ..LEGACY_QUIRKS };
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: VX and VF after the instruction, with and without the VF reset quirk
+    //--------------------------------------------------------------------
+    let cases = [
+        (0x61AB, LEGACY_QUIRKS, 0xAB,   7), // 6XNN
+        (0x71FF, LEGACY_QUIRKS, 0x0B,   7), // 7XNN wraps without carry
+        (0x8120, LEGACY_QUIRKS, 0b1010, 7), // 8XY0
+        (0x8121, LEGACY_QUIRKS, 0b1110, 7), // 8XY1
+        (0x8122, LEGACY_QUIRKS, 0b1000, 7), // 8XY2
+        (0x8123, LEGACY_QUIRKS, 0b0110, 7), // 8XY3
+        (0x8121, vf_reset,          0b1110, 0),
+        (0x8122, vf_reset,          0b1000, 0),
+        (0x8123, vf_reset,          0b0110, 0)
+    ];
+    for (opcode, quirks, vx, vf) in cases {
+        let chip8 = builder(quirks).run(opcode);
+        assert_eq!((chip8.registers[0x1], chip8.registers[0xF]), (vx, vf), "Wrong VX or VF for {:04X}", opcode);
+        assert_eq!(chip8.program_counter, 0x202, "PC should be incremented for {:04X}", opcode);
+    }
+}
+
+#[test]
+fn test_add_and_sub_flags() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: VX and VF for the carry and the borrow of 8XY4, 8XY5 and 8XY7
+    //--------------------------------------------------------------------
+    let cases = [
+        (0x8124, 0xFF, 0x02, 0x01, 1), // Carry
+        (0x8124, 0xFE, 0x01, 0xFF, 0), // No carry
+        (0x8125, 0x01, 0x02, 0xFF, 0), // Borrow
+        (0x8125, 0x02, 0x02, 0x00, 1), // No borrow when equal
+        (0x8127, 0x02, 0x01, 0xFF, 0), // Borrow
+        (0x8127, 0x01, 0x03, 0x02, 1)  // No borrow
+    ];
+    for (opcode, x, y, vx, vf) in cases {
+        let chip8 = Chip8Builder::new().register(0x1, x).register(0x2, y).run(opcode);
+        assert_eq!((chip8.registers[0x1], chip8.registers[0xF]), (vx, vf), "Wrong VX or VF for {:04X} with {:02X}, {:02X}", opcode, x, y);
+    }
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The flag wins when VF is the destination
+    //--------------------------------------------------------------------
+    let chip8 = Chip8Builder::new().register(0xF, 0xFF).register(0x1, 0x02).run(0x8F14);
+    assert_eq!(chip8.registers[0xF], 1, "VF should hold the carry");
+
+    let chip8 = Chip8Builder::new().register(0xF, 0x01).register(0x1, 0x02).run(0x8F15);
+    assert_eq!(chip8.registers[0xF], 0, "VF should hold the borrow");
+}
+
+#[test]
+fn test_shift_flags() {
+    //--------------------------------------------------------------------
+    // Setup: V1 = 0b1000_0001 and V2 = 0b0100_0010
+    //--------------------------------------------------------------------
+    let builder = |quirks: Quirks| Chip8Builder::new().quirks(quirks).register(0x1, 0b1000_0001).register(0x2, 0b0100_0010);
+    let shift_vy = Quirks { shift: false, ..LEGACY_QUIRKS };
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: VX and VF (0 or 1) after shifting VX, or VY without the shift quirk
+    //--------------------------------------------------------------------
+    let cases = [
+        (0x8126, LEGACY_QUIRKS, 0b0100_0000, 1),
+        (0x812E, LEGACY_QUIRKS, 0b0000_0010, 1),
+        (0x8126, shift_vy,          0b0010_0001, 0),
+        (0x812E, shift_vy,          0b1000_0100, 0)
+    ];
+    for (opcode, quirks, vx, vf) in cases {
+        let chip8 = builder(quirks).run(opcode);
+        assert_eq!((chip8.registers[0x1], chip8.registers[0xF]), (vx, vf), "Wrong VX or VF for {:04X}", opcode);
+    }
+
+    let chip8 = Chip8Builder::new().register(0xF, 0b1000_0000).run(0x8FFE);
+    assert_eq!(chip8.registers[0xF], 1, "VF should hold the shifted out bit");
+}
+
+#[test]
+fn test_addr_register() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: ANNN, FX1E with its overflow flag, and FX29
+    //--------------------------------------------------------------------
+    assert_eq!(Chip8Builder::new().run(0xA123).addr_register, 0x123, "ANNN should set I");
+
+    let chip8 = Chip8Builder::new().addr_register(0x0FFF).register(0x1, 0x02).run(0xF11E);
+    assert_eq!((chip8.addr_register, chip8.registers[0xF]), (0x1001, 0), "FX1E should add VX to I");
+
+    let chip8 = Chip8Builder::new().addr_register(0xFFFF).register(0x1, 0x02).run(0xF11E);
+    assert_eq!((chip8.addr_register, chip8.registers[0xF]), (0x0001, 1), "FX1E should set VF when I overflows");
+
+    let chip8 = Chip8Builder::new().register(0x1, 0xA).run(0xF129);
+    assert_eq!(chip8.addr_register, 0x50 + 0xA * 5, "FX29 should point I to the font sprite of VX");
+}
+
+#[test]
+fn test_bcd() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: The hundreds, tens and units of VX are stored at I
+    //--------------------------------------------------------------------
+    for (value, digits) in [(254, [2, 5, 4]), (7, [0, 0, 7]), (40, [0, 4, 0])] {
+        let chip8 = Chip8Builder::new().addr_register(0x300).register(0x1, value).run(0xF133);
+        assert_eq!(chip8.memory[0x300..0x303], digits, "Wrong BCD of {}", value);
+        assert_eq!(chip8.addr_register, 0x300, "I should not change");
+    }
+}
+
+#[test]
+fn test_reg_dump_and_load() {
+    //--------------------------------------------------------------------
+    // Setup: V0-V2 = 1, 2, 3 and 4, 5, 6 at 0x300
+    //--------------------------------------------------------------------
+    let builder = |quirks: Quirks| Chip8Builder::new().quirks(quirks).addr_register(0x300)
+        .register(0x0, 1).register(0x1, 2).register(0x2, 3)
+        .memory(0x300, &[4, 5, 6]);
+    let memory_increment = Quirks { memory_increment: true, ..LEGACY_QUIRKS };
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: FX55 and FX65 store and load V0-VX, I moves with the memory increment quirk
+    //--------------------------------------------------------------------
+    let chip8 = builder(LEGACY_QUIRKS).run(0xF155);
+    assert_eq!((&chip8.memory[0x300..0x303], chip8.addr_register), (&[1, 2, 6][..], 0x300), "FX55 should store V0-V1");
+
+    let chip8 = builder(memory_increment).run(0xF155);
+    assert_eq!(chip8.addr_register, 0x302, "FX55 should move I with the quirk");
+
+    let chip8 = builder(LEGACY_QUIRKS).run(0xF165);
+    assert_eq!((&chip8.registers[0..3], chip8.addr_register), (&[4, 5, 3][..], 0x300), "FX65 should load V0-V1");
+
+    let chip8 = builder(memory_increment).run(0xF265);
+    assert_eq!(chip8.addr_register, 0x303, "FX65 should move I with the quirk");
+}
+
+#[test]
+fn test_rpl_flags() {
+    //--------------------------------------------------------------------
+    // Setup: V0-V2 = 1, 2, 3 and the RPL flags 4, 5, 6
+    //--------------------------------------------------------------------
+    let builder = |platform: Platform| Chip8Builder::new().platform(platform)
+        .register(0x0, 1).register(0x1, 2).register(0x2, 3)
+        .rpl_flags(&[4, 5, 6]);
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: FX75 and FX85 save and load V0-VX, within the flags of the platform
+    //--------------------------------------------------------------------
+    let mut chip8 = builder(Platform::SuperChip).run(0xF175);
+    assert_eq!(&chip8.rpl_flags()[0..3], &[1, 2, 6], "FX75 should save V0-V1");
+    assert!(chip8.take_rpl_flags_saved() && !chip8.take_rpl_flags_saved(), "The save should be reported once");
+
+    let mut chip8 = builder(Platform::SuperChip).run(0xF185);
+    assert_eq!(&chip8.registers[0..3], &[4, 5, 3], "FX85 should load V0-V1");
+    assert!(!chip8.take_rpl_flags_saved(), "FX85 should not save the flags");
+
+    assert_eq!(builder(Platform::SuperChip).try_run(0xF875).err(),
+               Some("F875 at 200 uses V8, beyond the 8 RPL user flags of SUPER-CHIP!".to_string()),
+               "SUPER-CHIP should have 8 flags");
+    let chip8 = builder(Platform::XoChip).register(0xF, 7).run(0xFF75);
+    assert_eq!((chip8.rpl_flags()[15], chip8.program_counter), (7, 0x202), "XO-CHIP should have 16 flags");
+}
+
+#[test]
+fn test_timers_keys_and_random() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: FX15, FX18 and FX07 on the timers
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8Builder::new().register(0x1, 0x30).run(0xF115);
+    chip8.execute_opcode(0xF118).unwrap();
+    chip8.execute_opcode(0xF207).unwrap();
+    assert_eq!((chip8.registers[0x2], chip8.sound_timer.get_delay()), (0x30, 0x30), "The timers should be set");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: FX0A waits until a key is pressed
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8Builder::new().run(0xF30A);
+    assert_eq!(chip8.program_counter, 0x200, "FX0A should wait without key");
+
+    chip8.key_input.keys[0xB] = true;
+    chip8.execute_opcode(0xF30A).unwrap();
+    assert_eq!((chip8.registers[0x3], chip8.program_counter), (0xB, 0x202), "FX0A should store the key");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: CXNN masks the random number
+    //--------------------------------------------------------------------
+    for _ in 0..16 {
+        assert_eq!(Chip8Builder::new().run(0xC10F).registers[0x1] & 0xF0, 0, "CXNN should mask the number with NN");
+    }
+}
+
+#[test]
+fn test_draw_collision() {
+    //--------------------------------------------------------------------
+    // Setup: A 2x1 sprite at 10, 5
+    //--------------------------------------------------------------------
+    let mut chip8 = Chip8Builder::new().addr_register(0x300).memory(0x300, &[0xC0]).register(0x1, 10).register(0x2, 5).run(0xD121);
+    let first_vf = chip8.registers[0xF];
+
+    //--------------------------------------------------------------------
+    // Execute: Draw it again
+    //--------------------------------------------------------------------
+    chip8.execute_opcode(0xD121).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The first draw lights the pixels, the second one erases them with a collision
+    //--------------------------------------------------------------------
+    assert_eq!(first_vf, 0, "The first draw should not collide");
+    assert_eq!(chip8.registers[0xF], 1, "The second draw should collide");
+    assert!(chip8.screen.pixels.iter().all(|pixel| *pixel == 0), "The second draw should erase the sprite");
+}
+
+#[test]
+fn test_draw_wrap_and_clip() {
+    //--------------------------------------------------------------------
+    // Setup: A 2x2 sprite at 63, 31, and at 127, 63 which wraps to the same position
+    //--------------------------------------------------------------------
+    let builder = |quirks: Quirks, x: u8, y: u8| Chip8Builder::new().quirks(quirks).addr_register(0x300)
+        .memory(0x300, &[0xC0, 0xC0]).register(0x1, x).register(0x2, y);
+    let clip = Quirks { clip: true, ..LEGACY_QUIRKS };
+    let lit = |chip8: &Chip8<_, _>| -> Vec<usize> {
+        let pixels: &[u8] = &chip8.gfx;
+        pixels.iter().enumerate().filter(|(_, pixel)| **pixel != 0).map(|(index, _)| index).collect()
+    };
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The sprite wraps to the other corners, or is clipped
+    //--------------------------------------------------------------------
+    let corners = vec![0, 63, 64 * 31, 64 * 31 + 63];
+    assert_eq!(lit(&builder(LEGACY_QUIRKS, 63, 31).run(0xD122)), corners, "The sprite should wrap");
+    assert_eq!(lit(&builder(LEGACY_QUIRKS, 127, 63).run(0xD122)), corners, "The start position should wrap");
+    assert_eq!(lit(&builder(clip, 63, 31).run(0xD122)), vec![64 * 31 + 63], "The sprite should be clipped");
+    assert_eq!(lit(&builder(clip, 127, 63).run(0xD122)), vec![64 * 31 + 63], "The start position should wrap when clipping");
+}
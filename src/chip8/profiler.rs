@@ -0,0 +1,208 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Execution profiler: instructions per address and per opcode class,
+// and call stacks built from 2NNN/00EE, one instruction being one cycle
+//************************************************************************
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::chip8::constants::CHIP8_MEMORY_SIZE;
+use crate::chip8::disassembler::{disassemble, opcode_class};
+use crate::chip8::types::{Address, OpCode};
+
+// Number of addresses in the report
+const PROFILER_TOP_ADDRESSES: usize = 20;
+
+pub struct Profiler {
+    address_counts: Vec<u64>,
+    class_counts  : BTreeMap<&'static str, u64>,
+
+    // Subroutines being executed, and instructions executed with each call stack
+    call_stack: Vec<Address>,
+    stacks    : HashMap<Vec<Address>, u64>,
+    calls     : BTreeMap<Address, u64>,
+
+    total: u64
+}
+
+// Cycles of a subroutine, with and without the subroutines it calls
+#[derive(Debug, Default, PartialEq)]
+struct SubroutineCycles {
+    inclusive: u64,
+    exclusive: u64,
+    calls    : u64
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            address_counts: vec![0; CHIP8_MEMORY_SIZE],
+            class_counts  : BTreeMap::new(),
+            call_stack    : Vec::new(),
+            stacks        : HashMap::new(),
+            calls         : BTreeMap::new(),
+            total         : 0
+        }
+    }
+
+    // Count an instruction, before its execution
+    pub fn record(&mut self, address: Address, opcode: OpCode) {
+        self.total += 1;
+        self.address_counts[address as usize % CHIP8_MEMORY_SIZE] += 1;
+        *self.class_counts.entry(opcode_class(opcode)).or_insert(0) += 1;
+
+        // The call stack is only cloned the first time it is seen
+        match self.stacks.get_mut(&self.call_stack) {
+            Some(count) => *count += 1,
+            None => { self.stacks.insert(self.call_stack.clone(), 1); }
+        }
+
+        // The CALL is counted in the caller, and the RET in the subroutine
+        match opcode {
+            0x00EE => { self.call_stack.pop(); }
+            0x2000..=0x2FFF => {
+                let subroutine = opcode & 0x0FFF;
+                self.call_stack.push(subroutine);
+                *self.calls.entry(subroutine).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // Forget the subroutines being executed, after a reset
+    pub fn clear_call_stack(&mut self) {
+        self.call_stack.clear();
+    }
+
+    fn subroutine_cycles(&self) -> BTreeMap<Address, SubroutineCycles> {
+        let mut cycles: BTreeMap<Address, SubroutineCycles> = BTreeMap::new();
+        for (stack, count) in &self.stacks {
+            if let Some(subroutine) = stack.last() {
+                cycles.entry(*subroutine).or_default().exclusive += count;
+            }
+
+            // A recursive subroutine is only counted once per stack
+            let mut seen: Vec<Address> = Vec::new();
+            for subroutine in stack {
+                if !seen.contains(subroutine) {
+                    seen.push(*subroutine);
+                    cycles.entry(*subroutine).or_default().inclusive += count;
+                }
+            }
+        }
+
+        for (subroutine, calls) in &self.calls {
+            cycles.entry(*subroutine).or_default().calls = *calls;
+        }
+
+        cycles
+    }
+
+    // Text report: top addresses, opcode classes and subroutines
+    pub fn report(&self, memory: &[u8]) -> String {
+        let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+        let mut report = String::new();
+
+        writeln!(report, "Instructions executed: {}", self.total).unwrap();
+
+        writeln!(report, "\nTop addresses\n  Address       Count       %  Instruction").unwrap();
+        let mut addresses: Vec<(usize, u64)> = self.address_counts.iter().copied().enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (address, count) in addresses.into_iter().take(PROFILER_TOP_ADDRESSES) {
+            let opcode = (memory[address] as OpCode) << 8 | memory[(address + 1) % memory.len()] as OpCode;
+            writeln!(report, "  {:03X}     {:>12} {:>6.2}%  {}", address, count, percent(count), disassemble(opcode)).unwrap();
+        }
+
+        writeln!(report, "\nOpcode classes\n  Class         Count       %").unwrap();
+        let mut classes: Vec<(&str, u64)> = self.class_counts.iter().map(|(class, count)| (*class, *count)).collect();
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (class, count) in classes {
+            writeln!(report, "  {:<6} {:>12} {:>6.2}%", class, count, percent(count)).unwrap();
+        }
+
+        writeln!(report, "\nSubroutines (cycles = instructions)\n  Address   Calls     Inclusive       %     Exclusive       %").unwrap();
+        let main_exclusive = self.stacks.get(&Vec::new()).copied().unwrap_or(0);
+        writeln!(report, "  main   {:>8} {:>13} {:>6.2}% {:>13} {:>6.2}%", "-", self.total, 100.0,
+                 main_exclusive, percent(main_exclusive)).unwrap();
+        let mut subroutines: Vec<(Address, SubroutineCycles)> = self.subroutine_cycles().into_iter().collect();
+        subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        for (address, cycles) in subroutines {
+            writeln!(report, "  {:03X}    {:>8} {:>13} {:>6.2}% {:>13} {:>6.2}%", address, cycles.calls,
+                     cycles.inclusive, percent(cycles.inclusive), cycles.exclusive, percent(cycles.exclusive)).unwrap();
+        }
+
+        report
+    }
+
+    // Collapsed stacks, one "main;sub_2A4;sub_2F0 <cycles>" line per call stack, for flamegraph tools
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self.stacks.iter()
+            .map(|(stack, count)| {
+                let frames: Vec<String> = std::iter::once("main".to_string())
+                    .chain(stack.iter().map(|subroutine| format!("sub_{:03X}", subroutine)))
+                    .collect();
+                format!("{} {}", frames.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+// Unit tests
+#[cfg(test)]
+fn profile_call() -> Profiler {
+    // main: LD V0, 01 / CALL 300 / JP 202, sub_300: ADD V0, 01 / CALL 300 (once) / RET
+    let mut profiler = Profiler::new();
+    let start = crate::chip8::constants::CHIP8_MEMORY_START;
+    profiler.record(start, 0x6001);
+    profiler.record(start + 2, 0x2300);
+    profiler.record(0x300, 0x7001);
+    profiler.record(0x302, 0x2300);
+    profiler.record(0x300, 0x7001);
+    profiler.record(0x302, 0x00EE);
+    profiler.record(0x304, 0x00EE);
+    profiler.record(start + 4, 0x1202);
+
+    profiler
+}
+
+#[test]
+fn test_profiler_subroutines() {
+    //--------------------------------------------------------------------
+    // Setup: A main calling a recursive subroutine
+    //--------------------------------------------------------------------
+    let profiler = profile_call();
+
+    //--------------------------------------------------------------------
+    // Execute: Get the cycles of the subroutines
+    //--------------------------------------------------------------------
+    let cycles = profiler.subroutine_cycles();
+
+    //--------------------------------------------------------------------
+    // Verify: The recursion is counted once in the inclusive cycles
+    //--------------------------------------------------------------------
+    assert_eq!(profiler.total, 8, "8 instructions should be counted");
+    assert_eq!(profiler.class_counts.get("2NNN"), Some(&2), "2 calls should be counted");
+    assert_eq!(cycles.get(&0x300), Some(&SubroutineCycles { inclusive: 5, exclusive: 5, calls: 2 }),
+               "The subroutine should have run 5 instructions");
+}
+
+#[test]
+fn test_profiler_collapsed_stacks() {
+    //--------------------------------------------------------------------
+    // Execute: Get the collapsed stacks
+    //--------------------------------------------------------------------
+    let collapsed = profile_call().collapsed_stacks();
+
+    //--------------------------------------------------------------------
+    // Verify: One line per call stack
+    //--------------------------------------------------------------------
+    assert_eq!(collapsed, "main 3\nmain;sub_300 3\nmain;sub_300;sub_300 2\n", "The stacks should be collapsed");
+}
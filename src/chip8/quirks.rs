@@ -0,0 +1,104 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Platforms and quirks
+//************************************************************************
+
+use crate::chip8::constants::CHIP8_MEMORY_START;
+
+// The platform a ROM was written for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+    // Parse a platform id from the community chip-8-database
+    pub fn from_database_id(id: &str) -> Option<Self> {
+        match id {
+            "originalChip8" | "hybridVIP" | "modernChip8" | "chip48" => Some(Platform::Chip8),
+            "superchip1" | "superchip" | "megachip8"                 => Some(Platform::SuperChip),
+            "xochip"                                                 => Some(Platform::XoChip),
+            _                                                        => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8     => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip    => "XO-CHIP"
+        }
+    }
+
+    // Memory addressable by the programs, the accesses past its end wrap around
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip                      => 0x10000
+        }
+    }
+
+    // Largest ROM, loaded from 0x200 to the end of the memory
+    pub fn max_rom_size(&self) -> usize {
+        self.memory_size() - CHIP8_MEMORY_START as usize
+    }
+
+    // RPL user flags saved and loaded by FX75 and FX85
+    pub fn rpl_flag_count(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 8,
+            Platform::XoChip                      => 16
+        }
+    }
+
+    // Quirks usually expected by the ROMs of this platform
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                shift           : false,
+                memory_increment: true,
+                jump            : false,
+                vf_reset        : true,
+                clip            : true
+            },
+            Platform::SuperChip => Quirks {
+                shift           : true,
+                memory_increment: false,
+                jump            : true,
+                vf_reset        : false,
+                clip            : true
+            },
+            Platform::XoChip => Quirks {
+                shift           : false,
+                memory_increment: true,
+                jump            : false,
+                vf_reset        : false,
+                clip            : false
+            }
+        }
+    }
+}
+
+// Behaviours that differ between the CHIP-8 interpreters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of VY
+    pub shift: bool,
+
+    // FX55/FX65 leave I incremented by X + 1
+    pub memory_increment: bool,
+
+    // BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump: bool,
+
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+
+    // DXYN clips sprites at the screen edges instead of wrapping them
+    pub clip: bool
+}
+
@@ -0,0 +1,76 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Snapshot of the machine state, for the debugging tools
+//************************************************************************
+
+use crate::chip8::{Chip8, Display, KeyInput, Platform, Quirks};
+use crate::chip8::constants::{CHIP8_MEMORY_SIZE, CHIP8_REGISTER_COUNT, CHIP8_STACK_COUNT};
+use crate::chip8::types::{Address, OpCode};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineState {
+    pub registers      : [u8; CHIP8_REGISTER_COUNT],
+    pub addr_register  : Address,
+    pub program_counter: Address,
+    pub stack          : [Address; CHIP8_STACK_COUNT],
+    pub stack_ptr      : usize,
+    pub delay_timer    : u8,
+    pub sound_timer    : u8,
+    pub memory         : [u8; CHIP8_MEMORY_SIZE],
+    pub keys           : [bool; 16],
+    pub quirks         : Quirks
+}
+
+impl MachineState {
+    // Opcode stored at an address, None when it does not fit in memory
+    pub fn opcode_at(&self, address: Address) -> Option<OpCode> {
+        let address = address as usize;
+        if address + 1 >= self.memory.len() {
+            return None;
+        }
+
+        Some((self.memory[address] as OpCode) << 8 | self.memory[address + 1] as OpCode)
+    }
+}
+
+impl Default for MachineState {
+    fn default() -> Self {
+        MachineState {
+            registers      : [0; CHIP8_REGISTER_COUNT],
+            addr_register  : 0,
+            program_counter: 0x200,
+            stack          : [0; CHIP8_STACK_COUNT],
+            stack_ptr      : 0,
+            delay_timer    : 0,
+            sound_timer    : 0,
+            memory         : [0; CHIP8_MEMORY_SIZE],
+            keys           : [false; 16],
+            quirks         : Platform::Chip8.default_quirks()
+        }
+    }
+}
+
+impl<Screen, Input> Chip8<Screen, Input> where Screen: Display, Input: KeyInput {
+    // Copy the registers, the stack, the timers, the memory and the pressed keys
+    pub fn state(&self) -> MachineState {
+        let mut keys = [false; 16];
+        for (key, pressed) in keys.iter_mut().enumerate() {
+            *pressed = self.key_input.is_key_pressed(key as u8);
+        }
+
+        MachineState {
+            registers      : self.registers,
+            addr_register  : self.addr_register,
+            program_counter: self.program_counter,
+            stack          : self.stack,
+            stack_ptr      : self.stack_ptr,
+            delay_timer    : self.delay_timer.get_delay(),
+            sound_timer    : self.sound_timer.get_delay(),
+            memory         : self.memory,
+            keys,
+            quirks         : self.quirks
+        }
+    }
+}
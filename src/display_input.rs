@@ -4,8 +4,9 @@
 //************************************************************************
 
 use sdl2::EventPump;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::{Scancode, Keycode};
+use sdl2::mouse::MouseButton;
 
 use crate::chip8::{Display, KeyInput};
 
@@ -50,58 +51,117 @@ impl Display for Screen {
 }
 
 //-------------------------- INPUT --------------------------
+// Frontend actions bound to keys outside of the CHIP-8 keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    Quit,
+    // Leave the ROM, back to the browser when there is one
+    Back,
+    CyclePalette,
+    Record,
+    Screenshot,
+    SoftReset,
+    HardReset,
+    Pause,
+    FrameAdvance,
+    Slower,
+    Faster,
+    ToggleOsd,
+    ToggleViewer,
+    // Rows to scroll the memory dump of the viewer
+    Scroll(i32),
+    // A window has been closed, from its id
+    CloseWindow(u32),
+    // Left click in a window, from its id
+    Click(u32, i32, i32)
+}
+
 pub struct Input<'a> {
-    event_pump: &'a mut EventPump
+    event_pump : &'a mut EventPump,
+    key_layout : Vec<(Scancode, u8)>,
+
+    // Key pressed during the last poll, waiting to be read by FX0A
+    pending_key: Option<u8>
 }
 
 impl<'a> Input<'a> {
     pub fn new(event_pump: &'a mut EventPump) -> Self {
         Input {
-            event_pump
+            event_pump,
+            key_layout : Vec::new(),
+            pending_key: None
         }
     }
 
-    pub fn check_quit(&mut self) -> bool {
-        for event in self.event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    return true;
-                },
-                _ => {}
-            }
-        }
+    // Map the arrows, space and left shift to CHIP-8 keys, from the ROM database
+    pub fn set_key_layout(&mut self, keys: &[(String, u8)]) {
+        self.key_layout = keys.iter()
+            .filter_map(|(name, key)| {
+                let scancode = match name.as_str() {
+                    "up"    => Scancode::Up,
+                    "down"  => Scancode::Down,
+                    "left"  => Scancode::Left,
+                    "right" => Scancode::Right,
+                    "a"     => Scancode::Space,
+                    "b"     => Scancode::LShift,
+                    _       => return None
+                };
+
+                Some((scancode, *key & 0x0F))
+            })
+            .collect();
+    }
 
-        false
+    // Get the CHIP-8 key of a scancode
+    fn get_key_from_scancode(&self, scancode: Scancode) -> Option<u8> {
+        SCANCODES.iter().position(|&s| s == scancode).map(|key| key as u8)
+            .or_else(|| self.key_layout.iter().find(|(s, _)| *s == scancode).map(|(_, key)| *key))
+    }
+
+    // Get the frontend hotkeys pressed since the last call, and the CHIP-8 key pressed for FX0A
+    pub fn poll_hotkeys(&mut self) -> Vec<Hotkey> {
+        // A key which has not been read by FX0A during the frame is not waited for anymore
+        self.pending_key = None;
+
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        events.into_iter()
+            .filter_map(|event| match event {
+                Event::KeyDown { scancode: Some(scancode), repeat: false, .. } if self.get_key_from_scancode(scancode).is_some() => {
+                    self.pending_key = self.get_key_from_scancode(scancode);
+                    None
+                }
+                Event::Quit { .. } => Some(Hotkey::Quit),
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => Some(Hotkey::Back),
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => Some(Hotkey::CyclePalette),
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => Some(Hotkey::SoftReset),
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => Some(Hotkey::HardReset),
+                Event::KeyDown { keycode: Some(Keycode::F4), .. } => Some(Hotkey::ToggleOsd),
+                Event::KeyDown { keycode: Some(Keycode::F5), .. } => Some(Hotkey::Pause),
+                Event::KeyDown { keycode: Some(Keycode::F6), .. } => Some(Hotkey::FrameAdvance),
+                Event::KeyDown { keycode: Some(Keycode::F7), .. } => Some(Hotkey::Slower),
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => Some(Hotkey::Faster),
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => Some(Hotkey::ToggleViewer),
+                Event::KeyDown { keycode: Some(Keycode::F10), .. } => Some(Hotkey::Record),
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => Some(Hotkey::Screenshot),
+                Event::MouseWheel { y, .. } => Some(Hotkey::Scroll(-y)),
+                Event::MouseButtonDown { window_id, mouse_btn: MouseButton::Left, x, y, .. } => Some(Hotkey::Click(window_id, x, y)),
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => Some(Hotkey::CloseWindow(window_id)),
+                _ => None
+            })
+            .collect()
     }
 }
 
 impl KeyInput for Input<'_> {
     fn is_key_pressed(&self, key: u8) -> bool {
-        self.event_pump.keyboard_state().is_scancode_pressed(SCANCODES[key as usize])
+        let keyboard = self.event_pump.keyboard_state();
+
+        keyboard.is_scancode_pressed(SCANCODES[key as usize]) ||
+            self.key_layout.iter().any(|&(scancode, k)| k == key && keyboard.is_scancode_pressed(scancode))
     }
 
-    fn get_key(&mut self) -> u8 {
-        loop {
-            let event = self.event_pump.wait_event();
-            match event {
-                Event::KeyDown { scancode: Some(Scancode::Kp0), ..} => return 0x0,
-                Event::KeyDown { scancode: Some(Scancode::Kp1), ..} => return 0x1,
-                Event::KeyDown { scancode: Some(Scancode::Kp2), ..} => return 0x2,
-                Event::KeyDown { scancode: Some(Scancode::Kp3), ..} => return 0x3,
-                Event::KeyDown { scancode: Some(Scancode::Kp4), ..} => return 0x4,
-                Event::KeyDown { scancode: Some(Scancode::Kp5), ..} => return 0x5,
-                Event::KeyDown { scancode: Some(Scancode::Kp6), ..} => return 0x6,
-                Event::KeyDown { scancode: Some(Scancode::Kp7), ..} => return 0x7,
-                Event::KeyDown { scancode: Some(Scancode::Kp8), ..} => return 0x8,
-                Event::KeyDown { scancode: Some(Scancode::Kp9), ..} => return 0x9,
-                Event::KeyDown { scancode: Some(Scancode::A), ..}   => return 0xA,
-                Event::KeyDown { scancode: Some(Scancode::B), ..}   => return 0xB,
-                Event::KeyDown { scancode: Some(Scancode::C), ..}   => return 0xC,
-                Event::KeyDown { scancode: Some(Scancode::D), ..}   => return 0xD,
-                Event::KeyDown { scancode: Some(Scancode::E), ..}   => return 0xE,
-                Event::KeyDown { scancode: Some(Scancode::F), ..}   => return 0xF,
-                _ => continue
-            }
-        }
+    // The keys are read between the frames, the key pressed during the last frame is used
+    fn get_key(&mut self) -> Option<u8> {
+        self.pending_key.take()
     }
 }
\ No newline at end of file
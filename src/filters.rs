@@ -0,0 +1,332 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Post-processing filters, applied to the screen image on the CPU
+//************************************************************************
+
+use crate::image::{Image, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::{Palette, Rgb};
+
+// Brightness of the darkened scanlines and of the CRT mask
+const SCANLINE_BRIGHTNESS: f32 = 0.6;
+const CRT_MASK_BRIGHTNESS: f32 = 0.7;
+
+// Biggest nearest-neighbour scale, also used for the screenshots and the recordings, and biggest scale of a list
+// of filters
+pub const MAX_SCALE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    // Nearest-neighbour integer scale
+    Scale(usize),
+    Scale2x,
+    Scale3x,
+    Epx,
+    // Darken every other row
+    Scanlines,
+    // Aperture grille, each column lets a color channel through
+    CrtMask
+}
+
+impl Filter {
+    // Parse a filter name: "nearest<N>", "scale2x", "scale3x", "epx", "scanlines" or "crt"
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "scale2x"   => Ok(Filter::Scale2x),
+            "scale3x"   => Ok(Filter::Scale3x),
+            "epx"       => Ok(Filter::Epx),
+            "scanlines" => Ok(Filter::Scanlines),
+            "crt"       => Ok(Filter::CrtMask),
+            _ => name.strip_prefix("nearest")
+                .and_then(|factor| factor.parse().ok())
+                .filter(|factor| (1..=MAX_SCALE).contains(factor))
+                .map(Filter::Scale)
+                .ok_or(format!("Unknown filter {} (nearest scale from 1 to {})", name, MAX_SCALE))
+        }
+    }
+
+    // Parse a comma separated list of filters, applied in order, which cannot scale the screen more than MAX_SCALE
+    pub fn parse_list(names: &str) -> Result<Vec<Self>, String> {
+        let filters = names.split(',').map(|name| Filter::parse(name.trim())).collect::<Result<Vec<_>, _>>()?;
+
+        let factor = filters.iter().try_fold(1usize, |factor, filter| factor.checked_mul(filter.factor()));
+        match factor {
+            Some(factor) if factor <= MAX_SCALE => Ok(filters),
+            _ => Err(format!("The filters {} scale the screen more than {} times", names, MAX_SCALE))
+        }
+    }
+
+    // Scale of the image, in each direction
+    pub fn factor(&self) -> usize {
+        match self {
+            Filter::Scale(factor)               => *factor,
+            Filter::Scale2x | Filter::Epx       => 2,
+            Filter::Scale3x                     => 3,
+            Filter::Scanlines | Filter::CrtMask => 1
+        }
+    }
+
+    pub fn apply(&self, image: &Image) -> Image {
+        match self {
+            Filter::Scale(factor) => scale_image(image, *factor),
+            Filter::Scale2x       => scale2x(image),
+            Filter::Scale3x       => scale3x(image),
+            Filter::Epx           => epx(image),
+            Filter::Scanlines     => scanlines(image),
+            Filter::CrtMask       => crt_mask(image)
+        }
+    }
+}
+
+// Apply a list of filters in order
+pub fn apply_filters(filters: &[Filter], image: Image) -> Image {
+    filters.iter().fold(image, |image, filter| filter.apply(&image))
+}
+
+// Images of the screenshots and the recordings: the filters of the window, then a nearest-neighbour scale up to
+// about the scale of the capture
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capture {
+    filters: Vec<Filter>,
+    scale  : usize
+}
+
+impl Capture {
+    pub fn new(filters: &[Filter], scale: usize) -> Self {
+        let factor: usize = filters.iter().map(Filter::factor).product();
+        let scale = (scale.clamp(1, MAX_SCALE) / factor).max(1);
+        Capture { filters: filters.to_vec(), scale }
+    }
+
+    // Size of the images
+    pub fn size(&self) -> (usize, usize) {
+        let factor = self.scale * self.filters.iter().map(Filter::factor).product::<usize>();
+        (SCREEN_WIDTH * factor, SCREEN_HEIGHT * factor)
+    }
+
+    pub fn apply(&self, framebuffer: &[u8], palette: &Palette) -> Image {
+        let image = apply_filters(&self.filters, Image::from_framebuffer(framebuffer, palette));
+        match self.scale {
+            1 => image,
+            scale => scale_image(&image, scale)
+        }
+    }
+}
+
+fn scale_image(image: &Image, factor: usize) -> Image {
+    let mut result = Image::new(image.width * factor, image.height * factor, [0; 3]);
+    for y in 0..result.height {
+        for x in 0..result.width {
+            result.set(x, y, image.get(x / factor, y / factor));
+        }
+    }
+
+    result
+}
+
+// Neighbours of a pixel, from the top left to the bottom right
+fn neighbours(image: &Image, x: usize, y: usize) -> [Rgb; 9] {
+    let (x, y) = (x as isize, y as isize);
+    [
+        image.get_clamped(x - 1, y - 1), image.get_clamped(x, y - 1), image.get_clamped(x + 1, y - 1),
+        image.get_clamped(x - 1, y),     image.get_clamped(x, y),     image.get_clamped(x + 1, y),
+        image.get_clamped(x - 1, y + 1), image.get_clamped(x, y + 1), image.get_clamped(x + 1, y + 1)
+    ]
+}
+
+// Apply a function building the factor x factor block of each pixel from its neighbours
+fn scale_with<F>(image: &Image, factor: usize, block: F) -> Image where F: Fn(&[Rgb; 9]) -> Vec<Rgb> {
+    let mut result = Image::new(image.width * factor, image.height * factor, [0; 3]);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            for (index, color) in block(&neighbours(image, x, y)).into_iter().enumerate() {
+                result.set(x * factor + index % factor, y * factor + index / factor, color);
+            }
+        }
+    }
+
+    result
+}
+
+// https://www.scale2x.it/algorithm
+fn scale2x(image: &Image) -> Image {
+    scale_with(image, 2, |&[_, b, _, d, e, f, _, h, _]| {
+        if b != h && d != f {
+            vec![if d == b { d } else { e }, if b == f { f } else { e },
+                 if d == h { d } else { e }, if h == f { f } else { e }]
+        } else {
+            vec![e; 4]
+        }
+    })
+}
+
+fn scale3x(image: &Image) -> Image {
+    scale_with(image, 3, |&[a, b, c, d, e, f, g, h, i]| {
+        if b != h && d != f {
+            vec![
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                e,
+                if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                if h == f { f } else { e }
+            ]
+        } else {
+            vec![e; 9]
+        }
+    })
+}
+
+// Eric's Pixel Expansion, the original rule keeping the pixel when 3 neighbours are identical
+fn epx(image: &Image) -> Image {
+    scale_with(image, 2, |&[_, top, _, left, p, right, _, bottom, _]| {
+        let identical = [(top, right), (top, left), (top, bottom), (right, left), (right, bottom), (left, bottom)]
+            .iter()
+            .filter(|(first, second)| first == second)
+            .count();
+
+        // 3 identical neighbours make 3 equal pairs
+        if identical >= 3 {
+            return vec![p; 4];
+        }
+
+        vec![if left == top { top } else { p }, if top == right { right } else { p },
+             if bottom == left { left } else { p }, if right == bottom { bottom } else { p }]
+    })
+}
+
+fn scanlines(image: &Image) -> Image {
+    let mut result = image.clone();
+    for y in (1..image.height).step_by(2) {
+        for x in 0..image.width {
+            result.set(x, y, darken(image.get(x, y), [SCANLINE_BRIGHTNESS; 3]));
+        }
+    }
+
+    result
+}
+
+fn crt_mask(image: &Image) -> Image {
+    let mut result = image.clone();
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let mut brightness = [CRT_MASK_BRIGHTNESS; 3];
+            brightness[x % 3] = 1.0;
+            result.set(x, y, darken(image.get(x, y), brightness));
+        }
+    }
+
+    result
+}
+
+fn darken(color: Rgb, brightness: [f32; 3]) -> Rgb {
+    [
+        (color[0] as f32 * brightness[0]) as u8,
+        (color[1] as f32 * brightness[1]) as u8,
+        (color[2] as f32 * brightness[2]) as u8
+    ]
+}
+
+// Unit tests
+#[cfg(test)]
+const W: Rgb = [0xFF; 3];
+#[cfg(test)]
+const B: Rgb = [0x00; 3];
+
+#[test]
+fn test_parse_filters() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Filters are parsed in order
+    //--------------------------------------------------------------------
+    assert_eq!(Filter::parse_list("nearest4, scale2x,scanlines"),
+               Ok(vec![Filter::Scale(4), Filter::Scale2x, Filter::Scanlines]), "The filters should be parsed");
+    assert!(Filter::parse("nearest0").is_err(), "The scale factor should be at least 1");
+    assert!(Filter::parse("nearest16").is_ok(), "The scale factor should go up to 16");
+    assert!(Filter::parse("nearest17").is_err(), "The scale factor should be at most 16");
+    assert!(Filter::parse("hq9x").is_err(), "Unknown filters should be rejected");
+    assert!(Filter::parse_list("nearest8,scale2x").is_ok(), "The filters should scale up to 16 times");
+    assert_eq!(Filter::parse_list("nearest16,nearest16,scale3x"),
+               Err("The filters nearest16,nearest16,scale3x scale the screen more than 16 times".to_string()),
+               "The filters should not build huge images");
+}
+
+#[test]
+fn test_capture() {
+    //--------------------------------------------------------------------
+    // Setup: Light the top left pixel
+    //--------------------------------------------------------------------
+    let mut framebuffer = [0u8; 2048];
+    framebuffer[0] = 1;
+    let palette = Palette::parse("#000000,#FFFFFF").unwrap();
+
+    //--------------------------------------------------------------------
+    // Execute: Capture it with scanlines at scale 2, and with Scale3x at scale 10
+    //--------------------------------------------------------------------
+    let scanlines = Capture::new(&[Filter::Scanlines], 2);
+    let image = scanlines.apply(&framebuffer, &palette);
+    let scale3x = Capture::new(&[Filter::Scale3x], 10);
+
+    //--------------------------------------------------------------------
+    // Verify: The filters are applied before the scale, which completes theirs
+    //--------------------------------------------------------------------
+    assert_eq!(((image.width, image.height), scanlines.size()), ((128, 64), (128, 64)), "The image should be scaled");
+    assert_eq!((image.get(1, 1), image.get(0, 2), image.get(0, 3)), (W, B, B), "The scanlines should follow the screen rows");
+    assert_eq!(Capture::new(&[], 2).apply(&framebuffer, &palette).get(1, 2), B, "The unlit pixels should stay dark");
+    assert_eq!(scale3x.size(), (64 * 9, 32 * 9), "Scale3x should be completed to about the scale");
+}
+
+#[test]
+fn test_scale2x_diagonal() {
+    //--------------------------------------------------------------------
+    // Setup: Create a diagonal line
+    //--------------------------------------------------------------------
+    let image = Image { width: 3, height: 3, pixels: vec![W, B, B,
+                                                          B, W, B,
+                                                          B, B, W] };
+
+    //--------------------------------------------------------------------
+    // Execute: Scale it with Scale2x and EPX
+    //--------------------------------------------------------------------
+    let scale2x = Filter::Scale2x.apply(&image);
+    let epx = Filter::Epx.apply(&image);
+
+    //--------------------------------------------------------------------
+    // Verify: The steps of the diagonal are filled instead of being blocky
+    //--------------------------------------------------------------------
+    assert_eq!((scale2x.width, scale2x.height), (6, 6), "The image should be twice as big");
+    assert_eq!(scale2x.get(2, 1), W, "The step above the diagonal should be filled");
+    assert_eq!(scale2x.get(1, 2), W, "The step below the diagonal should be filled");
+    assert_eq!(scale2x.get(3, 0), B, "Pixels away from the diagonal should not change");
+    assert_eq!(epx, scale2x, "EPX and Scale2x should agree on a diagonal");
+}
+
+#[test]
+fn test_scale3x_and_nearest_keep_flat_areas() {
+    //--------------------------------------------------------------------
+    // Setup: Create a flat image
+    //--------------------------------------------------------------------
+    let image = Image::new(3, 2, W);
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Flat areas stay flat
+    //--------------------------------------------------------------------
+    assert_eq!(Filter::Scale3x.apply(&image), Image::new(9, 6, W), "Scale3x should keep flat areas");
+    assert_eq!(Filter::Scale(3).apply(&image), Image::new(9, 6, W), "Nearest should keep flat areas");
+}
+
+#[test]
+fn test_scanlines() {
+    //--------------------------------------------------------------------
+    // Execute: Apply scanlines on a white image
+    //--------------------------------------------------------------------
+    let image = Filter::Scanlines.apply(&Image::new(1, 2, W));
+
+    //--------------------------------------------------------------------
+    // Verify: Odd rows are darkened
+    //--------------------------------------------------------------------
+    assert_eq!(image.pixels, vec![W, [153; 3]], "The second row should be darkened");
+}
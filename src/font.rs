@@ -0,0 +1,78 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Built-in 3x5 bitmap font, used to draw text over the screen
+//************************************************************************
+
+pub const FONT_WIDTH : usize = 3;
+pub const FONT_HEIGHT: usize = 5;
+
+// Rows of each glyph, from the top, the left pixel being the bit 2
+const GLYPHS: [(char, [u8; FONT_HEIGHT]); 59] = [
+    (' ', [0, 0, 0, 0, 0]), ('0', [7, 5, 5, 5, 7]), ('1', [2, 6, 2, 2, 7]), ('2', [7, 1, 7, 4, 7]),
+    ('3', [7, 1, 7, 1, 7]), ('4', [5, 5, 7, 1, 1]), ('5', [7, 4, 7, 1, 7]), ('6', [7, 4, 7, 5, 7]),
+    ('7', [7, 1, 1, 2, 2]), ('8', [7, 5, 7, 5, 7]), ('9', [7, 5, 7, 1, 7]), ('A', [2, 5, 7, 5, 5]),
+    ('B', [6, 5, 6, 5, 6]), ('C', [3, 4, 4, 4, 3]), ('D', [6, 5, 5, 5, 6]), ('E', [7, 4, 6, 4, 7]),
+    ('F', [7, 4, 6, 4, 4]), ('G', [3, 4, 5, 5, 3]), ('H', [5, 5, 7, 5, 5]), ('I', [7, 2, 2, 2, 7]),
+    ('J', [1, 1, 1, 5, 2]), ('K', [5, 5, 6, 5, 5]), ('L', [4, 4, 4, 4, 7]), ('M', [5, 7, 7, 5, 5]),
+    ('N', [6, 5, 5, 5, 5]), ('O', [2, 5, 5, 5, 2]), ('P', [6, 5, 6, 4, 4]), ('Q', [2, 5, 5, 6, 3]),
+    ('R', [6, 5, 6, 5, 5]), ('S', [3, 4, 2, 1, 6]), ('T', [7, 2, 2, 2, 2]), ('U', [5, 5, 5, 5, 7]),
+    ('V', [5, 5, 5, 5, 2]), ('W', [5, 5, 7, 7, 5]), ('X', [5, 5, 2, 5, 5]), ('Y', [5, 5, 2, 2, 2]),
+    ('Z', [7, 1, 2, 4, 7]), ('.', [0, 0, 0, 0, 2]), (',', [0, 0, 0, 2, 4]), (':', [0, 2, 0, 2, 0]),
+    ('/', [1, 1, 2, 4, 4]), ('-', [0, 0, 7, 0, 0]), ('+', [0, 2, 7, 2, 0]), ('%', [5, 1, 2, 4, 5]),
+    ('(', [1, 2, 2, 2, 1]), (')', [4, 2, 2, 2, 4]), ('[', [3, 2, 2, 2, 3]), (']', [6, 2, 2, 2, 6]),
+    ('<', [1, 2, 4, 2, 1]), ('>', [4, 2, 1, 2, 4]), ('=', [0, 7, 0, 7, 0]), ('_', [0, 0, 0, 0, 7]),
+    ('!', [2, 2, 2, 0, 2]), ('?', [7, 1, 2, 0, 2]), ('#', [5, 7, 5, 7, 5]), ('*', [0, 5, 2, 5, 0]),
+    ('\'', [2, 2, 0, 0, 0]), ('"', [5, 5, 0, 0, 0]), ('|', [2, 2, 2, 2, 2])
+];
+
+// Get the rows of a character, lowercase letters are drawn uppercase and unknown characters as '?'
+fn glyph(character: char) -> [u8; FONT_HEIGHT] {
+    let character = character.to_ascii_uppercase();
+    GLYPHS.iter()
+        .find(|(c, _)| *c == character)
+        .or_else(|| GLYPHS.iter().find(|(c, _)| *c == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap()
+}
+
+// Size of a text in pixels, with a column of space between the characters
+pub fn text_size(text: &str) -> (usize, usize) {
+    let count = text.chars().count();
+    ((count * (FONT_WIDTH + 1)).saturating_sub(1), FONT_HEIGHT)
+}
+
+// Coordinates of the lit pixels of a text
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for (index, character) in text.chars().enumerate() {
+        for (y, row) in glyph(character).iter().enumerate() {
+            for x in 0..FONT_WIDTH {
+                if row & (0x04 >> x) != 0 {
+                    pixels.push((index * (FONT_WIDTH + 1) + x, y));
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+// Unit tests
+#[test]
+fn test_text_pixels() {
+    //--------------------------------------------------------------------
+    // Execute: Get the pixels of "1-"
+    //--------------------------------------------------------------------
+    let pixels = text_pixels("1-");
+
+    //--------------------------------------------------------------------
+    // Verify: The second character starts after a column of space
+    //--------------------------------------------------------------------
+    assert_eq!(text_size("1-"), (7, 5), "2 characters should be 7 pixels wide");
+    assert_eq!(&pixels[0..3], &[(1, 0), (0, 1), (1, 1)], "The top of the 1 should be drawn");
+    assert_eq!(&pixels[pixels.len() - 3..], &[(4, 2), (5, 2), (6, 2)], "The dash should be in the second character");
+    assert_eq!(text_pixels("a"), text_pixels("A"), "Lowercase letters should be drawn uppercase");
+    assert_eq!(text_pixels("~"), text_pixels("?"), "Unknown characters should be drawn as ?");
+}
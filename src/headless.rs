@@ -0,0 +1,99 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Headless frontend, running without a window
+//************************************************************************
+
+use std::path::Path;
+
+use crate::chip8::{Chip8, KeyInput};
+use crate::display_input::Screen;
+use crate::filters::Capture;
+use crate::options::Options;
+use crate::recorder::Recorder;
+use crate::{enable_trackers, finish, finish_after_error, platform_notice, select_palette, take_screenshot};
+
+//-------------------------- INPUT --------------------------
+// No key is ever pressed
+pub struct NoInput;
+
+impl KeyInput for NoInput {
+    fn is_key_pressed(&self, _key: u8) -> bool {
+        false
+    }
+
+    fn get_key(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+//-------------------------- RUN --------------------------
+// Run the ROM for a number of frames as fast as possible, then save a screenshot
+// The frames are recorded with --record
+pub fn run_screenshot(options: &Options, frames: u64) -> Result<(), String> {
+    let mut chip8 = Chip8::new(Screen::new(), NoInput);
+    chip8.load_rom_file(&options.rom_file)?;
+    // Nothing could resume a break without a window
+    enable_trackers(&mut chip8, &Options { break_on_code_write: false, ..options.clone() });
+    chip8.init()?;
+    if let Some(notice) = platform_notice(&chip8) {
+        println!("{}", notice);
+    }
+
+    let palette = select_palette(options, chip8.rom_info())?;
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::start(Path::new(path), &palette, &Capture::new(&options.filters, options.record_scale))?),
+        None => None
+    };
+
+    for _ in 0..frames {
+        chip8.run_frame().map_err(|e| finish_after_error(&chip8, options, recorder.take(), e))?;
+
+        if let Some(recorder) = &mut recorder {
+            recorder.add_frame(&chip8.screen.data, &palette)?;
+        }
+    }
+
+    finish(&chip8, options, recorder)?;
+
+    println!("{}", take_screenshot(options, &chip8.screen.data, &palette, frames)?);
+
+    Ok(())
+}
+
+// Unit tests
+#[test]
+fn test_run_screenshot_error() {
+    //--------------------------------------------------------------------
+    // Setup: A ROM failing on its second instruction, recorded and tracked
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("headless-error");
+    let rom_file = directory.join("error.ch8");
+    std::fs::write(&rom_file, [0x60, 0x01, 0xFF, 0xFF]).unwrap();
+    let path = |name: &str| Some(directory.join(name).to_string_lossy().into_owned());
+    let options = Options {
+        rom_file      : rom_file.to_string_lossy().into_owned(),
+        screenshot_dir: directory.to_string_lossy().into_owned(),
+        record        : path("error.gif"),
+        profile       : path("error.profile"),
+        coverage      : path("error.coverage"),
+        code_writes   : path("error.writes"),
+        ..Options::default()
+    };
+
+    //--------------------------------------------------------------------
+    // Execute: Run it headless
+    //--------------------------------------------------------------------
+    let result = run_screenshot(&options, 10);
+    let written: Vec<bool> = ["error.gif", "error.profile", "error.folded", "error.coverage", "error.writes"].iter()
+        .map(|name| directory.join(name).is_file())
+        .collect();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The error is reported after the recording and the reports are written
+    //--------------------------------------------------------------------
+    assert_eq!(result, Err("Unknown opcode FFFF at 202!".to_string()), "The emulation error should be reported");
+    assert_eq!(written, vec![true; 5], "The recording and the reports should be written");
+}
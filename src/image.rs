@@ -0,0 +1,57 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// RGB image, built from the framebuffer for the renderers and exporters
+//************************************************************************
+
+use crate::palette::{Palette, Rgb};
+
+pub const SCREEN_WIDTH : usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width : usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, color: Rgb) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![color; width * height]
+        }
+    }
+
+    // Color the framebuffer of the emulator
+    pub fn from_framebuffer(framebuffer: &[u8], palette: &Palette) -> Self {
+        Image {
+            width : SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            pixels: framebuffer.iter().map(|&pixel| palette.color(pixel)).collect()
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[x + y * self.width]
+    }
+
+    // Get a pixel, the coordinates are clamped to the image edges
+    pub fn get_clamped(&self, x: isize, y: isize) -> Rgb {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.get(x, y)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        self.pixels[x + y * self.width] = color;
+    }
+
+    // Pixels as RGB24 bytes
+    pub fn to_rgb24(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+}
@@ -0,0 +1,8 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Emulator core, shared by the executable and the integration tests
+//************************************************************************
+
+pub mod chip8;
@@ -3,18 +3,64 @@
 // Distributed under the MIT license
 //************************************************************************
 
-mod chip8;
+mod benchmark;
+mod browser;
 mod display_input;
+mod filters;
+mod font;
+mod headless;
+mod image;
+mod options;
+mod osd;
+mod pacing;
+mod palette;
+mod phosphor;
+mod recorder;
+mod renderer;
+mod rpl_flags;
+mod screenshot;
+mod terminal;
+mod viewer;
+mod watcher;
 
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Instant;
+
+use sdl2::{EventPump, VideoSubsystem};
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use rust_chip_8::chip8;
 
-use crate::display_input::{Screen, Input};
+use crate::browser::{Browser, BrowserAction};
+use crate::chip8::{Chip8, Display, KeyInput, Platform, RomInfo};
+use crate::display_input::{Hotkey, Screen, Input};
+use crate::filters::Capture;
+use crate::image::Image;
+use crate::options::{Command, Options};
+use crate::osd::Osd;
+use crate::pacing::Pacing;
+use crate::palette::{Palette, Rgb};
+use crate::phosphor::Phosphor;
+use crate::recorder::Recorder;
+use crate::renderer::Renderer;
+use crate::rpl_flags::RplFlagStore;
+use crate::viewer::Viewer;
+use crate::watcher::RomWatcher;
 
 const WIDTH : u32 = 640;
 const HEIGHT: u32 = 320;
 const TITLE : &'static str = "Rust CHIP-8 emulator";
 
+// How the player stopped a ROM: leaving it for the browser, or quitting the emulator
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+    Back,
+    Quit
+}
+
 fn init_sdl() -> (sdl2::Sdl, sdl2::video::Window) {
     let context = sdl2::init().unwrap();
     let video = context.video().unwrap();
@@ -27,64 +73,446 @@ fn init_sdl() -> (sdl2::Sdl, sdl2::video::Window) {
 }
 
 fn main() -> Result<(), String> {
-    // Get the rom file from the args
-    let args: Vec<String> = std::env::args().collect();
-    let has_path_arg = args.len() >= 2;
+    // Get the command from the args
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match options::parse_args(&args)? {
+        Command::Run(options) => run(*options),
+        Command::Info(rom_file) => print_rom_info(&rom_file)
+    }
+}
 
-    // If there is no path, return an error
-    if !has_path_arg {
-        return Err(format!("Missing the path to the CHIP-8 ROM file!"));
+// Print what the ROM database knows about a ROM
+fn print_rom_info(rom_file: &str) -> Result<(), String> {
+    let rom = chip8::read_rom_file(rom_file)?;
+
+    let info = match chip8::decode_rom(&rom)? {
+        (_, Some(info)) => info,
+        (program, None) => {
+            println!("{}: unknown ROM (SHA-1 {})", rom_file, chip8::sha1_hex(&program));
+            println!("Recommended settings: {}, {} Hz", Platform::Chip8.name(), chip8::CHIP8_CPU_CLOCK_SPEED);
+            return Ok(());
+        }
+    };
+
+    println!("Title      : {}", info.title);
+    println!("Authors    : {}", info.authors.join(", "));
+    if let Some(release) = &info.release {
+        println!("Release    : {}", release);
+    }
+    if let Some(description) = &info.description {
+        println!("Description: {}", description);
+    }
+    println!("SHA-1      : {}", info.hash);
+    println!("Platform   : {}", info.platform.name());
+    println!("Clock speed: {} Hz", info.clock_speed().unwrap_or(chip8::CHIP8_CPU_CLOCK_SPEED));
+    println!("Quirks     : {:?}", info.quirks);
+    if !info.keys.is_empty() {
+        let keys: Vec<String> = info.keys.iter().map(|(name, key)| format!("{}={:X}", name, key)).collect();
+        println!("Keys       : {}", keys.join(", "));
+    }
+    if !info.colors.is_empty() {
+        let colors: Vec<String> = info.colors.iter()
+            .map(|c| format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2]))
+            .collect();
+        println!("Colors     : {}", colors.join(", "));
     }
 
-    // Init SDL
+    Ok(())
+}
+
+// Run a ROM in a SDL window
+fn run(options: Options) -> Result<(), String> {
+    // The browser only runs in the window, the other modes need a ROM file
+    let other_mode = options.screenshot_at_frame.is_some() || options.terminal.is_some() || options.benchmark.is_some();
+    if other_mode && Path::new(&options.rom_file).is_dir() {
+        return Err(format!("{} is a directory: the browser only runs in the window, give a ROM file", options.rom_file));
+    }
+
+    // Save a screenshot without opening a window
+    if let Some(frame) = options.screenshot_at_frame {
+        return headless::run_screenshot(&options, frame);
+    }
+
+    // Run in the terminal without SDL
+    if let Some(mode) = options.terminal {
+        return terminal::run_terminal(&options, mode);
+    }
+
+    // Init SDL, with the software renderer scaling the screen texture
     let (sdl_context, window) = init_sdl();
-    let mut canvas = window.into_canvas().build().unwrap();
+    let mut canvas = window.into_canvas().software().build().map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    // Get the rom file path
-    let rom_file = args.get(1).cloned().unwrap();
+    // Compare the renderers instead of playing
+    if let Some(frames) = options.benchmark {
+        return benchmark::run_benchmark(&options.rom_file, frames, &mut canvas, &mut event_pump, &texture_creator);
+    }
+
+    // Browse the ROMs of a directory, else play the ROM until the window is closed
+    let video = sdl_context.video()?;
+    if Path::new(&options.rom_file).is_dir() {
+        return browse(&options, &video, &mut canvas, &mut event_pump, &texture_creator);
+    }
+
+    play(&options, &video, &mut canvas, &mut event_pump, &texture_creator).map(|_| ())
+}
+
+// Pick ROMs in the browser and play them, until the browser is closed. The errors of the ROMs are shown in the browser
+fn browse(options: &Options, video: &VideoSubsystem, canvas: &mut WindowCanvas, event_pump: &mut EventPump,
+          texture_creator: &TextureCreator<WindowContext>) -> Result<(), String> {
+    let mut browser = Browser::open(Path::new(&options.rom_file), Path::new(&options.screenshot_dir))?;
+
+    loop {
+        let rom_file = match browser.choose(canvas, texture_creator, event_pump)? {
+            BrowserAction::Play(path) => path.to_string_lossy().to_string(),
+            BrowserAction::Quit => return Ok(())
+        };
+
+        let rom_options = Options { rom_file, ..options.clone() };
+        match play(&rom_options, video, canvas, event_pump, texture_creator) {
+            Ok(Exit::Back) => browser.load_thumbnail(),
+            Ok(Exit::Quit) => return Ok(()),
+            Err(e) => {
+                eprintln!("{}", e);
+                browser.set_message(e);
+            }
+        }
+        canvas.window_mut().set_title(TITLE).map_err(|e| e.to_string())?;
+    }
+}
+
+// Play a ROM in the window, until the player leaves it or closes the window
+fn play(options: &Options, video: &VideoSubsystem, canvas: &mut WindowCanvas, event_pump: &mut EventPump,
+        texture_creator: &TextureCreator<WindowContext>) -> Result<Exit, String> {
+    let mut viewer = if options.viewer { Some(Viewer::open(video)?) } else { None };
+
+    let mut renderer = Renderer::new(texture_creator);
+    let mut phosphor = options.phosphor.map(Phosphor::new);
 
     // Prepare the emulator
-    let mut chip8 = chip8::Chip8::new(Screen::new(), Input::new(&mut event_pump));
+    let mut chip8 = Chip8::new(Screen::new(), Input::new(event_pump));
 
-    // Load the rom file
-    chip8.load_rom_file(&rom_file)?;
+    // Load the rom file, kept for the resets
+    let mut rom = chip8::read_rom_file(&options.rom_file)?;
+    chip8.load_rom(&rom)?;
+    enable_trackers(&mut chip8, options);
+
+    // Use the palette and the key layout from the ROM database
+    let mut palette = select_palette(options, chip8.rom_info())?;
+    if let Some(info) = chip8.rom_info() {
+        let keys = info.keys.clone();
+        chip8.key_input.set_key_layout(&keys);
+    }
 
     // Init chip-8
     chip8.init()?;
+    chip8.screen.require_update = true;
+
+    // Record from the start
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::start(Path::new(path), &palette, &Capture::new(&options.filters, options.record_scale))?),
+        None => None
+    };
+
+    let mut watcher = match options.watch {
+        Some(_) => Some(RomWatcher::new(&options.rom_file)?),
+        None => None
+    };
 
-    // Main loop
+    // Main loop, one iteration per drawn frame
+    let mut pacing = Pacing::new();
+    let mut osd = Osd::new(options.osd);
+    if let Some(notice) = platform_notice(&chip8) {
+        osd.message(notice, Instant::now());
+    }
+
+    // Restore and persist the RPL user flags of the ROM, only in memory when their file fails
+    let mut rpl_flags = RplFlagStore::open().unwrap_or_else(|e| {
+        warn_rpl_flags(&mut osd, e);
+        RplFlagStore::in_memory()
+    });
+    if let Err(e) = rpl_flags.sync(&mut chip8) {
+        warn_rpl_flags(&mut osd, e);
+    }
+
+    let mut frame: u64 = 0;
     loop {
-        // Run a step from chip-8
-        chip8.step()?;
+        let frame_start = Instant::now();
+        let instruction_count = chip8.instruction_count();
+
+        // Run the frames of the iteration, depending on the pause and the speed
+        let mut frames_run = 0;
+        while pacing.should_run_frame(frames_run, frame_start) {
+            frames_run += 1;
+            frame += 1;
+            chip8.run_frame().map_err(|e| finish_after_error(&chip8, options, recorder.take(), e))?;
+
+            if let Some(recorder) = &mut recorder {
+                recorder.add_frame(&chip8.screen.data, &palette)?;
+            }
+
+            // Pause at the breakpoints
+            if let Some(address) = chip8.take_breakpoint_hit() {
+                pacing.pause();
+                osd.message(format!("Breakpoint at {:03X}", address), Instant::now());
+                break;
+            }
+            if let Some(write) = chip8.take_code_write_hit() {
+                pacing.pause();
+                osd.message(format!("Code at {:03X} written by {:03X}", write.address, write.program_counter), Instant::now());
+                break;
+            }
+        }
+
+        if let Err(e) = rpl_flags.sync(&mut chip8) {
+            warn_rpl_flags(&mut osd, e);
+        }
 
-        // Clear the canvas
-        if chip8.screen.require_update {
-            canvas.set_draw_color(Color::RGB(0, 0, 0));
-            canvas.clear();
+        // Upload the screen only when it changed, or while the phosphor is fading during the emulation
+        let fading = frames_run > 0 && phosphor.as_ref().is_some_and(|phosphor| phosphor.is_fading());
+        if chip8.screen.require_update || fading {
+            let image = match &mut phosphor {
+                Some(phosphor) => phosphor.apply(&chip8.screen.data, &palette, frames_run),
+                None => Image::from_framebuffer(&chip8.screen.data, &palette)
+            };
+
+            renderer.update(&filters::apply_filters(&options.filters, image))?;
             chip8.screen.require_update = false;
         }
 
-        // Draw pixels
-        canvas.set_draw_color(Color::RGB(255,255,255));
-        for y in 0..32 {
-            for x in 0..64 {
-                let pixel = chip8.screen.data[(x + y * 64) as usize];
+        osd.add_frame(chip8.instruction_count() - instruction_count, frame_start);
+        let overlay = osd.overlay(chip8.platform().name(), chip8.clock_speed(), pacing.indicator(), frame_start);
+        renderer.present(canvas, &overlay)?;
+
+        if let Some(viewer) = &mut viewer {
+            viewer.update(&chip8.state(), chip8.breakpoints())?;
+        }
 
-                if pixel != 0 {
-                    canvas.fill_rect(Rect::new(x*10, y*10, 10, 10))?;
+        // Handle the hotkeys
+        for hotkey in chip8.key_input.poll_hotkeys() {
+            match hotkey {
+                Hotkey::Quit => {
+                    return finish(&chip8, options, recorder).map(|_| Exit::Quit);
+                }
+                Hotkey::Back => {
+                    return finish(&chip8, options, recorder).map(|_| Exit::Back);
+                }
+                Hotkey::CyclePalette => {
+                    palette = palette.next_preset();
+                    chip8.screen.require_update = true;
+                    canvas.window_mut().set_title(&format!("{} - {}", TITLE, palette.name()))
+                        .map_err(|e| e.to_string())?;
+                    osd.message(format!("Palette {}", palette.name()), Instant::now());
+                }
+                Hotkey::Screenshot => {
+                    let message = take_screenshot(options, &chip8.screen.data, &palette, frame)?;
+                    println!("{}", message);
+                    osd.message(message, Instant::now());
+                }
+                Hotkey::Record => {
+                    let (new_recorder, message) = toggle_recording(options, recorder, &palette, frame)?;
+                    recorder = new_recorder;
+                    println!("{}", message);
+                    osd.message(message, Instant::now());
+                }
+                Hotkey::SoftReset => {
+                    reset(&mut chip8, &rom, false)?;
+                    osd.message("Soft reset".to_string(), Instant::now());
+                }
+                Hotkey::HardReset => {
+                    reset(&mut chip8, &rom, true)?;
+                    osd.message("Hard reset".to_string(), Instant::now());
+                }
+                Hotkey::Pause        => pacing.toggle_pause(),
+                Hotkey::FrameAdvance => pacing.advance_frame(),
+                Hotkey::Slower       => pacing.slower(),
+                Hotkey::Faster       => pacing.faster(),
+                Hotkey::ToggleOsd    => osd.toggle(),
+                Hotkey::ToggleViewer => viewer = match viewer {
+                    Some(_) => None,
+                    None => Some(Viewer::open(video)?)
+                },
+                Hotkey::Scroll(rows) => {
+                    if let Some(viewer) = &mut viewer {
+                        viewer.scroll(rows);
+                    }
+                }
+                // Clicking an address of the viewer sets or clears a breakpoint
+                Hotkey::Click(window_id, x, y) => {
+                    let address = viewer.as_ref()
+                        .filter(|viewer| viewer.window_id() == window_id)
+                        .and_then(|viewer| viewer.address_at(x, y));
+                    if let Some(address) = address {
+                        let state = if chip8.toggle_breakpoint(address) { "set" } else { "cleared" };
+                        osd.message(format!("Breakpoint at {:03X} {}", address, state), Instant::now());
+                    }
+                }
+                // Closing the viewer only closes it, closing the main window quits
+                Hotkey::CloseWindow(window_id) => {
+                    if viewer.as_ref().is_some_and(|viewer| viewer.window_id() == window_id) {
+                        viewer = None;
+                    } else {
+                        return finish(&chip8, options, recorder).map(|_| Exit::Quit);
+                    }
                 }
             }
         }
 
-        // Check if we have to quit
-        if chip8.key_input.check_quit() {
-            break;
+        // Reload the ROM when its file changed, an invalid ROM is shown until it is fixed and the running one goes on
+        if let (Some(watcher), Some(mode)) = (&mut watcher, options.watch) {
+            if watcher.poll(frame_start) {
+                match watcher::reload(&mut chip8, &options.rom_file, mode) {
+                    Ok(new_rom) => {
+                        rom = new_rom;
+                        let keys = chip8.rom_info().map(|info| info.keys.clone()).unwrap_or_default();
+                        chip8.key_input.set_key_layout(&keys);
+                        osd.set_error(None);
+                        osd.message("ROM reloaded".to_string(), Instant::now());
+
+                        // The palette follows the database entry of the new ROM
+                        match select_palette(options, chip8.rom_info()) {
+                            Ok(new_palette) => {
+                                palette = new_palette;
+                                chip8.screen.require_update = true;
+                            }
+                            Err(e) => osd.message(e, Instant::now())
+                        }
+                        if let Some(notice) = platform_notice(&chip8) {
+                            osd.message(notice, Instant::now());
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        osd.set_error(Some(e));
+                    }
+                }
+            }
+        }
+
+        // Wait for the next frame
+        if let Some(remaining) = pacing.frame_duration().checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
         }
+    }
+}
+
+// Reload the ROM and init the emulator again, a hard reset also clears the memory
+fn reset<S, I>(chip8: &mut Chip8<S, I>, rom: &[u8], hard: bool) -> Result<(), String> where S: Display, I: KeyInput {
+    chip8.reset(hard);
+    chip8.load_rom(rom)?;
+    chip8.init()
+}
+
+// Enable the trackers of --profile, --coverage, --code-writes and --break-on-code-write
+fn enable_trackers<S, I>(chip8: &mut Chip8<S, I>, options: &Options) where S: Display, I: KeyInput {
+    if options.profile.is_some() {
+        chip8.enable_profiler();
+    }
+    if options.coverage.is_some() {
+        chip8.enable_coverage();
+    }
+    if options.code_writes.is_some() || options.break_on_code_write {
+        chip8.enable_code_write_tracking(options.break_on_code_write);
+    }
+}
+
+// Stop the recording, and write the reports of --profile, --coverage and --code-writes, when quitting and after an
+// emulation error
+fn finish<S, I>(chip8: &Chip8<S, I>, options: &Options, recorder: Option<Recorder>) -> Result<(), String> where S: Display, I: KeyInput {
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+
+    if let (Some(path), Some((report, collapsed_stacks))) = (&options.profile, chip8.profile()) {
+        let path = Path::new(path);
+        std::fs::write(path, report).map_err(|e| format!("Cannot write the profile {}: {}", path.display(), e))?;
+
+        let folded = path.with_extension("folded");
+        std::fs::write(&folded, collapsed_stacks).map_err(|e| format!("Cannot write the profile {}: {}", folded.display(), e))?;
+        println!("Profile written to {} and {}", path.display(), folded.display());
+    }
 
-        canvas.present();
+    if let (Some(path), Some(report)) = (&options.coverage, chip8.coverage_report()) {
+        std::fs::write(path, report).map_err(|e| format!("Cannot write the coverage {}: {}", path, e))?;
+        println!("Coverage written to {}", path);
+    }
+
+    if let (Some(path), Some(report)) = (&options.code_writes, chip8.code_write_report()) {
+        std::fs::write(path, report).map_err(|e| format!("Cannot write the code writes {}: {}", path, e))?;
+        println!("Code writes written to {}", path);
     }
 
     Ok(())
 }
 
+// Stop the recording and write the reports after an emulation error, which stays the first error reported
+fn finish_after_error<S, I>(chip8: &Chip8<S, I>, options: &Options, recorder: Option<Recorder>, error: String) -> String where S: Display, I: KeyInput {
+    match finish(chip8, options, recorder) {
+        Ok(()) => error,
+        Err(e) => format!("{}\n{}", error, e)
+    }
+}
+
+// Tell when an unknown ROM too big for the 4 KB of CHIP-8 runs as XO-CHIP
+fn platform_notice<S, I>(chip8: &Chip8<S, I>) -> Option<String> where S: Display, I: KeyInput {
+    let guessed = chip8.rom_info().is_none() && chip8.platform() == Platform::XoChip &&
+                  chip8.rom_size() > Platform::Chip8.max_rom_size();
+    guessed.then(|| format!("Unknown ROM of {} bytes, run as XO-CHIP", chip8.rom_size()))
+}
+
+// Use the palette from the options, else the colors from the ROM database
+fn select_palette(options: &Options, rom_info: Option<&RomInfo>) -> Result<Palette, String> {
+    match (&options.palette, rom_info) {
+        (Some(palette), _) => Ok(palette.clone()),
+        (None, Some(info)) if info.colors.len() >= 2 => Palette::custom(&info.title, &info.colors),
+        _ => Ok(Palette::default())
+    }
+}
+
+// The RPL user flags are kept in memory when their file fails, which is not fatal
+fn warn_rpl_flags(osd: &mut Osd, error: String) {
+    let message = format!("{}, the RPL user flags are only kept in memory", error);
+    eprintln!("{}", message);
+    osd.message(message, Instant::now());
+}
+
+// Save a screenshot of the framebuffer in the screenshot directory, and get the message to show
+fn take_screenshot(options: &Options, framebuffer: &[u8], palette: &Palette, frame: u64) -> Result<String, String> {
+    let path = screenshot::capture_path(Path::new(&options.screenshot_dir), &options.rom_file, frame, "png");
+    screenshot::save_screenshot(framebuffer, palette, &Capture::new(&options.filters, options.screenshot_scale), &path)?;
+
+    Ok(format!("Screenshot saved to {}", path.display()))
+}
+
+// Stop the recording, or start recording to the screenshot directory in the format of --record (GIF by default),
+// and get the message to show
+fn toggle_recording(options: &Options, recorder: Option<Recorder>, palette: &Palette, frame: u64) -> Result<(Option<Recorder>, String), String> {
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+        return Ok((None, "Recording stopped".to_string()));
+    }
+
+    let extension = options.record.as_ref()
+        .and_then(|path| Path::new(path).extension()?.to_str())
+        .unwrap_or("gif");
+    let path = screenshot::capture_path(Path::new(&options.screenshot_dir), &options.rom_file, frame, extension);
+
+    let recorder = Recorder::start(&path, palette, &Capture::new(&options.filters, options.record_scale))?;
+    Ok((Some(recorder), format!("Recording to {}", path.display())))
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::RGB(rgb[0], rgb[1], rgb[2])
+}
+
+// Unit tests
+// Empty directory of a test, unique to the process running it
+#[cfg(test)]
+fn test_directory(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("rust-chip-8-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
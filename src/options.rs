@@ -0,0 +1,238 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Command line options
+//************************************************************************
+
+use crate::filters::{Filter, MAX_SCALE};
+use crate::palette::Palette;
+use crate::recorder::RECORD_DEFAULT_SCALE;
+use crate::screenshot::SCREENSHOT_DEFAULT_SCALE;
+use crate::terminal::{TerminalMode, TERMINAL_DEFAULT_KEY_HOLD};
+use crate::watcher::WatchMode;
+
+use std::time::Duration;
+
+const USAGE: &str = "Usage: rust-chip-8 [info] [options] [<rom file or directory>]
+
+The ROM file can be a zip archive containing the ROM, or - to read it from the standard input.
+With a directory, or without ROM file for the current directory, a browser lists the ROMs to play in the window.
+
+Options:
+    --benchmark <frames>  Compare the instruction throughput of the renderers, uncapped
+    --break-on-code-write <on|off>
+                          Pause after an instruction writing into the code already executed
+    --code-writes <file>  Write the regions of self-modifying code and the log of the writes to a file
+                          when quitting or on an emulation error
+    --config <file>       Read options from a file, one \"name = value\" per line
+    --coverage <file>     Write an annotated disassembly of the bytes executed, read and written to a file
+                          when quitting or on an emulation error
+    --filters <filters>   Post-processing filters applied in order: nearest<N> (N from 1 to 16), scale2x,
+                          scale3x, epx, scanlines, crt (e.g. scale3x,scanlines), up to 16 times the screen,
+                          also applied to the screenshots and the recordings
+    --key-hold <ms>       In the terminal, time a key stays pressed after its last press or repeat
+                          (default: 300)
+    --osd <on|off>        Show the frame rate, the instruction rate, the platform and the messages (F4)
+    --palette <palette>   classic, green, amber, octo or a list of colors (#000000,#FFFFFF)
+    --phosphor <frames>   Fade the erased pixels out, with a half-life in frames
+    --profile <file>      Write the hotspots, opcode classes and subroutines to a file when quitting or on an
+                          emulation error, and the collapsed stacks to the same file with the .folded extension
+    --record <file>       Record the gameplay from the start, as .gif or .y4m
+    --record-scale <scale>
+                          Scale of the recordings, from 1 to 16 (default: 4)
+    --screenshot-at-frame <frame>
+                          Run without a window, and save a screenshot at this frame
+    --screenshot-dir <directory>
+                          Directory of the screenshots and recordings (default: current directory)
+    --screenshot-scale <scale>
+                          Scale of the screenshots, from 1 to 16 (default: 10)
+    --terminal <mode>     Run in the terminal instead of a window: halfblock or braille
+    --viewer <on|off>     Show the registers, the timers, the stack and the memory in a second window (F9)
+    --watch <mode>        Reload the ROM when its file changes: reset the machine (reset) or keep the registers
+                          V0-VF and I (keep)";
+
+// What the emulator has been asked to do
+pub enum Command {
+    // Run a ROM
+    Run(Box<Options>),
+
+    // Print what the ROM database knows about a ROM
+    Info(String)
+}
+
+// Options used to run a ROM
+#[derive(Clone)]
+pub struct Options {
+    pub rom_file           : String,
+    pub palette            : Option<Palette>,
+    pub phosphor           : Option<f32>,
+    pub filters            : Vec<Filter>,
+    pub benchmark          : Option<u32>,
+    pub screenshot_at_frame: Option<u64>,
+    pub screenshot_dir     : String,
+    pub screenshot_scale   : usize,
+    pub record             : Option<String>,
+    pub record_scale       : usize,
+    pub terminal           : Option<TerminalMode>,
+    pub key_hold           : Duration,
+    pub osd                : bool,
+    pub viewer             : bool,
+    pub profile            : Option<String>,
+    pub coverage           : Option<String>,
+    pub code_writes        : Option<String>,
+    pub break_on_code_write: bool,
+    pub watch              : Option<WatchMode>
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rom_file           : String::new(),
+            palette            : None,
+            phosphor           : None,
+            filters            : Vec::new(),
+            benchmark          : None,
+            screenshot_at_frame: None,
+            screenshot_dir     : ".".to_string(),
+            screenshot_scale   : SCREENSHOT_DEFAULT_SCALE,
+            record             : None,
+            record_scale       : RECORD_DEFAULT_SCALE,
+            terminal           : None,
+            key_hold           : Duration::from_millis(TERMINAL_DEFAULT_KEY_HOLD),
+            osd                : false,
+            viewer             : false,
+            profile            : None,
+            coverage           : None,
+            code_writes        : None,
+            break_on_code_write: false,
+            watch              : None
+        }
+    }
+}
+
+impl Options {
+    // Set an option from its name and value
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "palette"             => self.palette = Some(Palette::parse(value)?),
+            "phosphor"            => self.phosphor = Some(parse_number(name, value)?),
+            "filters"             => self.filters = Filter::parse_list(value)?,
+            "benchmark"           => self.benchmark = Some(parse_number(name, value)?),
+            "screenshot-at-frame" => self.screenshot_at_frame = Some(parse_number(name, value)?),
+            "screenshot-dir"      => self.screenshot_dir = value.to_string(),
+            "screenshot-scale"    => self.screenshot_scale = parse_scale(name, value)?,
+            "record"              => self.record = Some(value.to_string()),
+            "record-scale"        => self.record_scale = parse_scale(name, value)?,
+            "terminal"            => self.terminal = Some(TerminalMode::parse(value)?),
+            "key-hold"            => self.key_hold = Duration::from_millis(parse_number(name, value)?),
+            "osd"                 => self.osd = parse_switch(name, value)?,
+            "viewer"              => self.viewer = parse_switch(name, value)?,
+            "profile"             => self.profile = Some(value.to_string()),
+            "coverage"            => self.coverage = Some(value.to_string()),
+            "code-writes"         => self.code_writes = Some(value.to_string()),
+            "break-on-code-write" => self.break_on_code_write = parse_switch(name, value)?,
+            "watch"               => self.watch = Some(WatchMode::parse(value)?),
+            _                     => return Err(format!("Unknown option --{}\n{}", name, USAGE))
+        }
+
+        Ok(())
+    }
+}
+
+// Parse the command line arguments (without the executable name)
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args.next().ok_or(format!("Missing value for --{}\n{}", name, USAGE))?;
+
+                if name == "config" {
+                    read_config_file(value, &mut options)?;
+                } else {
+                    options.set(name, value)?;
+                }
+            }
+            None => positional.push(arg.clone())
+        }
+    }
+
+    match &positional[..] {
+        [command, rom_file] if command == "info" => Ok(Command::Info(rom_file.clone())),
+        [rom_file] => {
+            options.rom_file = rom_file.clone();
+            Ok(Command::Run(Box::new(options)))
+        }
+        // Without ROM, the browser lists the current directory, in a window
+        [] if options.terminal.is_none() && options.screenshot_at_frame.is_none() && options.benchmark.is_none() => {
+            options.rom_file = ".".to_string();
+            Ok(Command::Run(Box::new(options)))
+        }
+        _  => Err(format!("Invalid arguments!\n{}", USAGE))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid number for --{}: {}", name, value))
+}
+
+fn parse_scale(name: &str, value: &str) -> Result<usize, String> {
+    Some(parse_number(name, value)?)
+        .filter(|scale| (1..=MAX_SCALE).contains(scale))
+        .ok_or(format!("Invalid scale for --{}: {} (1 to {})", name, value, MAX_SCALE))
+}
+
+fn parse_switch(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on"  => Ok(true),
+        "off" => Ok(false),
+        _     => Err(format!("Invalid value for --{}: {} (on or off)", name, value))
+    }
+}
+
+// Read options from a config file, empty lines and lines starting with # are ignored
+fn read_config_file(path: &str, options: &mut Options) -> Result<(), String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|_| format!("Impossible to read the config file {}", path))?;
+
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (name, value) = line.split_once('=')
+            .ok_or(format!("Invalid line in the config file {}: {}", path, line))?;
+
+        options.set(name.trim(), value.trim())?;
+    }
+
+    Ok(())
+}
+
+// Unit tests
+#[test]
+fn test_parse_scale() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: The scales are bounded
+    //--------------------------------------------------------------------
+    assert_eq!(parse_scale("record-scale", "16"), Ok(16), "A scale of 16 should be accepted");
+    assert!(parse_scale("record-scale", "0").is_err(), "A scale should be at least 1");
+    assert_eq!(parse_scale("screenshot-scale", "1000"), Err("Invalid scale for --screenshot-scale: 1000 (1 to 16)".to_string()),
+               "A scale overflowing the images should be rejected");
+}
+
+#[test]
+fn test_parse_args_without_rom() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Only the window browses the current directory
+    //--------------------------------------------------------------------
+    let args = |args: &[&str]| parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+
+    match args(&["--osd", "on"]) {
+        Ok(Command::Run(options)) => assert_eq!(options.rom_file, ".", "The window should browse the current directory"),
+        _ => panic!("The window should run without ROM")
+    }
+    for mode in [&["--terminal", "braille"], &["--screenshot-at-frame", "60"], &["--benchmark", "600"]] {
+        assert_eq!(args(mode).err(), Some(format!("Invalid arguments!\n{}", USAGE)), "{} should need a ROM", mode[0]);
+    }
+}
@@ -0,0 +1,176 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// On-screen display: frame rate, instruction rate, platform and messages
+//************************************************************************
+
+use std::time::{Duration, Instant};
+
+// Time between two updates of the rates, and time a message stays on screen
+const OSD_RATE_PERIOD     : Duration = Duration::from_secs(1);
+const OSD_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+const OSD_MAX_MESSAGES    : usize = 4;
+
+// Texts drawn over the screen, line by line from each corner
+#[derive(Debug, Default, PartialEq)]
+pub struct Overlay {
+    pub top_left   : Vec<String>,
+    pub top_right  : Vec<String>,
+    pub bottom_left: Vec<String>
+}
+
+pub struct Osd {
+    enabled: bool,
+
+    // Counters since the start of the current period
+    period_start       : Instant,
+    period_frames      : u64,
+    period_instructions: u64,
+
+    // Rates of the last period
+    fps: f64,
+    ips: f64,
+
+    // Messages, with the time they were posted
+    messages: Vec<(String, Instant)>,
+
+    // Error shown until it is cleared, even when the OSD is disabled
+    error: Option<String>
+}
+
+impl Osd {
+    pub fn new(enabled: bool) -> Self {
+        Osd {
+            enabled,
+            period_start       : Instant::now(),
+            period_frames      : 0,
+            period_instructions: 0,
+            fps                : 0.0,
+            ips                : 0.0,
+            messages           : Vec::new(),
+            error              : None
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Count a drawn frame and the instructions executed since the last one
+    pub fn add_frame(&mut self, instructions: u64, now: Instant) {
+        self.period_frames += 1;
+        self.period_instructions += instructions;
+
+        let elapsed = now.duration_since(self.period_start);
+        if elapsed >= OSD_RATE_PERIOD {
+            self.fps = self.period_frames as f64 / elapsed.as_secs_f64();
+            self.ips = self.period_instructions as f64 / elapsed.as_secs_f64();
+            self.period_start = now;
+            self.period_frames = 0;
+            self.period_instructions = 0;
+        }
+    }
+
+    // Show a message for a few seconds
+    pub fn message(&mut self, message: String, now: Instant) {
+        self.messages.push((message, now));
+        if self.messages.len() > OSD_MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // Show an error below the messages until it is replaced or cleared with None
+    pub fn set_error(&mut self, error: Option<String>) {
+        self.error = error;
+    }
+
+    // Texts to draw, the pacing indicator and the error are shown even when the OSD is disabled
+    pub fn overlay(&mut self, platform: &str, clock_speed: u32, indicator: Option<String>, now: Instant) -> Overlay {
+        self.messages.retain(|(_, time)| now.duration_since(*time) < OSD_MESSAGE_DURATION);
+
+        let mut overlay = Overlay {
+            top_right: indicator.into_iter().collect(),
+            ..Overlay::default()
+        };
+
+        if self.enabled {
+            overlay.top_left = vec![
+                format!("FPS {:.0}", self.fps),
+                format!("IPS {:.0}/{}", self.ips, clock_speed),
+                platform.to_string()
+            ];
+            overlay.bottom_left = self.messages.iter().map(|(message, _)| message.clone()).collect();
+        }
+        overlay.bottom_left.extend(self.error.clone());
+
+        overlay
+    }
+}
+
+// Unit tests
+#[test]
+fn test_osd_rates() {
+    //--------------------------------------------------------------------
+    // Setup: Draw 60 frames of 10 instructions in a second
+    //--------------------------------------------------------------------
+    let start = Instant::now();
+    let mut osd = Osd::new(true);
+    osd.period_start = start;
+
+    //--------------------------------------------------------------------
+    // Execute: Add the frames
+    //--------------------------------------------------------------------
+    for frame in 1..=60 {
+        osd.add_frame(10, start + OSD_RATE_PERIOD * frame / 60);
+    }
+    let overlay = osd.overlay("CHIP-8", 600, Some("x2".to_string()), start + OSD_RATE_PERIOD);
+
+    //--------------------------------------------------------------------
+    // Verify: The rates of the last second are shown
+    //--------------------------------------------------------------------
+    assert_eq!(overlay.top_left, vec!["FPS 60", "IPS 600/600", "CHIP-8"], "The rates should be shown");
+    assert_eq!(overlay.top_right, vec!["x2"], "The indicator should be shown");
+}
+
+#[test]
+fn test_osd_messages() {
+    //--------------------------------------------------------------------
+    // Setup: Post a message
+    //--------------------------------------------------------------------
+    let start = Instant::now();
+    let mut osd = Osd::new(true);
+    osd.message("State saved to slot 2".to_string(), start);
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The message expires, and is hidden with the OSD
+    //--------------------------------------------------------------------
+    assert_eq!(osd.overlay("CHIP-8", 500, None, start).bottom_left, vec!["State saved to slot 2"],
+               "The message should be shown");
+
+    osd.toggle();
+    assert_eq!(osd.overlay("CHIP-8", 500, None, start), Overlay::default(), "Nothing should be shown when disabled");
+
+    osd.toggle();
+    assert!(osd.overlay("CHIP-8", 500, None, start + OSD_MESSAGE_DURATION).bottom_left.is_empty(),
+            "The message should expire");
+}
+
+#[test]
+fn test_osd_error() {
+    //--------------------------------------------------------------------
+    // Setup: Set an error with the OSD disabled
+    //--------------------------------------------------------------------
+    let start = Instant::now();
+    let mut osd = Osd::new(false);
+    osd.set_error(Some("Invalid ROM".to_string()));
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The error is shown until it is cleared
+    //--------------------------------------------------------------------
+    assert_eq!(osd.overlay("CHIP-8", 500, None, start + OSD_MESSAGE_DURATION).bottom_left, vec!["Invalid ROM"],
+               "The error should be shown without the OSD, and should not expire");
+
+    osd.set_error(None);
+    assert_eq!(osd.overlay("CHIP-8", 500, None, start), Overlay::default(), "The error should be cleared");
+}
@@ -0,0 +1,154 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Emulation pacing: pause, frame advance, slow motion and fast-forward
+//************************************************************************
+
+use std::time::{Duration, Instant};
+
+use crate::chip8::CHIP8_FRAME_DURATION;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Speed {
+    // One emulated frame every N frames
+    Slow(u32),
+    Normal,
+    // N emulated frames per frame
+    Fast(u32),
+    // As many emulated frames as possible, the screen is still drawn at 60 Hz
+    Uncapped
+}
+
+// Speeds, in the order they are cycled
+const SPEEDS: [Speed; 7] = [Speed::Slow(4), Speed::Slow(2), Speed::Normal, Speed::Fast(2), Speed::Fast(4),
+                            Speed::Fast(8), Speed::Uncapped];
+const NORMAL_SPEED: usize = 2;
+
+pub struct Pacing {
+    paused : bool,
+    advance: bool,
+    speed  : usize
+}
+
+impl Pacing {
+    pub fn new() -> Self {
+        Pacing {
+            paused : false,
+            advance: false,
+            speed  : NORMAL_SPEED
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    // Pause, and run a single frame
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn speed(&self) -> Speed {
+        SPEEDS[self.speed]
+    }
+
+    // Whether another frame should be emulated before drawing the screen,
+    // knowing how many frames have been emulated since the start of the frame
+    pub fn should_run_frame(&mut self, frames_run: u32, frame_start: Instant) -> bool {
+        if self.paused {
+            return std::mem::take(&mut self.advance);
+        }
+
+        match self.speed() {
+            Speed::Slow(_) | Speed::Normal => frames_run < 1,
+            Speed::Fast(factor)            => frames_run < factor,
+            Speed::Uncapped                => frames_run < 1 || frame_start.elapsed() < CHIP8_FRAME_DURATION
+        }
+    }
+
+    // Time between two draws of the screen
+    pub fn frame_duration(&self) -> Duration {
+        match self.speed() {
+            Speed::Slow(factor) if !self.paused => CHIP8_FRAME_DURATION * factor,
+            _ => CHIP8_FRAME_DURATION
+        }
+    }
+
+    // Text showing the state and the speed, nothing at normal speed
+    pub fn indicator(&self) -> Option<String> {
+        if self.paused {
+            return Some("PAUSED".to_string());
+        }
+
+        match self.speed() {
+            Speed::Slow(factor) => Some(format!("x1/{}", factor)),
+            Speed::Normal       => None,
+            Speed::Fast(factor) => Some(format!("x{}", factor)),
+            Speed::Uncapped     => Some("UNCAPPED".to_string())
+        }
+    }
+}
+
+// Unit tests
+#[test]
+fn test_pause_and_advance() {
+    //--------------------------------------------------------------------
+    // Setup: Pause
+    //--------------------------------------------------------------------
+    let mut pacing = Pacing::new();
+    pacing.toggle_pause();
+    let start = Instant::now();
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: No frame while paused, a single one when advancing
+    //--------------------------------------------------------------------
+    assert!(!pacing.should_run_frame(0, start), "No frame should run while paused");
+    pacing.advance_frame();
+    assert!(pacing.should_run_frame(0, start), "A frame should run when advancing");
+    assert!(!pacing.should_run_frame(0, start), "A single frame should run when advancing");
+    assert_eq!(pacing.indicator().as_deref(), Some("PAUSED"), "The indicator should show the pause");
+
+    pacing.toggle_pause();
+    assert!(pacing.should_run_frame(0, start), "A frame should run once resumed");
+    assert!(!pacing.should_run_frame(1, start), "A single frame should run at normal speed");
+    assert_eq!(pacing.indicator(), None, "Nothing should be shown at normal speed");
+}
+
+#[test]
+fn test_speeds() {
+    //--------------------------------------------------------------------
+    // Setup: Go one step faster and one step slower than normal
+    //--------------------------------------------------------------------
+    let mut fast = Pacing::new();
+    fast.faster();
+    let mut slow = Pacing::new();
+    slow.slower();
+    let start = Instant::now();
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: Frames per draw and time between draws
+    //--------------------------------------------------------------------
+    assert!(fast.should_run_frame(1, start) && !fast.should_run_frame(2, start), "x2 should run 2 frames per draw");
+    assert_eq!(fast.indicator().as_deref(), Some("x2"), "The indicator should show x2");
+    assert_eq!(slow.frame_duration(), CHIP8_FRAME_DURATION * 2, "x1/2 should draw every other frame");
+    assert_eq!(slow.indicator().as_deref(), Some("x1/2"), "The indicator should show x1/2");
+
+    for _ in 0..SPEEDS.len() {
+        fast.faster();
+    }
+    assert_eq!(fast.speed(), Speed::Uncapped, "The fastest speed should be uncapped");
+}
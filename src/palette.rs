@@ -0,0 +1,134 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Color palettes
+//************************************************************************
+
+use crate::chip8::parse_color;
+
+pub type Rgb = [u8; 3];
+
+// Max number of colors, for 4 bitplanes
+pub const PALETTE_MAX_COLORS: usize = 16;
+
+// Named presets, in the order they are cycled
+const PRESETS: [(&str, &[Rgb]); 4] = [
+    ("classic", &[[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]]),
+    ("green",   &[[0x0A, 0x14, 0x0A], [0x33, 0xFF, 0x66]]),
+    ("amber",   &[[0x14, 0x0C, 0x00], [0xFF, 0xB0, 0x00]]),
+    ("octo",    &[[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]])
+];
+
+// Colors used to draw the framebuffer, the index of a color is the bitplane mask of a pixel
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    name  : String,
+    colors: Vec<Rgb>
+}
+
+impl Palette {
+    // Get a preset from its name
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS.iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(preset, colors)| Palette { name: preset.to_string(), colors: colors.to_vec() })
+    }
+
+    // Create a palette from a list of colors (background first)
+    pub fn custom(name: &str, colors: &[Rgb]) -> Result<Self, String> {
+        if colors.len() < 2 || colors.len() > PALETTE_MAX_COLORS {
+            return Err(format!("A palette needs 2 to {} colors ({} given)", PALETTE_MAX_COLORS, colors.len()));
+        }
+
+        Ok(Palette { name: name.to_string(), colors: colors.to_vec() })
+    }
+
+    // Parse a preset name or a comma separated list of "#RRGGBB" colors
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Some(palette) = Palette::preset(value) {
+            return Ok(palette);
+        }
+
+        let colors = value.split(',')
+            .map(|color| parse_color(color.trim()).ok_or(format!("Invalid color {}", color)))
+            .collect::<Result<Vec<Rgb>, String>>()?;
+
+        Palette::custom("custom", &colors)
+    }
+
+    // The next preset, used to cycle the palettes at runtime
+    pub fn next_preset(&self) -> Self {
+        let index = PRESETS.iter()
+            .position(|(preset, _)| *preset == self.name)
+            .map_or(0, |index| (index + 1) % PRESETS.len());
+
+        Palette::preset(PRESETS[index].0).unwrap()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    // Get the index of the color of a pixel from its bitplane mask
+    pub fn index(&self, pixel: u8) -> usize {
+        let index = (pixel as usize) % PALETTE_MAX_COLORS;
+
+        // Palettes with less colors than planes draw every plane with the last color
+        index.min(self.colors.len() - 1)
+    }
+
+    // Get the color of a pixel from its bitplane mask
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[self.index(pixel)]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::preset(PRESETS[0].0).unwrap()
+    }
+}
+
+// Unit tests
+#[test]
+fn test_parse_palette() {
+    //--------------------------------------------------------------------
+    // Execute: Parse a preset and a custom palette
+    //--------------------------------------------------------------------
+    let amber = Palette::parse("amber").unwrap();
+    let custom = Palette::parse("#000000, #FF0000,00FF00").unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: Colors are indexed by the bitplane mask
+    //--------------------------------------------------------------------
+    assert_eq!(amber.name(), "amber", "The preset should be amber");
+    assert_eq!(custom.background(), [0, 0, 0], "The background should be black");
+    assert_eq!(custom.color(1), [0xFF, 0, 0], "Plane 1 should be red");
+    assert_eq!(custom.color(2), [0, 0xFF, 0], "Plane 2 should be green");
+    assert_eq!(custom.color(3), [0, 0xFF, 0], "Missing colors should use the last color");
+    assert!(Palette::parse("#000000").is_err(), "A palette needs at least 2 colors");
+}
+
+#[test]
+fn test_next_preset() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: Cycling goes through every preset and back
+    //--------------------------------------------------------------------
+    let mut palette = Palette::default();
+    for _ in 0..PRESETS.len() {
+        palette = palette.next_preset();
+    }
+
+    assert_eq!(palette, Palette::default(), "Cycling should go back to the first preset");
+    assert_eq!(Palette::parse("#000000,#FFFFFF").unwrap().next_preset().name(), "classic",
+               "A custom palette should cycle to the first preset");
+}
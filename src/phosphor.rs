@@ -0,0 +1,111 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Phosphor persistence: pixels fade out over several frames, to reduce
+// the flicker of the XOR-drawn sprites
+//************************************************************************
+
+use crate::image::Image;
+use crate::palette::{Palette, Rgb};
+
+pub struct Phosphor {
+    // Frames for the pixels to lose half of their intensity
+    half_life: f32,
+
+    // Color currently displayed for each pixel, and the color it fades to
+    colors : Vec<[f32; 3]>,
+    targets: Vec<Rgb>
+}
+
+impl Phosphor {
+    // Pixels lose half of their intensity every half_life frames
+    pub fn new(half_life: f32) -> Self {
+        Phosphor {
+            half_life: half_life.max(0.01),
+            colors   : Vec::new(),
+            targets  : Vec::new()
+        }
+    }
+
+    // Apply the persistence to the framebuffer drawn by the emulator, after the 60 Hz frames run since the last call
+    pub fn apply(&mut self, framebuffer: &[u8], palette: &Palette, frames: u32) -> Image {
+        let target = Image::from_framebuffer(framebuffer, palette);
+        let background = to_f32(palette.background());
+        let decay = 0.5f32.powf(frames as f32 / self.half_life);
+
+        // Start from the first frame
+        if self.colors.len() != target.pixels.len() {
+            self.colors = target.pixels.iter().map(|&color| to_f32(color)).collect();
+        }
+
+        for (color, (&pixel, &lit)) in self.colors.iter_mut().zip(framebuffer.iter().zip(target.pixels.iter())) {
+            if pixel != 0 {
+                // Lit pixels are displayed at once
+                *color = to_f32(lit);
+            } else {
+                // Others fade to the background
+                for channel in 0..3 {
+                    color[channel] = background[channel] + (color[channel] - background[channel]) * decay;
+                }
+            }
+        }
+
+        let image = Image {
+            width : target.width,
+            height: target.height,
+            pixels: self.colors.iter().map(|color| [color[0].round() as u8, color[1].round() as u8, color[2].round() as u8]).collect()
+        };
+        self.targets = target.pixels;
+        image
+    }
+
+    // Whether some pixels are still fading, and the image must be updated even if the framebuffer did not change
+    pub fn is_fading(&self) -> bool {
+        self.colors.iter().zip(self.targets.iter())
+            .any(|(color, &target)| (0..3).any(|channel| (color[channel] - target[channel] as f32).abs() >= 0.5))
+    }
+}
+
+fn to_f32(color: Rgb) -> [f32; 3] {
+    [color[0] as f32, color[1] as f32, color[2] as f32]
+}
+
+// Unit tests
+#[test]
+fn test_phosphor_fade() {
+    //--------------------------------------------------------------------
+    // Setup: Light a pixel with a half-life of 2 frames
+    //--------------------------------------------------------------------
+    let palette = Palette::default();
+    let mut phosphor = Phosphor::new(2.0);
+    let mut framebuffer = [0u8; 2048];
+    framebuffer[0] = 1;
+    phosphor.apply(&framebuffer, &palette, 1);
+
+    //--------------------------------------------------------------------
+    // Execute: Erase the pixel and run 2 frames, the first one paused, and 2 frames at once
+    //--------------------------------------------------------------------
+    framebuffer[0] = 0;
+    phosphor.apply(&framebuffer, &palette, 0);
+    phosphor.apply(&framebuffer, &palette, 1);
+    let image = phosphor.apply(&framebuffer, &palette, 1);
+    let mut fast_phosphor = Phosphor::new(2.0);
+    fast_phosphor.apply(&[1; 2048], &palette, 1);
+    let fast_image = fast_phosphor.apply(&framebuffer, &palette, 2);
+
+    //--------------------------------------------------------------------
+    // Verify: The pixel is at half intensity and still fading
+    //--------------------------------------------------------------------
+    assert!((127..=128).contains(&image.pixels[0][0]), "The pixel should be at half intensity");
+    assert!((127..=128).contains(&fast_image.pixels[0][0]), "The fade should follow the frames run, not the calls");
+    assert_eq!(image.pixels[1], [0, 0, 0], "Other pixels should stay off");
+    assert!(phosphor.is_fading(), "The pixel should still be fading");
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: A lit pixel is not fading
+    //--------------------------------------------------------------------
+    framebuffer[0] = 1;
+    phosphor.apply(&framebuffer, &palette, 1);
+    assert!(!phosphor.is_fading(), "A lit pixel should not be fading");
+}
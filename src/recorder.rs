@@ -0,0 +1,295 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Gameplay recording, one capture per 60 Hz frame:
+// - GIF: up to 256 colors, identical frames merged, only the changed area stored
+// - Y4M: raw YUV 4:4:4 stream, for external encoders
+//************************************************************************
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::filters::Capture;
+use crate::image::Image;
+use crate::palette::{Palette, Rgb};
+
+pub const RECORD_DEFAULT_SCALE: usize = 4;
+
+pub enum Recorder {
+    Gif(GifRecorder),
+    Y4m(Y4mRecorder)
+}
+
+impl Recorder {
+    // Start a recording, the format comes from the extension of the file (.gif or .y4m)
+    pub fn start(path: &Path, palette: &Palette, capture: &Capture) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Impossible to create the file {}: {}", path.display(), e))?;
+        let writer = BufWriter::new(file);
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gif") => Ok(Recorder::Gif(GifRecorder::new(writer, palette, capture.clone())?)),
+            Some("y4m") => Ok(Recorder::Y4m(Y4mRecorder::new(writer, capture.clone())?)),
+            _ => Err(format!("Unknown recording format for {}, use .gif or .y4m", path.display()))
+        }
+    }
+
+    // Capture the framebuffer, should be called once per 60 Hz frame
+    pub fn add_frame(&mut self, framebuffer: &[u8], palette: &Palette) -> Result<(), String> {
+        match self {
+            Recorder::Gif(recorder) => recorder.add_frame(framebuffer, palette),
+            Recorder::Y4m(recorder) => recorder.add_frame(framebuffer, palette)
+        }
+    }
+
+    // Write the end of the recording
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            Recorder::Gif(recorder) => recorder.finish(),
+            Recorder::Y4m(recorder) => recorder.finish()
+        }
+    }
+}
+
+//-------------------------- GIF --------------------------
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    capture: Capture,
+    width  : usize,
+
+    // Colors used so far, starting with the palette of the start of the recording which is the global palette. The
+    // frames with other colors, such as the ones created by the filters, have all of them in a local palette
+    colors       : HashMap<Rgb, u8>,
+    color_table  : Vec<u8>,
+    global_colors: usize,
+
+    // Color indices of the last written frame
+    written: Option<Vec<u8>>,
+
+    // Frame waiting for the next different one, and for how many frames it is displayed
+    pending       : Option<Vec<u8>>,
+    pending_frames: u64,
+
+    // Number of frames written, to compute the delays without drifting
+    written_frames: u64
+}
+
+impl GifRecorder {
+    fn new(writer: BufWriter<File>, palette: &Palette, capture: Capture) -> Result<Self, String> {
+        let (width, height) = capture.size();
+        let color_table: Vec<u8> = palette.colors().iter().flatten().copied().collect();
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &color_table)
+            .map_err(|e| format!("Impossible to write the GIF: {}", e))?;
+        encoder.set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| format!("Impossible to write the GIF: {}", e))?;
+
+        let mut colors = HashMap::new();
+        for (index, &color) in palette.colors().iter().enumerate() {
+            colors.entry(color).or_insert(index as u8);
+        }
+
+        Ok(GifRecorder {
+            encoder,
+            capture,
+            width,
+            colors,
+            color_table,
+            global_colors : palette.colors().len(),
+            written       : None,
+            pending       : None,
+            pending_frames: 0,
+            written_frames: 0
+        })
+    }
+
+    fn add_frame(&mut self, framebuffer: &[u8], palette: &Palette) -> Result<(), String> {
+        let image = self.capture.apply(framebuffer, palette);
+        let mut indices = Vec::with_capacity(image.pixels.len());
+        for color in image.pixels {
+            let index = match self.colors.get(&color) {
+                Some(&index) => index,
+                None => {
+                    let index = self.color_table.len() / 3;
+                    if index > u8::MAX as usize {
+                        return Err("Too many colors for a GIF, record a .y4m with these filters".to_string());
+                    }
+
+                    self.colors.insert(color, index as u8);
+                    self.color_table.extend_from_slice(&color);
+                    index as u8
+                }
+            };
+            indices.push(index);
+        }
+
+        // Identical frames only make the pending frame last longer
+        if self.pending.as_ref() == Some(&indices) {
+            self.pending_frames += 1;
+            return Ok(());
+        }
+
+        self.write_pending()?;
+        self.pending = Some(indices);
+        self.pending_frames = 1;
+
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> Result<(), String> {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(())
+        };
+
+        // Only store the area which changed since the last written frame
+        let (left, top, right, bottom) = match &self.written {
+            Some(written) => changed_area(written, &pending, self.width).unwrap_or((0, 0, 1, 1)),
+            None => (0, 0, self.width, pending.len() / self.width)
+        };
+
+        let (width, height) = (right - left, bottom - top);
+        let mut buffer = Vec::with_capacity(width * height);
+        for y in top..bottom {
+            buffer.extend_from_slice(&pending[left + y * self.width..right + y * self.width]);
+        }
+
+        // GIF delays are in 1/100 s, round the end time of the frame to avoid drifting
+        let start = self.written_frames * 100 / 60;
+        self.written_frames += self.pending_frames;
+        let delay = self.written_frames * 100 / 60 - start;
+
+        let local_palette = Some(self.color_table.clone()).filter(|table| table.len() > self.global_colors * 3);
+        let mut frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, buffer, None);
+        frame.palette = local_palette;
+        frame.left    = left as u16;
+        frame.top     = top as u16;
+        frame.delay = delay.min(u16::MAX as u64) as u16;
+
+        self.encoder.write_frame(&frame).map_err(|e| format!("Impossible to write the GIF: {}", e))?;
+        self.written = Some(pending);
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.write_pending()?;
+        self.encoder.into_inner()
+            .and_then(|mut writer| writer.flush().map_err(Into::into))
+            .map_err(|e| format!("Impossible to write the GIF: {}", e))
+    }
+}
+
+// Bounding box (left, top, right, bottom) of the pixels which differ between two frames
+fn changed_area(before: &[u8], after: &[u8], width: usize) -> Option<(usize, usize, usize, usize)> {
+    let mut area: Option<(usize, usize, usize, usize)> = None;
+    for (index, _) in before.iter().zip(after.iter()).enumerate().filter(|(_, (b, a))| b != a) {
+        let (x, y) = (index % width, index / width);
+        area = Some(match area {
+            Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1)),
+            None => (x, y, x + 1, y + 1)
+        });
+    }
+
+    area
+}
+
+//-------------------------- Y4M --------------------------
+pub struct Y4mRecorder {
+    writer : BufWriter<File>,
+    capture: Capture
+}
+
+impl Y4mRecorder {
+    fn new(mut writer: BufWriter<File>, capture: Capture) -> Result<Self, String> {
+        let (width, height) = capture.size();
+        writeln!(writer, "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444", width, height)
+            .map_err(|e| format!("Impossible to write the Y4M: {}", e))?;
+
+        Ok(Y4mRecorder { writer, capture })
+    }
+
+    fn add_frame(&mut self, framebuffer: &[u8], palette: &Palette) -> Result<(), String> {
+        let Image { pixels, .. } = self.capture.apply(framebuffer, palette);
+
+        // BT.601 limited range, one plane after the other
+        let mut frame = Vec::with_capacity(6 + pixels.len() * 3);
+        frame.extend_from_slice(b"FRAME\n");
+        for plane in 0..3 {
+            frame.extend(pixels.iter().map(|&[r, g, b]| {
+                let (r, g, b) = (r as i32, g as i32, b as i32);
+                let value = match plane {
+                    0 => 16 + ((66 * r + 129 * g + 25 * b + 128) >> 8),
+                    1 => 128 + ((-38 * r - 74 * g + 112 * b + 128) >> 8),
+                    _ => 128 + ((112 * r - 94 * g - 18 * b + 128) >> 8)
+                };
+
+                value as u8
+            }));
+        }
+
+        self.writer.write_all(&frame).map_err(|e| format!("Impossible to write the Y4M: {}", e))
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.writer.flush().map_err(|e| format!("Impossible to write the Y4M: {}", e))
+    }
+}
+
+// Unit tests
+#[test]
+fn test_changed_area() {
+    //--------------------------------------------------------------------
+    // Setup: Change 2 pixels
+    //--------------------------------------------------------------------
+    let before = vec![0u8; 64 * 32];
+    let mut after = before.clone();
+    after[3 + 2 * 64] = 1;
+    after[10 + 5 * 64] = 1;
+
+    //--------------------------------------------------------------------
+    // Execute and Verify: The area contains both pixels
+    //--------------------------------------------------------------------
+    assert_eq!(changed_area(&before, &after, 64), Some((3, 2, 11, 6)), "The area should contain both pixels");
+    assert_eq!(changed_area(&before, &before, 64), None, "Identical frames have no changed area");
+}
+
+#[test]
+fn test_record_gif() {
+    //--------------------------------------------------------------------
+    // Setup: Record 60 identical frames, then 30 frames with a lit pixel
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("record");
+    let path = directory.join("record.gif");
+    let palette = Palette::default();
+    let mut framebuffer = [0u8; 2048];
+    let mut recorder = Recorder::start(&path, &palette, &Capture::new(&[], 2)).unwrap();
+
+    for _ in 0..60 {
+        recorder.add_frame(&framebuffer, &palette).unwrap();
+    }
+    framebuffer[65] = 1;
+    for _ in 0..30 {
+        recorder.add_frame(&framebuffer, &palette).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    //--------------------------------------------------------------------
+    // Execute: Decode the GIF
+    //--------------------------------------------------------------------
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        frames.push((frame.delay, frame.left, frame.top, frame.width, frame.height));
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: 2 frames, the second one only stores the lit pixel
+    //--------------------------------------------------------------------
+    assert_eq!(frames, vec![(100, 0, 0, 128, 64), (50, 2, 2, 2, 2)], "Identical frames should be merged");
+}
@@ -0,0 +1,89 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// SDL renderer, uploading the screen image to a streaming texture
+//************************************************************************
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use crate::font::{self, FONT_HEIGHT};
+use crate::image::Image;
+use crate::osd::Overlay;
+
+// Size of a font pixel in the window, margin around the texts and height of a line
+pub const TEXT_SCALE      : u32 = 2;
+pub const TEXT_MARGIN     : i32 = 6;
+pub const TEXT_LINE_HEIGHT: i32 = (FONT_HEIGHT as i32 + 3) * TEXT_SCALE as i32;
+
+pub struct Renderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture        : Option<Texture<'a>>
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+        Renderer {
+            texture_creator,
+            texture: None
+        }
+    }
+
+    // Upload the image to the texture, which is created again when the image size changes
+    pub fn update(&mut self, image: &Image) -> Result<(), String> {
+        let size = (image.width as u32, image.height as u32);
+        let texture = match self.texture.take() {
+            Some(texture) if (texture.query().width, texture.query().height) == size => texture,
+            _ => self.texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, size.0, size.1)
+                .map_err(|e| e.to_string())?
+        };
+
+        let texture = self.texture.insert(texture);
+        texture.update(None, &image.to_rgb24(), image.width * 3).map_err(|e| e.to_string())
+    }
+
+    // Draw the texture, scaled to the whole window, and the overlay texts in the corners
+    pub fn present(&self, canvas: &mut WindowCanvas, overlay: &Overlay) -> Result<(), String> {
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        if let Some(texture) = &self.texture {
+            canvas.copy(texture, None, None)?;
+        }
+
+        let (width, height) = canvas.output_size()?;
+        for (line, text) in overlay.top_left.iter().enumerate() {
+            draw_text(canvas, text, TEXT_MARGIN, TEXT_MARGIN + line as i32 * TEXT_LINE_HEIGHT, Color::WHITE, Color::BLACK)?;
+        }
+        for (line, text) in overlay.top_right.iter().enumerate() {
+            let x = width as i32 - font::text_size(text).0 as i32 * TEXT_SCALE as i32 - TEXT_MARGIN;
+            draw_text(canvas, text, x, TEXT_MARGIN + line as i32 * TEXT_LINE_HEIGHT, Color::WHITE, Color::BLACK)?;
+        }
+        for (line, text) in overlay.bottom_left.iter().rev().enumerate() {
+            let y = height as i32 - TEXT_MARGIN - FONT_HEIGHT as i32 * TEXT_SCALE as i32 - line as i32 * TEXT_LINE_HEIGHT;
+            draw_text(canvas, text, TEXT_MARGIN, y, Color::WHITE, Color::BLACK)?;
+        }
+
+        canvas.present();
+
+        Ok(())
+    }
+}
+
+// Draw a text over a box of the background color, from its top left corner in the window
+pub fn draw_text(canvas: &mut WindowCanvas, text: &str, x: i32, y: i32, color: Color, background: Color) -> Result<(), String> {
+    let (width, height) = font::text_size(text);
+    let scale = TEXT_SCALE as i32;
+
+    canvas.set_draw_color(background);
+    canvas.fill_rect(Rect::new(x - scale, y - scale, (width as u32 + 2) * TEXT_SCALE, (height as u32 + 2) * TEXT_SCALE))?;
+
+    let pixels: Vec<Rect> = font::text_pixels(text).iter()
+        .map(|&(px, py)| Rect::new(x + px as i32 * scale, y + py as i32 * scale, TEXT_SCALE, TEXT_SCALE))
+        .collect();
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&pixels)
+}
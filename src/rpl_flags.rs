@@ -0,0 +1,173 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// RPL user flags of the ROMs, kept by SHA-1 in a JSON file of the user
+// data directory so the high scores saved with FX75 survive restarts. The
+// file is replaced at once, and the flags stay in memory when it fails
+//************************************************************************
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::chip8::{Chip8, Display, KeyInput};
+
+const RPL_FLAGS_DIRECTORY: &str = "rust-chip-8";
+const RPL_FLAGS_FILE     : &str = "rpl-flags.json";
+
+pub struct RplFlagStore {
+    // File of the flags, None to keep them in memory only
+    path : Option<PathBuf>,
+    flags: BTreeMap<String, Vec<u8>>,
+
+    // ROM whose flags are in the emulator
+    rom_hash: Option<String>
+}
+
+impl RplFlagStore {
+    // Open the flags of the user data directory, e.g. ~/.local/share/rust-chip-8/rpl-flags.json on Linux
+    pub fn open() -> Result<Self, String> {
+        let directory = dirs::data_dir().ok_or("No user data directory to save the RPL user flags")?;
+        Self::open_file(&directory.join(RPL_FLAGS_DIRECTORY).join(RPL_FLAGS_FILE))
+    }
+
+    // Open a file of flags, which is created with the first save
+    pub fn open_file(path: &Path) -> Result<Self, String> {
+        let flags = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid RPL user flags {}: {}", path.display(), e))?,
+            Err(_) => BTreeMap::new()
+        };
+
+        Ok(RplFlagStore { path: Some(path.to_path_buf()), flags, rom_hash: None })
+    }
+
+    // Flags lost when quitting, when their file cannot be used
+    pub fn in_memory() -> Self {
+        RplFlagStore { path: None, flags: BTreeMap::new(), rom_hash: None }
+    }
+
+    // Should be called once per frame: restore the flags of a newly loaded ROM, and write the flags saved by FX75.
+    // After an error the flags are only kept in memory, so that it is reported once
+    pub fn sync<S, I>(&mut self, chip8: &mut Chip8<S, I>) -> Result<(), String> where S: Display, I: KeyInput {
+        if self.rom_hash.as_deref() != Some(chip8.rom_hash()) {
+            let hash = chip8.rom_hash().to_string();
+            chip8.set_rpl_flags(self.flags.get(&hash).map(Vec::as_slice).unwrap_or_default());
+            self.rom_hash = Some(hash);
+        }
+
+        if !chip8.take_rpl_flags_saved() {
+            return Ok(());
+        }
+
+        // The flags are written only when they change, as some ROMs save them at every frame
+        let flags = chip8.rpl_flags().to_vec();
+        if self.flags.get(chip8.rom_hash()) == Some(&flags) {
+            return Ok(());
+        }
+        self.flags.insert(chip8.rom_hash().to_string(), flags);
+
+        if let Some(path) = &self.path {
+            if let Err(e) = write_flags(path, &self.flags) {
+                self.path = None;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Write the flags to a temporary file renamed over the file, which is never left half written
+fn write_flags(path: &Path, flags: &BTreeMap<String, Vec<u8>>) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Impossible to create the directory {}: {}", directory.display(), e))?;
+    }
+
+    let json = serde_json::to_string_pretty(flags).map_err(|e| e.to_string())?;
+    let temporary = path.with_extension("json.tmp");
+    std::fs::write(&temporary, json)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .map_err(|e| format!("Cannot write the RPL user flags {}: {}", path.display(), e))
+}
+
+// Unit tests
+#[test]
+fn test_rpl_flag_store() {
+    //--------------------------------------------------------------------
+    // Setup: A ROM saving V0-V1 in the flags, then loading them back
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("rpl-flags");
+    let path = directory.join(RPL_FLAGS_DIRECTORY).join(RPL_FLAGS_FILE);
+
+    let rom = [0x60, 0x2A, 0x61, 0x07, 0xF1, 0x75, 0x12, 0x06];
+    let new_chip8 = || {
+        let mut chip8 = Chip8::new(crate::display_input::Screen::new(), crate::headless::NoInput);
+        chip8.load_rom(&rom).unwrap();
+        chip8.init().unwrap();
+        chip8
+    };
+
+    //--------------------------------------------------------------------
+    // Execute: Run the ROM and sync the flags, then restore them in a new emulator
+    //--------------------------------------------------------------------
+    let mut chip8 = new_chip8();
+    let mut store = RplFlagStore::open_file(&path).unwrap();
+    store.sync(&mut chip8).unwrap();
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    store.sync(&mut chip8).unwrap();
+
+    let mut restored = new_chip8();
+    RplFlagStore::open_file(&path).unwrap().sync(&mut restored).unwrap();
+    let json = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The flags are written by ROM hash, and restored
+    //--------------------------------------------------------------------
+    let hash = crate::chip8::sha1_hex(&rom);
+    let flags: BTreeMap<String, Vec<u8>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(flags.get(&hash).map(|flags| &flags[0..3]), Some(&[0x2A, 0x07, 0x00][..]),
+               "The flags should be saved by hash");
+    assert_eq!(&restored.rpl_flags()[0..3], &[0x2A, 0x07, 0x00], "The flags should be restored");
+    assert!(RplFlagStore::open_file(&directory.join("missing.json")).is_ok(), "A missing file should have no flags");
+}
+
+#[test]
+fn test_rpl_flag_store_failure() {
+    //--------------------------------------------------------------------
+    // Setup: A ROM saving an increasing V0 in the flags, and a store whose
+    // directory is a file
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("rpl-flags-failure");
+    std::fs::write(directory.join("file"), "").unwrap();
+    let mut store = RplFlagStore::open_file(&directory.join("file").join(RPL_FLAGS_FILE)).unwrap();
+
+    let mut chip8 = Chip8::new(crate::display_input::Screen::new(), crate::headless::NoInput);
+    chip8.load_rom(&[0x70, 0x01, 0xF0, 0x75, 0x12, 0x00]).unwrap();
+    chip8.init().unwrap();
+
+    //--------------------------------------------------------------------
+    // Execute: Sync the flags after each save
+    //--------------------------------------------------------------------
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        results.push(store.sync(&mut chip8).is_ok());
+    }
+    std::fs::write(directory.join(RPL_FLAGS_FILE), "{").unwrap();
+    let invalid = RplFlagStore::open_file(&directory.join(RPL_FLAGS_FILE)).is_err();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The error is reported once, and the flags are kept in memory
+    //--------------------------------------------------------------------
+    assert_eq!(results, vec![false, true], "The write error should be reported once");
+    assert_eq!(store.path, None, "The store should keep the flags in memory after an error");
+    assert_eq!(store.flags.get(chip8.rom_hash()).map(|flags| flags[0]), Some(2), "The last flags should be kept");
+    assert!(invalid, "An invalid file should be an error, for the caller to keep the flags in memory");
+}
@@ -0,0 +1,160 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Screenshots, saved as PNG
+//************************************************************************
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::filters::Capture;
+use crate::image::Image;
+use crate::palette::Palette;
+
+pub const SCREENSHOT_DEFAULT_SCALE: usize = 10;
+
+// Color, filter and scale the framebuffer, and save it as PNG
+pub fn save_screenshot(framebuffer: &[u8], palette: &Palette, capture: &Capture, path: &Path) -> Result<(), String> {
+    save_png(&capture.apply(framebuffer, palette), path)
+}
+
+// Path of a screenshot or a recording: <directory>/<rom name>-<frame>.<extension>, "capture" for the standard input
+pub fn capture_path(directory: &Path, rom_file: &str, frame: u64, extension: &str) -> PathBuf {
+    let rom_name = Path::new(rom_file).file_stem().and_then(|name| name.to_str()).filter(|_| rom_file != "-").unwrap_or("capture");
+    directory.join(format!("{}-{}.{}", rom_name, frame, extension))
+}
+
+// Latest capture of a ROM in a directory, from the modification time then the frame
+pub fn latest_capture(directory: &Path, rom_file: &str, extension: &str) -> Option<PathBuf> {
+    let rom_name = Path::new(rom_file).file_stem()?.to_str()?;
+
+    std::fs::read_dir(directory).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = path.file_name()?.to_str()?;
+            let frame: u64 = name.strip_prefix(rom_name)?.strip_prefix('-')?.strip_suffix(extension)?.strip_suffix('.')?.parse().ok()?;
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok()?;
+            Some(((modified, frame), path))
+        })
+        .max_by_key(|(key, _)| *key)
+        .map(|(_, path)| path)
+}
+
+pub fn save_png(image: &Image, path: &Path) -> Result<(), String> {
+    let file = File::create(path)
+        .map_err(|e| format!("Impossible to create the file {}: {}", path.display(), e))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.to_rgb24()))
+        .map_err(|e| format!("Impossible to write the PNG {}: {}", path.display(), e))
+}
+
+// Read a PNG with 8 bits RGB or RGBA pixels, such as the screenshots
+pub fn load_png(path: &Path) -> Result<Image, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Impossible to open the file {}: {}", path.display(), e))?;
+
+    let mut reader = png::Decoder::new(BufReader::new(file)).read_info()
+        .map_err(|e| format!("Impossible to read the PNG {}: {}", path.display(), e))?;
+    let mut data = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let info = reader.next_frame(&mut data)
+        .map_err(|e| format!("Impossible to read the PNG {}: {}", path.display(), e))?;
+
+    let channels = match (info.color_type, info.bit_depth) {
+        (png::ColorType::Rgb, png::BitDepth::Eight)  => 3,
+        (png::ColorType::Rgba, png::BitDepth::Eight) => 4,
+        _ => return Err(format!("Unsupported PNG {}: {:?} with {:?} bits", path.display(), info.color_type, info.bit_depth))
+    };
+
+    Ok(Image {
+        width : info.width as usize,
+        height: info.height as usize,
+        pixels: data[..info.buffer_size()].chunks_exact(channels).map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect()
+    })
+}
+
+// Unit tests
+#[test]
+fn test_save_screenshot() {
+    //--------------------------------------------------------------------
+    // Setup: Light the top left pixel, and the one below
+    //--------------------------------------------------------------------
+    let mut framebuffer = [0u8; 2048];
+    framebuffer[0] = 1;
+    framebuffer[64] = 1;
+    let directory = crate::test_directory("screenshot");
+    let path = directory.join("screenshot.png");
+
+    //--------------------------------------------------------------------
+    // Execute: Save the screenshot at scale 2, with scanlines
+    //--------------------------------------------------------------------
+    let capture = Capture::new(&[crate::filters::Filter::Scanlines], 2);
+    save_screenshot(&framebuffer, &Palette::parse("amber").unwrap(), &capture, &path).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: Decode the PNG
+    //--------------------------------------------------------------------
+    let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut data).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!((info.width, info.height), (128, 64), "The screenshot should be scaled");
+    assert_eq!(&data[0..3], &[0xFF, 0xB0, 0x00], "The lit pixel should use the palette");
+    assert_eq!(&data[6..9], &[0x14, 0x0C, 0x00], "The other pixels should use the background");
+    assert_ne!(&data[2 * 128 * 3..2 * 128 * 3 + 3], &[0xFF, 0xB0, 0x00], "The scanline should darken the second row of the screen");
+}
+
+#[test]
+fn test_capture_path() {
+    //--------------------------------------------------------------------
+    // Execute and Verify: The path uses the ROM name and the frame
+    //--------------------------------------------------------------------
+    assert_eq!(capture_path(Path::new("shots"), "roms/PONG.ch8", 42, "png"), Path::new("shots/PONG-42.png"),
+               "The path should be shots/PONG-42.png");
+    assert_eq!(capture_path(Path::new("shots"), "-", 42, "png"), Path::new("shots/capture-42.png"),
+               "The ROM read from the standard input should have no name");
+}
+
+#[test]
+fn test_latest_capture() {
+    //--------------------------------------------------------------------
+    // Setup: Screenshots of two ROMs, the frame 30 of PONG taken after the frame 120
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("latest-capture");
+
+    let mut framebuffer = [0u8; 2048];
+    framebuffer[0] = 1;
+    let palette = Palette::parse("amber").unwrap();
+    let times = [("PONG-120.png", 100), ("PONG-30.png", 200), ("PONG2-500.png", 300), ("PONG-abc.png", 400)];
+    for (name, seconds) in times {
+        let path = directory.join(name);
+        save_screenshot(&framebuffer, &palette, &Capture::new(&[], 1), &path).unwrap();
+        File::options().write(true).open(&path).unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds)).unwrap();
+    }
+
+    //--------------------------------------------------------------------
+    // Execute: Find the latest screenshot of PONG, and load it
+    //--------------------------------------------------------------------
+    let latest = latest_capture(&directory, "roms/PONG.ch8", "png");
+    let image = load_png(&directory.join("PONG-30.png")).unwrap();
+    let missing = latest_capture(&directory, "roms/TETRIS.ch8", "png");
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The last screenshot of PONG is the frame 30, and it has the pixels saved
+    //--------------------------------------------------------------------
+    assert_eq!(latest, Some(directory.join("PONG-30.png")), "The most recent screenshot of PONG should be found");
+    assert_eq!(missing, None, "A ROM without screenshots should have no capture");
+    assert_eq!((image.width, image.height), (64, 32), "The PNG should have the size of the screen");
+    assert_eq!((image.get(0, 0), image.get(1, 0)), ([0xFF, 0xB0, 0x00], [0x14, 0x0C, 0x00]), "The PNG should have the colors saved");
+}
@@ -0,0 +1,526 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Terminal frontend, drawing the screen with Unicode characters and ANSI colors
+//************************************************************************
+
+use std::io::{stdout, Stdout, Write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, MoveToNextLine, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+                       KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::chip8::{self, Chip8, Display, KeyInput};
+use crate::display_input::Hotkey;
+use crate::filters::Capture;
+use crate::image::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::options::Options;
+use crate::palette::{Palette, Rgb};
+use crate::recorder::Recorder;
+use crate::pacing::Pacing;
+use crate::rpl_flags::RplFlagStore;
+use crate::watcher::{self, RomWatcher};
+use crate::{enable_trackers, finish, finish_after_error, platform_notice, reset, select_palette, take_screenshot, toggle_recording};
+
+pub const TERMINAL_DEFAULT_KEY_HOLD: u64 = 300;
+
+// Braille dot of each pixel of a 2x4 cell, by row then column
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminalMode {
+    // One character for 1x2 pixels, 64x16 characters
+    HalfBlock,
+    // One character for 2x4 pixels, 32x8 characters, with a single color per character
+    Braille
+}
+
+impl TerminalMode {
+    // Parse a mode name: "halfblock" or "braille"
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "halfblock" => Ok(TerminalMode::HalfBlock),
+            "braille"   => Ok(TerminalMode::Braille),
+            _ => Err(format!("Unknown terminal mode {}, use halfblock or braille", name))
+        }
+    }
+}
+
+// A character of the terminal, with its foreground and background colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cell {
+    character : char,
+    foreground: Rgb,
+    background: Rgb
+}
+
+// Convert the framebuffer to rows of characters
+fn to_cells(framebuffer: &[u8], palette: &Palette, mode: TerminalMode) -> Vec<Vec<Cell>> {
+    let pixel = |x: usize, y: usize| framebuffer[x + y * SCREEN_WIDTH];
+
+    match mode {
+        // The upper half block is drawn with the top pixel, and its background with the bottom one
+        TerminalMode::HalfBlock => (0..SCREEN_HEIGHT).step_by(2)
+            .map(|y| (0..SCREEN_WIDTH)
+                .map(|x| Cell {
+                    character : '▀',
+                    foreground: palette.color(pixel(x, y)),
+                    background: palette.color(pixel(x, y + 1))
+                })
+                .collect())
+            .collect(),
+
+        // The dots are drawn with the color of the highest bitplane mask of the cell
+        TerminalMode::Braille => (0..SCREEN_HEIGHT).step_by(4)
+            .map(|y| (0..SCREEN_WIDTH).step_by(2)
+                .map(|x| {
+                    let mut dots = 0;
+                    let mut mask = 0;
+                    for (row, row_dots) in BRAILLE_DOTS.iter().enumerate() {
+                        for (column, dot) in row_dots.iter().enumerate() {
+                            let value = pixel(x + column, y + row);
+                            if value != 0 {
+                                dots |= dot;
+                                mask = mask.max(value);
+                            }
+                        }
+                    }
+
+                    Cell {
+                        character : char::from_u32(0x2800 + dots as u32).unwrap(),
+                        foreground: palette.color(mask),
+                        background: palette.background()
+                    }
+                })
+                .collect())
+            .collect()
+    }
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::Rgb { r: rgb[0], g: rgb[1], b: rgb[2] }
+}
+
+//-------------------------- DISPLAY --------------------------
+pub struct TerminalScreen {
+    pub data          : [u8; 2048],
+    pub require_update: bool,
+    mode              : TerminalMode,
+    stdout            : Stdout,
+
+    // Last message, shown under the indicator as printing would scroll the screen
+    status: String
+}
+
+impl TerminalScreen {
+    pub fn new(mode: TerminalMode) -> Self {
+        TerminalScreen {
+            data          : [0; 2048],
+            require_update: true,
+            mode,
+            stdout        : stdout(),
+            status        : String::new()
+        }
+    }
+
+    // Show a message until the next one
+    pub fn set_status(&mut self, message: String) {
+        self.status = message;
+        self.require_update = true;
+    }
+
+    // Draw the screen in the top left corner of the terminal, and the indicator and the status below it,
+    // colors are only sent when they change
+    pub fn present(&mut self, palette: &Palette, indicator: Option<&str>) -> Result<(), String> {
+        let mut colors: Option<(Rgb, Rgb)> = None;
+
+        queue!(self.stdout, MoveTo(0, 0)).map_err(|e| e.to_string())?;
+        for row in to_cells(&self.data, palette, self.mode) {
+            for cell in row {
+                if colors != Some((cell.foreground, cell.background)) {
+                    queue!(self.stdout, SetForegroundColor(to_color(cell.foreground)),
+                                        SetBackgroundColor(to_color(cell.background)))
+                        .map_err(|e| e.to_string())?;
+                    colors = Some((cell.foreground, cell.background));
+                }
+                queue!(self.stdout, Print(cell.character)).map_err(|e| e.to_string())?;
+            }
+            queue!(self.stdout, ResetColor, MoveToNextLine(1)).map_err(|e| e.to_string())?;
+            colors = None;
+        }
+        queue!(self.stdout, Clear(ClearType::CurrentLine), Print(indicator.unwrap_or("")), MoveToNextLine(1),
+                            Clear(ClearType::CurrentLine), Print(&self.status))
+            .map_err(|e| e.to_string())?;
+
+        self.require_update = false;
+        self.stdout.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl Display for TerminalScreen {
+    fn draw(&mut self, pixels: [u8; 2048]) {
+        self.data = pixels;
+        self.require_update = true;
+    }
+}
+
+//-------------------------- INPUT --------------------------
+// Terminals usually only report key presses, a key is considered held until the hold timeout
+// after its last press or repeat, or until its release when the terminal reports it
+pub struct TerminalInput {
+    key_hold   : Duration,
+    key_layout : Vec<(KeyCode, u8)>,
+    last_press : [Option<Instant>; 16],
+
+    // Key pressed during the last poll, waiting to be read by FX0A
+    pending_key: Option<u8>
+}
+
+impl TerminalInput {
+    pub fn new(key_hold: Duration) -> Self {
+        TerminalInput {
+            key_hold,
+            key_layout : Vec::new(),
+            last_press : [None; 16],
+            pending_key: None
+        }
+    }
+
+    // Map the arrows, space and enter to CHIP-8 keys, from the ROM database
+    pub fn set_key_layout(&mut self, keys: &[(String, u8)]) {
+        self.key_layout = keys.iter()
+            .filter_map(|(name, key)| {
+                let code = match name.as_str() {
+                    "up"    => KeyCode::Up,
+                    "down"  => KeyCode::Down,
+                    "left"  => KeyCode::Left,
+                    "right" => KeyCode::Right,
+                    "a"     => KeyCode::Char(' '),
+                    "b"     => KeyCode::Enter,
+                    _       => return None
+                };
+
+                Some((code, *key & 0x0F))
+            })
+            .collect();
+    }
+
+    // Get the CHIP-8 key of a key code: the hexadecimal digits, then the key layout
+    fn get_key_from_code(&self, code: KeyCode) -> Option<u8> {
+        match code {
+            KeyCode::Char(c) if c.is_ascii_hexdigit() => c.to_digit(16).map(|key| key as u8),
+            _ => self.key_layout.iter().find(|(k, _)| *k == code).map(|(_, key)| *key)
+        }
+    }
+
+    // Update the keys from a key event, and get the hotkey it triggers
+    fn handle_key_event(&mut self, event: KeyEvent) -> Option<Hotkey> {
+        let released = event.kind == KeyEventKind::Release;
+
+        // Ctrl+C quits, before C is read as a CHIP-8 key
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            return if released { None } else { Some(Hotkey::Quit) };
+        }
+
+        if let Some(key) = self.get_key_from_code(event.code) {
+            self.last_press[key as usize] = if released { None } else { Some(Instant::now()) };
+            if event.kind == KeyEventKind::Press {
+                self.pending_key = Some(key);
+            }
+            return None;
+        }
+
+        if released {
+            return None;
+        }
+
+        match event.code {
+            KeyCode::Esc => Some(Hotkey::Quit),
+            KeyCode::F(1)  => Some(Hotkey::CyclePalette),
+            KeyCode::F(2)  => Some(Hotkey::SoftReset),
+            KeyCode::F(3)  => Some(Hotkey::HardReset),
+            KeyCode::F(5)  => Some(Hotkey::Pause),
+            KeyCode::F(6)  => Some(Hotkey::FrameAdvance),
+            KeyCode::F(7)  => Some(Hotkey::Slower),
+            KeyCode::F(8)  => Some(Hotkey::Faster),
+            KeyCode::F(10) => Some(Hotkey::Record),
+            KeyCode::F(12) => Some(Hotkey::Screenshot),
+            _ => None
+        }
+    }
+
+    // Read the pending terminal events, and get the frontend hotkeys pressed since the last call
+    pub fn poll_hotkeys(&mut self) -> Result<Vec<Hotkey>, String> {
+        // A key which has not been read by FX0A during the frame is not waited for anymore
+        self.pending_key = None;
+
+        let mut hotkeys = Vec::new();
+        while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+            if let Event::Key(key_event) = event::read().map_err(|e| e.to_string())? {
+                hotkeys.extend(self.handle_key_event(key_event));
+            }
+        }
+
+        Ok(hotkeys)
+    }
+}
+
+impl KeyInput for TerminalInput {
+    fn is_key_pressed(&self, key: u8) -> bool {
+        self.last_press[key as usize].is_some_and(|time| time.elapsed() < self.key_hold)
+    }
+
+    // The keys are read between the frames, the key pressed during the last frame is used
+    fn get_key(&mut self) -> Option<u8> {
+        self.pending_key.take()
+    }
+}
+
+//-------------------------- RUN --------------------------
+// Raw mode and alternate screen, restored when dropped
+struct TerminalGuard {
+    keyboard_enhancement: bool
+}
+
+impl TerminalGuard {
+    fn enter() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+
+        // Ask for the key releases when the terminal supports it
+        let keyboard_enhancement = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        let mut stdout = stdout();
+        execute!(stdout, EnterAlternateScreen, Hide).map_err(|e| e.to_string())?;
+        if keyboard_enhancement {
+            execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(TerminalGuard { keyboard_enhancement })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = stdout();
+        if self.keyboard_enhancement {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Run a ROM in the terminal
+pub fn run_terminal(options: &Options, mode: TerminalMode) -> Result<(), String> {
+    let mut chip8 = Chip8::new(TerminalScreen::new(mode), TerminalInput::new(options.key_hold));
+    let mut rom = chip8::read_rom_file(&options.rom_file)?;
+    chip8.load_rom(&rom)?;
+    enable_trackers(&mut chip8, options);
+
+    // Use the palette and the key layout from the ROM database
+    let mut palette = select_palette(options, chip8.rom_info())?;
+    if let Some(info) = chip8.rom_info() {
+        let keys = info.keys.clone();
+        chip8.key_input.set_key_layout(&keys);
+    }
+
+    chip8.init()?;
+    if let Some(notice) = platform_notice(&chip8) {
+        chip8.screen.set_status(notice);
+    }
+
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::start(Path::new(path), &palette, &Capture::new(&options.filters, options.record_scale))?),
+        None => None
+    };
+
+    let mut watcher = match options.watch {
+        Some(_) => Some(RomWatcher::new(&options.rom_file)?),
+        None => None
+    };
+
+    // Restore and persist the RPL user flags of the ROM, only in memory when their file fails
+    let mut rpl_flags = RplFlagStore::open().unwrap_or_else(|e| {
+        chip8.screen.set_status(format!("{}, the RPL user flags are only kept in memory", e));
+        RplFlagStore::in_memory()
+    });
+    if let Err(e) = rpl_flags.sync(&mut chip8) {
+        chip8.screen.set_status(format!("{}, the RPL user flags are only kept in memory", e));
+    }
+
+    let guard = TerminalGuard::enter()?;
+
+    // Main loop, one iteration per drawn frame
+    let mut pacing = Pacing::new();
+    let mut indicator = None;
+    let mut frame: u64 = 0;
+    loop {
+        let frame_start = Instant::now();
+
+        let mut frames_run = 0;
+        while pacing.should_run_frame(frames_run, frame_start) {
+            frames_run += 1;
+            frame += 1;
+            chip8.run_frame().map_err(|e| finish_after_error(&chip8, options, recorder.take(), e))?;
+
+            if let Some(recorder) = &mut recorder {
+                recorder.add_frame(&chip8.screen.data, &palette)?;
+            }
+
+            // Pause after the writes into the code with --break-on-code-write
+            if let Some(write) = chip8.take_code_write_hit() {
+                pacing.pause();
+                chip8.screen.set_status(format!("Code at {:03X} written by {:03X}", write.address, write.program_counter));
+                break;
+            }
+        }
+
+        if let Err(e) = rpl_flags.sync(&mut chip8) {
+            chip8.screen.set_status(format!("{}, the RPL user flags are only kept in memory", e));
+        }
+
+        if chip8.screen.require_update || pacing.indicator() != indicator {
+            indicator = pacing.indicator();
+            chip8.screen.present(&palette, indicator.as_deref())?;
+        }
+
+        for hotkey in chip8.key_input.poll_hotkeys()? {
+            match hotkey {
+                // The reports are printed once the terminal is restored
+                Hotkey::Quit | Hotkey::Back => {
+                    drop(guard);
+                    return finish(&chip8, options, recorder);
+                }
+                Hotkey::CyclePalette => {
+                    palette = palette.next_preset();
+                    chip8.screen.require_update = true;
+                }
+                Hotkey::Screenshot   => {
+                    let message = take_screenshot(options, &chip8.screen.data, &palette, frame)?;
+                    chip8.screen.set_status(message);
+                }
+                Hotkey::Record       => {
+                    let (new_recorder, message) = toggle_recording(options, recorder, &palette, frame)?;
+                    recorder = new_recorder;
+                    chip8.screen.set_status(message);
+                }
+                Hotkey::SoftReset    => reset(&mut chip8, &rom, false)?,
+                Hotkey::HardReset    => reset(&mut chip8, &rom, true)?,
+                Hotkey::Pause        => pacing.toggle_pause(),
+                Hotkey::FrameAdvance => pacing.advance_frame(),
+                Hotkey::Slower       => pacing.slower(),
+                Hotkey::Faster       => pacing.faster(),
+                // The OSD and the viewer are only available with a window
+                Hotkey::ToggleOsd | Hotkey::ToggleViewer | Hotkey::Scroll(_) | Hotkey::CloseWindow(_) | Hotkey::Click(..) => {}
+            }
+        }
+
+        // Reload the ROM when its file changed, an invalid ROM is reported and the running one goes on
+        if let (Some(watcher), Some(mode)) = (&mut watcher, options.watch) {
+            if watcher.poll(frame_start) {
+                match watcher::reload(&mut chip8, &options.rom_file, mode) {
+                    Ok(new_rom) => {
+                        rom = new_rom;
+                        let keys = chip8.rom_info().map(|info| info.keys.clone()).unwrap_or_default();
+                        chip8.key_input.set_key_layout(&keys);
+
+                        // The palette follows the database entry of the new ROM
+                        let status = match select_palette(options, chip8.rom_info()) {
+                            Ok(new_palette) => {
+                                palette = new_palette;
+                                platform_notice(&chip8).map_or("ROM reloaded".to_string(), |notice| format!("ROM reloaded. {}", notice))
+                            }
+                            Err(e) => format!("ROM reloaded. {}", e)
+                        };
+                        chip8.screen.set_status(status);
+                    }
+                    Err(e) => chip8.screen.set_status(e)
+                }
+            }
+        }
+
+        // Wait for the next frame
+        if let Some(remaining) = pacing.frame_duration().checked_sub(frame_start.elapsed()) {
+            sleep(remaining);
+        }
+    }
+}
+
+// Unit tests
+#[test]
+fn test_to_cells() {
+    //--------------------------------------------------------------------
+    // Setup: Light the 2 top left pixels of the first column
+    //--------------------------------------------------------------------
+    let mut framebuffer = [0u8; 2048];
+    framebuffer[0] = 1;
+    framebuffer[SCREEN_WIDTH] = 1;
+    let palette = Palette::default();
+
+    //--------------------------------------------------------------------
+    // Execute: Convert the framebuffer in both modes
+    //--------------------------------------------------------------------
+    let half_block = to_cells(&framebuffer, &palette, TerminalMode::HalfBlock);
+    let braille = to_cells(&framebuffer, &palette, TerminalMode::Braille);
+
+    //--------------------------------------------------------------------
+    // Verify: Sizes and first characters
+    //--------------------------------------------------------------------
+    assert_eq!((half_block.len(), half_block[0].len()), (16, 64), "Half blocks should use 64x16 characters");
+    assert_eq!((braille.len(), braille[0].len()), (8, 32), "Braille should use 32x8 characters");
+    assert_eq!((half_block[0][0].foreground, half_block[0][0].background), ([0xFF; 3], [0xFF; 3]),
+               "Both halves of the first character should be lit");
+    assert_eq!(half_block[0][1].foreground, [0x00; 3], "The second character should be off");
+    assert_eq!(braille[0][0].character, '⠃', "The first braille character should have the 2 top left dots");
+    assert_eq!(braille[0][1].character, '⠀', "The second braille character should be empty");
+}
+
+#[test]
+fn test_key_hold() {
+    //--------------------------------------------------------------------
+    // Setup: A very short hold timeout
+    //--------------------------------------------------------------------
+    let mut input = TerminalInput::new(Duration::from_millis(20));
+
+    //--------------------------------------------------------------------
+    // Execute: Press the key A
+    //--------------------------------------------------------------------
+    let hotkey = input.handle_key_event(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+
+    //--------------------------------------------------------------------
+    // Verify: The key is held until the timeout, and read once by FX0A
+    //--------------------------------------------------------------------
+    assert_eq!(hotkey, None, "A CHIP-8 key is not a hotkey");
+    assert!(input.is_key_pressed(0xA), "The key should be pressed");
+    assert_eq!(input.get_key(), Some(0xA), "FX0A should get the key");
+    assert_eq!(input.get_key(), None, "FX0A should only get the key once");
+
+    sleep(Duration::from_millis(30));
+    assert!(!input.is_key_pressed(0xA), "The key should be released after the timeout");
+}
+
+#[test]
+fn test_ctrl_c() {
+    //--------------------------------------------------------------------
+    // Setup: The default key hold
+    //--------------------------------------------------------------------
+    let mut input = TerminalInput::new(Duration::from_millis(TERMINAL_DEFAULT_KEY_HOLD));
+
+    //--------------------------------------------------------------------
+    // Execute: Press Ctrl+C
+    //--------------------------------------------------------------------
+    let hotkey = input.handle_key_event(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+
+    //--------------------------------------------------------------------
+    // Verify: The emulator quits, and the key C is not pressed
+    //--------------------------------------------------------------------
+    assert_eq!(hotkey, Some(Hotkey::Quit), "Ctrl+C should quit");
+    assert!(!input.is_key_pressed(0xC), "Ctrl+C should not press the key C");
+}
@@ -0,0 +1,297 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Machine state viewer: registers, timers, stack, disassembly and memory dump in a second window
+//************************************************************************
+
+use std::collections::BTreeSet;
+
+use sdl2::VideoSubsystem;
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+
+use crate::chip8::{describe_next, disassemble, MachineState};
+use crate::font::FONT_WIDTH;
+use crate::renderer::{draw_text, TEXT_LINE_HEIGHT, TEXT_MARGIN, TEXT_SCALE};
+
+const VIEWER_WIDTH : u32 = 820;
+const VIEWER_HEIGHT: u32 = 580;
+const VIEWER_TITLE : &str = "Rust CHIP-8 emulator - Machine state";
+
+// Disassembly, centered on the PC, and effect of the next instruction below it
+const DISASSEMBLY_COLUMN       : usize = 16;
+const DISASSEMBLY_ROWS         : usize = 32;
+const DISASSEMBLY_ROWS_ABOVE_PC: usize = 16;
+const NEXT_ROW                 : usize = 34;
+
+// Memory dump: rows of 16 bytes, starting at this column
+const DUMP_COLUMN       : usize = 44;
+const DUMP_ROWS         : usize = 32;
+const DUMP_ROW_SIZE     : usize = 16;
+// Rows shown above the row of the PC, when following it
+const DUMP_ROWS_ABOVE_PC: usize = 8;
+const DUMP_MAX_FIRST_ROW: usize = 4096 / DUMP_ROW_SIZE - DUMP_ROWS;
+
+// Number of frames a changed byte stays highlighted
+const CHANGE_FRAMES: u8 = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Highlight {
+    Normal,
+    Label,
+    ProgramCounter,
+    AddrRegister,
+    Changed,
+    Breakpoint
+}
+
+impl Highlight {
+    // Text and background colors
+    fn colors(&self) -> (Color, Color) {
+        match self {
+            Highlight::Normal         => (Color::WHITE, Color::BLACK),
+            Highlight::Label          => (Color::RGB(0x80, 0x80, 0x80), Color::BLACK),
+            Highlight::ProgramCounter => (Color::BLACK, Color::RGB(0x33, 0xFF, 0x66)),
+            Highlight::AddrRegister   => (Color::BLACK, Color::RGB(0x33, 0x99, 0xFF)),
+            Highlight::Changed        => (Color::RGB(0xFF, 0x55, 0x55), Color::BLACK),
+            Highlight::Breakpoint     => (Color::WHITE, Color::RGB(0xC0, 0x20, 0x20))
+        }
+    }
+}
+
+// A text at a position of the character grid
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Span {
+    column   : usize,
+    row      : usize,
+    text     : String,
+    highlight: Highlight
+}
+
+impl Span {
+    fn new(column: usize, row: usize, text: String, highlight: Highlight) -> Self {
+        Span { column, row, text, highlight }
+    }
+}
+
+pub struct Viewer {
+    canvas: WindowCanvas,
+
+    // Memory of the last update, and for how many frames each byte stays highlighted
+    memory : Option<Vec<u8>>,
+    changes: Vec<u8>,
+
+    // First row of the memory dump, None follows the PC
+    first_row: Option<usize>,
+
+    // PC of the last update, to find the clicked addresses
+    program_counter: u16
+}
+
+impl Viewer {
+    pub fn open(video: &VideoSubsystem) -> Result<Self, String> {
+        let window = video.window(VIEWER_TITLE, VIEWER_WIDTH, VIEWER_HEIGHT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().software().build().map_err(|e| e.to_string())?;
+
+        Ok(Viewer {
+            canvas,
+            memory         : None,
+            changes        : Vec::new(),
+            first_row      : None,
+            program_counter: 0
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    // Scroll the memory dump, which stops following the PC
+    pub fn scroll(&mut self, rows: i32) {
+        let first_row = self.first_row.unwrap_or_else(|| following_row(self.program_counter)) as i32 + rows;
+        self.first_row = Some(first_row.clamp(0, DUMP_MAX_FIRST_ROW as i32) as usize);
+    }
+
+    // Address of the instruction or the byte at a position of the window
+    pub fn address_at(&self, x: i32, y: i32) -> Option<u16> {
+        let column_width = ((FONT_WIDTH + 1) as u32 * TEXT_SCALE) as i32;
+        if x < TEXT_MARGIN || y < TEXT_MARGIN {
+            return None;
+        }
+
+        let (column, row) = (((x - TEXT_MARGIN) / column_width) as usize, ((y - TEXT_MARGIN) / TEXT_LINE_HEIGHT) as usize);
+        if row == 0 {
+            return None;
+        }
+
+        if (DISASSEMBLY_COLUMN..DUMP_COLUMN).contains(&column) && row <= DISASSEMBLY_ROWS {
+            return disassembly_address(self.program_counter, row - 1);
+        }
+
+        let offset = column.checked_sub(DUMP_COLUMN + 6)?;
+        if offset / 3 < DUMP_ROW_SIZE && row <= DUMP_ROWS {
+            let first_row = self.first_row.unwrap_or_else(|| following_row(self.program_counter));
+            return Some(((first_row + row - 1) * DUMP_ROW_SIZE + offset / 3) as u16);
+        }
+
+        None
+    }
+
+    // Track the changed bytes and draw the state, should be called once per frame
+    pub fn update(&mut self, state: &MachineState, breakpoints: &BTreeSet<u16>) -> Result<(), String> {
+        self.program_counter = state.program_counter;
+
+        self.changes.resize(state.memory.len(), 0);
+        for change in self.changes.iter_mut() {
+            *change = change.saturating_sub(1);
+        }
+        if let Some(memory) = &self.memory {
+            for (index, _) in memory.iter().zip(state.memory.iter()).enumerate().filter(|(_, (b, a))| b != a) {
+                self.changes[index] = CHANGE_FRAMES;
+            }
+        }
+        self.memory = Some(state.memory.to_vec());
+
+        let first_row = self.first_row.unwrap_or_else(|| following_row(state.program_counter));
+
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        let column_width = ((FONT_WIDTH + 1) as u32 * TEXT_SCALE) as i32;
+        for span in layout(state, &self.changes, first_row, breakpoints) {
+            let (color, background) = span.highlight.colors();
+            let (x, y) = (TEXT_MARGIN + span.column as i32 * column_width, TEXT_MARGIN + span.row as i32 * TEXT_LINE_HEIGHT);
+            draw_text(&mut self.canvas, &span.text, x, y, color, background)?;
+        }
+        self.canvas.present();
+
+        Ok(())
+    }
+}
+
+// First row of the memory dump showing the PC
+fn following_row(program_counter: u16) -> usize {
+    (program_counter as usize / DUMP_ROW_SIZE).saturating_sub(DUMP_ROWS_ABOVE_PC).min(DUMP_MAX_FIRST_ROW)
+}
+
+// Address of a row of the disassembly, None above the start of the memory
+fn disassembly_address(program_counter: u16, row: usize) -> Option<u16> {
+    (program_counter as usize + row * 2).checked_sub(DISASSEMBLY_ROWS_ABOVE_PC * 2).map(|address| address as u16)
+}
+
+// Texts of the viewer: registers, timers and stack on the left, then the disassembly and the memory dump
+fn layout(state: &MachineState, changes: &[u8], first_row: usize, breakpoints: &BTreeSet<u16>) -> Vec<Span> {
+    let mut spans = vec![Span::new(0, 0, "REGISTERS".to_string(), Highlight::Label)];
+
+    for index in 0..8 {
+        let text = format!("V{:X} {:02X}  V{:X} {:02X}", index, state.registers[index], index + 8, state.registers[index + 8]);
+        spans.push(Span::new(0, 1 + index, text, Highlight::Normal));
+    }
+    spans.push(Span::new(0, 10, format!("I  {:04X}", state.addr_register), Highlight::AddrRegister));
+    spans.push(Span::new(0, 11, format!("PC {:04X}", state.program_counter), Highlight::ProgramCounter));
+    spans.push(Span::new(0, 13, format!("DT {:02X}  ST {:02X}", state.delay_timer, state.sound_timer), Highlight::Normal));
+
+    // The entries above the stack pointer are not used
+    spans.push(Span::new(0, 15, format!("STACK SP {}", state.stack_ptr), Highlight::Label));
+    for (index, address) in state.stack.iter().enumerate() {
+        let highlight = if index < state.stack_ptr { Highlight::Normal } else { Highlight::Label };
+        spans.push(Span::new(0, 16 + index, format!("{:X} {:04X}", index, address), highlight));
+    }
+
+    // The PC is marked with >, and the breakpoints with *
+    spans.push(Span::new(DISASSEMBLY_COLUMN, 0, "DISASSEMBLY".to_string(), Highlight::Label));
+    for row in 0..DISASSEMBLY_ROWS {
+        let address = match disassembly_address(state.program_counter, row) {
+            Some(address) => address,
+            None => continue
+        };
+        let opcode = match state.opcode_at(address) {
+            Some(opcode) => opcode,
+            None => break
+        };
+
+        let is_pc = address == state.program_counter;
+        let is_breakpoint = breakpoints.contains(&address);
+        let text = format!("{}{} {:03X} {:04X} {}", if is_pc { '>' } else { ' ' }, if is_breakpoint { '*' } else { ' ' },
+                           address, opcode, disassemble(opcode));
+        let highlight = match (is_pc, is_breakpoint) {
+            (true, _)      => Highlight::ProgramCounter,
+            (false, true)  => Highlight::Breakpoint,
+            (false, false) => Highlight::Normal
+        };
+        spans.push(Span::new(DISASSEMBLY_COLUMN, 1 + row, text, highlight));
+    }
+    spans.push(Span::new(DISASSEMBLY_COLUMN, NEXT_ROW, format!("NEXT: {}", describe_next(state)), Highlight::Normal));
+
+    spans.push(Span::new(DUMP_COLUMN, 0, "MEMORY".to_string(), Highlight::Label));
+    let (pc, i) = (state.program_counter as usize, state.addr_register as usize);
+    for row in 0..DUMP_ROWS {
+        let start = (first_row + row) * DUMP_ROW_SIZE;
+        if start >= state.memory.len() {
+            break;
+        }
+
+        spans.push(Span::new(DUMP_COLUMN, 1 + row, format!("{:04X}", start), Highlight::Label));
+        for (offset, byte) in state.memory[start..start + DUMP_ROW_SIZE].iter().enumerate() {
+            let address = start + offset;
+            let highlight = if address == pc || address == pc + 1 {
+                Highlight::ProgramCounter
+            } else if address == i {
+                Highlight::AddrRegister
+            } else if changes[address] > 0 {
+                Highlight::Changed
+            } else {
+                Highlight::Normal
+            };
+
+            spans.push(Span::new(DUMP_COLUMN + 6 + offset * 3, 1 + row, format!("{:02X}", byte), highlight));
+        }
+    }
+
+    spans
+}
+
+// Unit tests
+#[test]
+fn test_layout() {
+    //--------------------------------------------------------------------
+    // Setup: PC at 0x202, I at 0x210, a changed byte at 0x205 and a breakpoint at 0x204
+    //--------------------------------------------------------------------
+    let mut state = MachineState {
+        addr_register  : 0x210,
+        program_counter: 0x202,
+        stack_ptr      : 1,
+        delay_timer    : 0x3C,
+        ..MachineState::default()
+    };
+    state.registers[0xA] = 0x42;
+    let mut changes = vec![0; 4096];
+    changes[0x205] = 1;
+
+    //--------------------------------------------------------------------
+    // Execute: Layout the state, following the PC
+    //--------------------------------------------------------------------
+    let spans = layout(&state, &changes, following_row(state.program_counter), &BTreeSet::from([0x204]));
+    let byte = |address: usize| spans.iter()
+        .find(|span| span.row == 1 + address / 16 - following_row(0x202) && span.column == DUMP_COLUMN + 6 + (address % 16) * 3)
+        .map(|span| span.highlight);
+
+    //--------------------------------------------------------------------
+    // Verify: Registers and highlighted bytes
+    //--------------------------------------------------------------------
+    assert!(spans.contains(&Span::new(0, 3, "V2 00  VA 42".to_string(), Highlight::Normal)), "VA should be shown");
+    assert!(spans.contains(&Span::new(0, 13, "DT 3C  ST 00".to_string(), Highlight::Normal)), "The timers should be shown");
+    assert!(spans.contains(&Span::new(0, 17, "1 0000".to_string(), Highlight::Label)), "Unused stack entries should be dimmed");
+    assert_eq!(spans.iter().find(|span| span.text == "0180").map(|span| span.row), Some(1), "The dump should start 8 rows above the PC");
+    assert_eq!((byte(0x202), byte(0x203)), (Some(Highlight::ProgramCounter), Some(Highlight::ProgramCounter)),
+               "Both bytes of the opcode should be highlighted");
+    assert_eq!(byte(0x210), Some(Highlight::AddrRegister), "The byte at I should be highlighted");
+    assert_eq!(byte(0x205), Some(Highlight::Changed), "The changed byte should be highlighted");
+    assert_eq!(byte(0x206), Some(Highlight::Normal), "Other bytes should not be highlighted");
+    assert!(spans.contains(&Span::new(DISASSEMBLY_COLUMN, 17, ">  202 0000 SYS 000".to_string(), Highlight::ProgramCounter)),
+            "The PC should be in the middle of the disassembly");
+    assert!(spans.contains(&Span::new(DISASSEMBLY_COLUMN, 18, " * 204 0000 SYS 000".to_string(), Highlight::Breakpoint)),
+            "The breakpoint should be marked");
+}
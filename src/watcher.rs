@@ -0,0 +1,128 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Hot reload of the ROM file, checked from its modification time and size
+//************************************************************************
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::chip8::{self, Chip8, Display, KeyInput};
+
+// Time between two checks of the ROM file
+const WATCH_PERIOD: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchMode {
+    // Reset the whole machine
+    Reset,
+    // Reset the machine, but keep the registers V0-VF and I
+    KeepRegisters
+}
+
+impl WatchMode {
+    // Parse a mode name: "reset" or "keep"
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "reset" => Ok(WatchMode::Reset),
+            "keep"  => Ok(WatchMode::KeepRegisters),
+            _ => Err(format!("Unknown watch mode {}, use reset or keep", name))
+        }
+    }
+}
+
+pub struct RomWatcher {
+    path      : PathBuf,
+    last_check: Instant,
+
+    // Version of the file loaded, and of a change waiting for the file to stay the same during a period
+    loaded : Option<(SystemTime, u64)>,
+    pending: Option<(SystemTime, u64)>
+}
+
+impl RomWatcher {
+    pub fn new(rom_file: &str) -> Result<Self, String> {
+        if rom_file == "-" {
+            return Err("Impossible to watch the standard input, use a ROM file".to_string());
+        }
+
+        let path = PathBuf::from(rom_file);
+        Ok(RomWatcher {
+            loaded    : file_version(&path),
+            pending   : None,
+            last_check: Instant::now(),
+            path
+        })
+    }
+
+    // Check the file at most once per period. A change is reported once the file stays the same during a period, as
+    // the assemblers can write it in several steps
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last_check) < WATCH_PERIOD {
+            return false;
+        }
+        self.last_check = now;
+
+        // The file can be missing while it is saved
+        let version = file_version(&self.path);
+        if version.is_none() || version == self.loaded {
+            self.pending = None;
+            return false;
+        }
+        if version != self.pending {
+            self.pending = version;
+            return false;
+        }
+
+        self.loaded  = version;
+        self.pending = None;
+        true
+    }
+}
+
+// Modification time and size of a file
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Read the ROM file again and reload it into the emulator, returning the new ROM for the resets
+pub fn reload<S, I>(chip8: &mut Chip8<S, I>, rom_file: &str, mode: WatchMode) -> Result<Vec<u8>, String> where S: Display, I: KeyInput {
+    let rom = chip8::read_rom_file(rom_file)?;
+    chip8.reload_rom(&rom, mode == WatchMode::KeepRegisters)?;
+    Ok(rom)
+}
+
+// Unit tests
+#[test]
+fn test_rom_watcher() {
+    //--------------------------------------------------------------------
+    // Setup: Watch a ROM file
+    //--------------------------------------------------------------------
+    let directory = crate::test_directory("watcher");
+    let path = directory.join("rom.ch8");
+    std::fs::write(&path, [0x12, 0x00]).unwrap();
+    let mut watcher = RomWatcher::new(&path.to_string_lossy()).unwrap();
+    let start = watcher.last_check;
+
+    //--------------------------------------------------------------------
+    // Execute: Check it every 100 ms, with a change written after 300 ms
+    //--------------------------------------------------------------------
+    let mut changes = Vec::new();
+    for step in 1..=12 {
+        if step == 3 {
+            std::fs::write(&path, [0x12, 0x00, 0x00, 0xE0]).unwrap();
+        }
+        changes.push(watcher.poll(start + Duration::from_millis(step * 100)));
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    //--------------------------------------------------------------------
+    // Verify: The change is reported once, a period after it has been seen
+    //--------------------------------------------------------------------
+    let reported: Vec<usize> = changes.iter().enumerate().filter(|(_, changed)| **changed).map(|(step, _)| step + 1).collect();
+    assert_eq!(reported, vec![6], "The change should be reported after the check seeing it and the next one");
+    assert!(RomWatcher::new("-").is_err(), "The standard input should not be watched");
+    assert_eq!(WatchMode::parse("keep"), Ok(WatchMode::KeepRegisters), "keep should keep the registers");
+}
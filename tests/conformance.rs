@@ -0,0 +1,173 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Conformance tests: the test ROMs run headlessly for a number of frames
+// with each quirks preset, and the screen is compared to a golden image
+//
+// The golden images are in tests/golden, one "<rom>[-<test>]-<platform>.txt"
+// per run, with a # per lit pixel. Run with CHIP8_UPDATE_GOLDEN=1 to write
+// them from the current behaviour. The ROMs of the Timendus test suite run
+// once copied to tests/roms, and are skipped until then.
+//************************************************************************
+
+use std::path::{Path, PathBuf};
+
+use rust_chip_8::chip8::{Chip8, Platform};
+use rust_chip_8::chip8::mock::{MockInput, MockScreen};
+
+const SCREEN_WIDTH: usize = 64;
+
+// Quirks presets each ROM runs with
+const PLATFORMS: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+// How a ROM with several tests is told which one to run
+#[derive(Clone, Copy)]
+enum Selection {
+    // The ROM has a single test, or shows its menu
+    Menu,
+
+    // The test stored at 0x1FF
+    Test(u8),
+
+    // The test of the platform stored at 0x1FF (1: CHIP-8, 2: SUPER-CHIP, 3: XO-CHIP)
+    Platform
+}
+
+struct Case {
+    rom      : &'static str,
+    frames   : u64,
+    selection: Selection,
+
+    // Keys held during the whole run
+    keys: &'static [u8]
+}
+
+// ROMs distributed with the emulator
+const CASES: [Case; 1] = [
+    Case { rom: "quirk-digits.ch8", frames: 10,  selection: Selection::Menu,     keys: &[] }
+];
+
+// The ROMs of the Timendus CHIP-8 test suite are under the GPL, and are not distributed with the emulator:
+// they must be copied to tests/roms (https://github.com/Timendus/chip8-test-suite)
+const TIMENDUS_CASES: [Case; 7] = [
+    Case { rom: "1-chip8-logo.ch8", frames: 60,  selection: Selection::Menu,     keys: &[] },
+    Case { rom: "2-ibm-logo.ch8",   frames: 60,  selection: Selection::Menu,     keys: &[] },
+    Case { rom: "3-corax+.ch8",     frames: 120, selection: Selection::Menu,     keys: &[] },
+    Case { rom: "4-flags.ch8",      frames: 120, selection: Selection::Menu,     keys: &[] },
+    Case { rom: "5-quirks.ch8",     frames: 600, selection: Selection::Platform, keys: &[] },
+    Case { rom: "6-keypad.ch8",     frames: 60,  selection: Selection::Test(1),  keys: &[0x5] },
+    Case { rom: "6-keypad.ch8",     frames: 60,  selection: Selection::Test(2),  keys: &[0x5] }
+];
+
+fn platform_slug(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Chip8     => "chip8",
+        Platform::SuperChip => "superchip",
+        Platform::XoChip    => "xochip"
+    }
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name)
+}
+
+// Run a ROM with the quirks of a platform, and get its screen with a # per lit pixel
+fn run_case(case: &Case, rom_path: &Path, platform: Platform) -> Result<String, String> {
+    let mut chip8 = Chip8::new(MockScreen::new(), MockInput::pressed(case.keys));
+    chip8.load_rom_file(&rom_path.to_string_lossy())?;
+    chip8.set_platform(platform);
+    chip8.init()?;
+
+    match case.selection {
+        Selection::Menu       => {}
+        Selection::Test(test) => chip8.poke(0x1FF, test),
+        Selection::Platform   => chip8.poke(0x1FF, PLATFORMS.iter().position(|p| *p == platform).unwrap() as u8 + 1)
+    }
+
+    for _ in 0..case.frames {
+        chip8.run_frame()?;
+    }
+
+    let rows: Vec<String> = chip8.screen.pixels.chunks(SCREEN_WIDTH)
+        .map(|row| row.iter().map(|pixel| if *pixel != 0 { '#' } else { '.' }).collect())
+        .collect();
+    Ok(rows.join("\n") + "\n")
+}
+
+fn golden_name(case: &Case, platform: Platform) -> String {
+    let stem = case.rom.trim_end_matches(".ch8");
+    match case.selection {
+        Selection::Test(test) => format!("{}-{}-{}.txt", stem, test, platform_slug(platform)),
+        _                     => format!("{}-{}.txt", stem, platform_slug(platform))
+    }
+}
+
+// Run the ROMs of tests/roms with each preset, and get the failures. The optional ROMs are skipped when missing
+fn run_cases(cases: &[Case], optional: bool) -> Vec<String> {
+    let update = std::env::var("CHIP8_UPDATE_GOLDEN").is_ok();
+    let mut failures = Vec::new();
+
+    for case in cases {
+        let rom_path = test_dir("roms").join(case.rom);
+        if !rom_path.exists() {
+            match optional {
+                true  => eprintln!("{}: not found in tests/roms, skipped", case.rom),
+                false => failures.push(format!("{}: not found in tests/roms", case.rom))
+            }
+            continue;
+        }
+
+        for platform in PLATFORMS {
+            let golden_path = test_dir("golden").join(golden_name(case, platform));
+            let screen = match run_case(case, &rom_path, platform) {
+                Ok(screen) => screen,
+                Err(e) => {
+                    failures.push(format!("{} ({}): {}", case.rom, platform.name(), e));
+                    continue;
+                }
+            };
+
+            if update {
+                std::fs::write(&golden_path, &screen).unwrap();
+                continue;
+            }
+
+            match std::fs::read_to_string(&golden_path) {
+                Ok(golden) if golden == screen => {}
+                Ok(golden) => failures.push(format!("{} ({}) differs from {}\nExpected:\n{}Got:\n{}",
+                                                    case.rom, platform.name(), golden_path.display(), golden, screen)),
+                Err(_) => failures.push(format!("{} ({}): missing {}, run with CHIP8_UPDATE_GOLDEN=1 to write it",
+                                                case.rom, platform.name(), golden_path.display()))
+            }
+        }
+    }
+
+    failures
+}
+
+#[test]
+fn test_conformance() {
+    //--------------------------------------------------------------------
+    // Execute: Run the ROMs distributed with the emulator
+    //--------------------------------------------------------------------
+    let failures = run_cases(&CASES, false);
+
+    //--------------------------------------------------------------------
+    // Verify: Every screen matches its golden image
+    //--------------------------------------------------------------------
+    assert!(failures.is_empty(), "{} runs failed:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_timendus_suite() {
+    //--------------------------------------------------------------------
+    // Execute: Run the ROMs of the Timendus test suite copied to tests/roms
+    //--------------------------------------------------------------------
+    let failures = run_cases(&TIMENDUS_CASES, true);
+
+    //--------------------------------------------------------------------
+    // Verify: Every screen matches its golden image
+    //--------------------------------------------------------------------
+    assert!(failures.is_empty(), "{} runs failed:\n{}", failures.len(), failures.join("\n"));
+}
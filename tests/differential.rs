@@ -0,0 +1,369 @@
+//************************************************************************
+// Rust CHIP-8 emulator, created by David Garcia
+// Distributed under the MIT license
+//
+// Differential tests: random programs run step by step in the emulator
+// and in a small reference interpreter written from the specifications,
+// the machine states must stay the same
+//
+// The programs start by loading random values in the registers, I and
+// the timers. CXNN is random, and is not generated. The reference stops
+// the comparison before undefined behaviours: an empty or full stack and
+// memory accesses out of the 4 KB. The opcodes which are not instructions
+// must be errors in the emulator.
+//************************************************************************
+
+use proptest::prelude::*;
+use proptest::test_runner::FileFailurePersistence;
+
+use rust_chip_8::chip8::{Chip8, Platform};
+use rust_chip_8::chip8::mock::{MockInput, MockScreen};
+
+const MEMORY_SIZE  : usize = 4096;
+const SCREEN_WIDTH : usize = 64;
+const SCREEN_HEIGHT: usize = 32;
+const FONT_START   : usize = 0x50;
+const PROGRAM_START: usize = 0x200;
+
+// Instructions executed by each program, loops included
+const MAX_STEPS: usize = 200;
+
+//-------------------------- REFERENCE --------------------------
+// Behaviours that differ between the interpreters, from the quirks tests of the Timendus test suite
+struct Quirks {
+    shift           : bool, // 8XY6/8XYE shift VX instead of VY
+    memory_increment: bool, // FX55/FX65 increment I
+    jump            : bool, // BNNN jumps to XNN + VX
+    vf_reset        : bool, // 8XY1/8XY2/8XY3 reset VF
+    clip            : bool  // DXYN clips the sprites at the edges
+}
+
+fn platform_quirks(platform: Platform) -> Quirks {
+    match platform {
+        Platform::Chip8     => Quirks { shift: false, memory_increment: true,  jump: false, vf_reset: true,  clip: true },
+        Platform::SuperChip => Quirks { shift: true,  memory_increment: false, jump: true,  vf_reset: false, clip: true },
+        Platform::XoChip    => Quirks { shift: false, memory_increment: true,  jump: false, vf_reset: false, clip: false }
+    }
+}
+
+// Outcome of an instruction of the reference
+enum Step {
+    Executed,
+    // The behaviour is undefined, the comparison stops
+    Undefined,
+    // The opcode is not an instruction, the emulator must stop with an error
+    Invalid
+}
+
+struct Reference {
+    registers: [u8; 16],
+    i        : u16,
+    pc       : u16,
+    stack    : Vec<u16>,
+    delay    : u8,
+    sound    : u8,
+    memory   : Vec<u8>,
+    screen   : Vec<bool>,
+    keys     : Vec<u8>,
+    quirks   : Quirks
+}
+
+impl Reference {
+    fn new(program: &[u8], keys: &[u8], quirks: Quirks) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[FONT_START..FONT_START + 80].copy_from_slice(&FONT);
+        memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+
+        Reference {
+            registers: [0; 16],
+            i        : 0,
+            pc       : PROGRAM_START as u16,
+            stack    : Vec::new(),
+            delay    : 0,
+            sound    : 0,
+            memory,
+            screen   : vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            keys     : keys.to_vec(),
+            quirks
+        }
+    }
+
+    fn in_memory(&self, start: usize, length: usize) -> bool {
+        start + length <= MEMORY_SIZE
+    }
+
+    // Execute an instruction
+    fn step(&mut self) -> Step {
+        match self.execute() {
+            Some(step) => step,
+            None => Step::Undefined
+        }
+    }
+
+    // Execute an instruction, None when its behaviour is undefined
+    fn execute(&mut self) -> Option<Step> {
+        let pc = self.pc as usize;
+        if !self.in_memory(pc, 2) {
+            return None;
+        }
+
+        let opcode = (self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16;
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let mut next = self.pc + 2;
+
+        match (opcode >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => self.screen.iter_mut().for_each(|pixel| *pixel = false),
+            (0x0, 0x0, 0xE, 0xE) => next = self.stack.pop()? + 2,
+            (0x1, _, _, _) => next = nnn,
+            (0x2, _, _, _) => {
+                if self.stack.len() == 16 {
+                    return None;
+                }
+                self.stack.push(self.pc);
+                next = nnn;
+            }
+            (0x3, _, _, _) => if self.registers[x] == nn { next += 2 },
+            (0x4, _, _, _) => if self.registers[x] != nn { next += 2 },
+            (0x5, _, _, 0x0) => if self.registers[x] == self.registers[y] { next += 2 },
+            (0x6, _, _, _) => self.registers[x] = nn,
+            (0x7, _, _, _) => self.registers[x] = self.registers[x].wrapping_add(nn),
+            (0x8, _, _, 0x0) => self.registers[x] = self.registers[y],
+            (0x8, _, _, 0x1 ..= 0x3) => {
+                self.registers[x] = match n {
+                    0x1 => self.registers[x] | self.registers[y],
+                    0x2 => self.registers[x] & self.registers[y],
+                    _   => self.registers[x] ^ self.registers[y]
+                };
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            (0x8, _, _, 0x4) => {
+                let sum = self.registers[x] as u16 + self.registers[y] as u16;
+                self.registers[x] = sum as u8;
+                self.registers[0xF] = (sum > 0xFF) as u8;
+            }
+            (0x8, _, _, 0x5) | (0x8, _, _, 0x7) => {
+                let (a, b) = if n == 0x5 { (self.registers[x], self.registers[y]) } else { (self.registers[y], self.registers[x]) };
+                self.registers[x] = a.wrapping_sub(b);
+                self.registers[0xF] = (a >= b) as u8;
+            }
+            (0x8, _, _, 0x6) | (0x8, _, _, 0xE) => {
+                let value = if self.quirks.shift { self.registers[x] } else { self.registers[y] };
+                let (result, flag) = if n == 0x6 { (value >> 1, value & 1) } else { (value << 1, value >> 7) };
+                self.registers[x] = result;
+                self.registers[0xF] = flag;
+            }
+            (0x9, _, _, 0x0) => if self.registers[x] != self.registers[y] { next += 2 },
+            (0x5, _, _, _) | (0x8, _, _, _) | (0x9, _, _, _) => return Some(Step::Invalid),
+            (0xA, _, _, _) => self.i = nnn,
+            (0xB, _, _, _) => next = nnn + self.registers[if self.quirks.jump { x } else { 0 }] as u16,
+            (0xD, _, _, _) => {
+                if !self.in_memory(self.i as usize, n) {
+                    return None;
+                }
+                self.draw(self.registers[x] as usize, self.registers[y] as usize, n);
+            }
+            (0xE, _, 0x9, 0xE) => if self.keys.contains(&(self.registers[x] & 0xF)) { next += 2 },
+            (0xE, _, 0xA, 0x1) => if !self.keys.contains(&(self.registers[x] & 0xF)) { next += 2 },
+            (0xF, _, 0x0, 0x7) => self.registers[x] = self.delay,
+            (0xF, _, 0x0, 0xA) => match self.keys.iter().min() {
+                Some(key) => self.registers[x] = *key,
+                None => next = self.pc
+            },
+            (0xF, _, 0x1, 0x5) => self.delay = self.registers[x],
+            (0xF, _, 0x1, 0x8) => self.sound = self.registers[x],
+            (0xF, _, 0x1, 0xE) => {
+                let (i, overflow) = self.i.overflowing_add(self.registers[x] as u16);
+                self.i = i;
+                self.registers[0xF] = overflow as u8;
+            }
+            (0xF, _, 0x2, 0x9) => self.i = FONT_START as u16 + self.registers[x] as u16 * 5,
+            (0xF, _, 0x3, 0x3) => {
+                if !self.in_memory(self.i as usize, 3) {
+                    return None;
+                }
+                let value = self.registers[x];
+                let i = self.i as usize;
+                self.memory[i..i + 3].copy_from_slice(&[value / 100, value / 10 % 10, value % 10]);
+            }
+            (0xF, _, 0x5, 0x5) | (0xF, _, 0x6, 0x5) => {
+                if !self.in_memory(self.i as usize, x + 1) {
+                    return None;
+                }
+                let i = self.i as usize;
+                if y == 0x5 {
+                    self.memory[i..=i + x].copy_from_slice(&self.registers[..=x]);
+                } else {
+                    self.registers[..=x].copy_from_slice(&self.memory[i..=i + x]);
+                }
+                if self.quirks.memory_increment {
+                    self.i += x as u16 + 1;
+                }
+            }
+            // 0NNN, CXNN, and the opcodes of the other platforms
+            _ => return None
+        }
+
+        self.pc = next;
+        Some(Step::Executed)
+    }
+
+    fn draw(&mut self, x: usize, y: usize, height: usize) {
+        let (x, y) = (x % SCREEN_WIDTH, y % SCREEN_HEIGHT);
+        self.registers[0xF] = 0;
+
+        for row in 0..height {
+            let sprite = self.memory[self.i as usize + row];
+            for column in 0..8 {
+                if sprite & (0x80 >> column) == 0 {
+                    continue;
+                }
+                if self.quirks.clip && (x + column >= SCREEN_WIDTH || y + row >= SCREEN_HEIGHT) {
+                    continue;
+                }
+
+                let pixel = &mut self.screen[(y + row) % SCREEN_HEIGHT * SCREEN_WIDTH + (x + column) % SCREEN_WIDTH];
+                if *pixel {
+                    self.registers[0xF] = 1;
+                }
+                *pixel = !*pixel;
+            }
+        }
+    }
+}
+
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80
+];
+
+//-------------------------- EMULATOR --------------------------
+// Differences between the emulator and the reference, empty when they are in the same state
+fn differences(chip8: &Chip8<MockScreen, MockInput>, reference: &Reference) -> Vec<String> {
+    let state = chip8.state();
+    let mut differences = Vec::new();
+
+    let mut compare = |name: &str, emulator: String, expected: String| {
+        if emulator != expected {
+            differences.push(format!("{}: {} instead of {}", name, emulator, expected));
+        }
+    };
+    compare("V0-VF", format!("{:02X?}", state.registers), format!("{:02X?}", reference.registers));
+    compare("I", format!("{:03X}", state.addr_register), format!("{:03X}", reference.i));
+    compare("PC", format!("{:03X}", state.program_counter), format!("{:03X}", reference.pc));
+    compare("Stack", format!("{:03X?}", &state.stack[..state.stack_ptr]), format!("{:03X?}", reference.stack));
+    compare("Timers", format!("{:?}", (state.delay_timer, state.sound_timer)), format!("{:?}", (reference.delay, reference.sound)));
+
+    if let Some(address) = (0..MEMORY_SIZE).find(|address| state.memory[*address] != reference.memory[*address]) {
+        compare("Memory", format!("{:02X} at {:03X}", state.memory[address], address),
+                format!("{:02X}", reference.memory[address]));
+    }
+    if let Some(pixel) = (0..SCREEN_WIDTH * SCREEN_HEIGHT).find(|pixel| (chip8.screen.pixels[*pixel] != 0) != reference.screen[*pixel]) {
+        compare("Screen", format!("pixel {},{} {}", pixel % SCREEN_WIDTH, pixel / SCREEN_WIDTH, chip8.screen.pixels[pixel] != 0),
+                format!("{}", reference.screen[pixel]));
+    }
+
+    differences
+}
+
+//-------------------------- PROGRAMS --------------------------
+// Addresses the generated jumps and calls go to, in and around the program
+fn target() -> impl Strategy<Value = u16> {
+    (0u16..96).prop_map(|word| PROGRAM_START as u16 + word * 2)
+}
+
+fn instruction() -> impl Strategy<Value = u16> {
+    let x = || 0u16..16;
+    let nn = || 0u16..=0xFF;
+    // Mostly 0, the only valid value of 5XYN and 9XYN
+    let n = || prop_oneof![3 => Just(0u16), 1 => 1u16..16];
+    prop_oneof![
+        Just(0x00E0),
+        Just(0x00EE),
+        target().prop_map(|address| 0x1000 | address),
+        target().prop_map(|address| 0x2000 | address),
+        (x(), nn()).prop_map(|(x, nn)| 0x3000 | x << 8 | nn),
+        (x(), nn()).prop_map(|(x, nn)| 0x4000 | x << 8 | nn),
+        (x(), x(), n()).prop_map(|(x, y, n)| 0x5000 | x << 8 | y << 4 | n),
+        (x(), nn()).prop_map(|(x, nn)| 0x6000 | x << 8 | nn),
+        (x(), nn()).prop_map(|(x, nn)| 0x7000 | x << 8 | nn),
+        (x(), x(), prop::sample::select(vec![0x0u16, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE]))
+            .prop_map(|(x, y, n)| 0x8000 | x << 8 | y << 4 | n),
+        (x(), x(), n()).prop_map(|(x, y, n)| 0x9000 | x << 8 | y << 4 | n),
+        (0u16..0x1000).prop_map(|address| 0xA000 | address),
+        target().prop_map(|address| 0xB000 | address),
+        (x(), x(), 0u16..16).prop_map(|(x, y, n)| 0xD000 | x << 8 | y << 4 | n),
+        (x(), prop::sample::select(vec![0x9Eu16, 0xA1])).prop_map(|(x, nn)| 0xE000 | x << 8 | nn),
+        (x(), prop::sample::select(vec![0x07u16, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65]))
+            .prop_map(|(x, nn)| 0xF000 | x << 8 | nn)
+    ]
+}
+
+// Random registers, I and timers, loaded by the first instructions of the program
+fn prelude(registers: &[u8], i: u16, timers: (u8, u8)) -> Vec<u16> {
+    let mut prelude = vec![0x6000 | timers.0 as u16, 0xF015, 0x6000 | timers.1 as u16, 0xF018];
+    prelude.extend(registers.iter().enumerate().map(|(x, value)| 0x6000 | (x as u16) << 8 | *value as u16));
+    prelude.push(0xA000 | i);
+    prelude
+}
+
+fn platform() -> impl Strategy<Value = Platform> {
+    prop::sample::select(vec![Platform::Chip8, Platform::SuperChip, Platform::XoChip])
+}
+
+proptest! {
+    // The failing cases are saved, and run first the next times
+    #![proptest_config(ProptestConfig {
+        failure_persistence: Some(Box::new(FileFailurePersistence::Direct("tests/differential.proptest-regressions"))),
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn test_differential(registers in prop::collection::vec(any::<u8>(), 16),
+                         i in 0u16..0x1000,
+                         timers in (any::<u8>(), any::<u8>()),
+                         keys in prop::collection::btree_set(0u8..16, 0..3),
+                         platform in platform(),
+                         instructions in prop::collection::vec(instruction(), 1..64)) {
+        //--------------------------------------------------------------------
+        // Setup: Load the program in the emulator and in the reference
+        //--------------------------------------------------------------------
+        let program: Vec<u8> = prelude(&registers, i, timers).iter().chain(instructions.iter())
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect();
+        let keys: Vec<u8> = keys.into_iter().collect();
+
+        let mut chip8 = Chip8::new(MockScreen::new(), MockInput::pressed(&keys));
+        chip8.load_rom(&program).unwrap();
+        chip8.set_platform(platform);
+        chip8.init().unwrap();
+
+        let mut reference = Reference::new(&program, &keys, platform_quirks(platform));
+
+        //--------------------------------------------------------------------
+        // Execute and Verify: Same state after each step, until an undefined behaviour
+        //--------------------------------------------------------------------
+        for step in 0..MAX_STEPS {
+            let pc = reference.pc;
+            match reference.step() {
+                Step::Executed  => chip8.step().unwrap(),
+                Step::Undefined => break,
+                Step::Invalid   => {
+                    prop_assert!(chip8.step().is_err(), "Step {} at {:03X}: the opcode should be invalid", step, pc);
+                    break;
+                }
+            }
+
+            let differences = differences(&chip8, &reference);
+            prop_assert!(differences.is_empty(), "Step {} at {:03X} ({}): {}", step, pc, platform.name(), differences.join(", "));
+        }
+    }
+}